@@ -18,10 +18,10 @@ struct Assets;
 static LIBRARY: OnceCell<Library> = OnceCell::new();
 
 #[cfg(target_os = "windows")]
-const LIBRARY_NAME: &'static str = "FTD3XX.dll";
+const LIBRARY_NAME: &str = "FTD3XX.dll";
 
 #[cfg(target_os = "linux")]
-const LIBRARY_NAME: &'static str = "libftd3xx.so";
+const LIBRARY_NAME: &str = "libftd3xx.so";
 
 /// Load the dynamic library at the given path.
 ///
@@ -31,11 +31,11 @@ const LIBRARY_NAME: &'static str = "libftd3xx.so";
 pub fn load_dylib(path: impl AsRef<Path>) -> Result<()> {
     let path = path.as_ref();
     match LIBRARY.get() {
-        Some(_) => Err(D3xxError::LibraryAlreadyLoaded),
+        Some(_) => Err(D3xxError::LibraryAlreadyLoaded.into()),
         None => {
-            LIBRARY.get_or_try_init(|| {
-                unsafe { Library::new(path) }
-            })?;
+            LIBRARY.get_or_try_init(|| unsafe { Library::new(path) })?;
+            #[cfg(feature = "tracing")]
+            tracing::info!(path = %path.display(), "loaded D3XX library");
             Ok(())
         }
     }
@@ -54,16 +54,20 @@ pub fn load_bundled_dylib() -> Result<()> {
     static TEMP_DIR: OnceCell<TempDir> = OnceCell::new();
 
     if LIBRARY.get().is_some() {
-        return Err(D3xxError::LibraryAlreadyLoaded);
+        return Err(D3xxError::LibraryAlreadyLoaded.into());
     }
 
     let dylib_path = TEMP_DIR
-        .get_or_try_init(tempfile::tempdir)?
+        .get_or_try_init(tempfile::tempdir)
+        .map_err(D3xxError::UnpackingFailed)?
         .path()
         .join(LIBRARY_NAME);
     let asset = Assets::get(LIBRARY_NAME).expect("library asset not found");
-    let _ = File::create(&dylib_path)?
-        .write_all(asset.data.as_ref())?;
+    File::create(&dylib_path)
+        .and_then(|mut file| file.write_all(asset.data.as_ref()))
+        .map_err(D3xxError::UnpackingFailed)?;
+    #[cfg(feature = "tracing")]
+    tracing::debug!(path = %dylib_path.display(), "unpacked bundled D3XX library");
     load_dylib(dylib_path)
 }
 
@@ -72,5 +76,7 @@ pub fn load_bundled_dylib() -> Result<()> {
 /// # Errors
 /// - [`D3xxError::LibraryNotLoaded`] if the library has not been loaded.
 pub(crate) fn d3xx_lib() -> Result<&'static Library> {
-    LIBRARY.get().ok_or(D3xxError::LibraryNotLoaded)
+    LIBRARY
+        .get()
+        .ok_or_else(|| D3xxError::LibraryNotLoaded.into())
 }
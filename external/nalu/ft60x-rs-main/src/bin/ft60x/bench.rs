@@ -0,0 +1,339 @@
+//! `ft60x bench`
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{Args, ValueEnum};
+use ft60x_rs::{Device, Pipe, PipeStats, StreamConfig};
+use serde::Serialize;
+
+use crate::{
+    megabytes_per_second, parse_pipe, parse_seconds, parse_size, table::Table, Cli, CliResult,
+    Format,
+};
+
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// Benchmarks to run. `write` needs an FPGA design which drains the OUT pipe
+    /// and `loopback` one which echoes it to the IN pipe.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "read")]
+    mode: Vec<Mode>,
+    /// The IN pipe used by `read` and `loopback`.
+    #[arg(long = "in", value_name = "PIPE", value_parser = parse_pipe, default_value = "in0")]
+    in_pipe: Pipe,
+    /// The OUT pipe used by `write` and `loopback`.
+    #[arg(long = "out", value_name = "PIPE", value_parser = parse_pipe, default_value = "out0")]
+    out_pipe: Pipe,
+    /// Transfer sizes to sweep. Each must be a multiple of 1024.
+    #[arg(long, value_delimiter = ',', value_parser = parse_size, default_value = "64K,256K,1M,4M")]
+    sizes: Vec<usize>,
+    /// Stream sizes to sweep; `0` leaves streaming mode disabled.
+    #[arg(long, value_delimiter = ',', value_parser = parse_size, default_value = "0")]
+    stream_sizes: Vec<usize>,
+    /// Numbers of reads kept in flight to sweep. Writes and loopback always use one.
+    #[arg(long, value_delimiter = ',', default_value = "1,4,8")]
+    depths: Vec<usize>,
+    /// How long to run each case, in seconds.
+    #[arg(long, value_parser = parse_seconds, default_value = "2")]
+    duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Read from the IN pipe.
+    Read,
+    /// Write to the OUT pipe.
+    Write,
+    /// Write to the OUT pipe while reading back from the IN pipe.
+    Loopback,
+}
+
+/// One point of the sweep.
+#[derive(Debug, Clone, Copy)]
+struct Case {
+    mode: Mode,
+    transfer_size: usize,
+    stream_size: Option<u32>,
+    depth: usize,
+}
+
+/// Measurements for one case. Serialized as part of the JSON report.
+#[derive(Debug, Serialize)]
+struct CaseResult {
+    mode: Mode,
+    transfer_size: usize,
+    stream_size: Option<u32>,
+    depth: usize,
+    bytes: u64,
+    seconds: f64,
+    mb_per_s: f64,
+    transfers: u64,
+    latency_us: Latency,
+    timeouts: u64,
+    aborts: u64,
+    /// Failures other than timeouts and aborts.
+    errors: u64,
+    overruns: u64,
+}
+
+/// Transfer latency percentiles in microseconds.
+#[derive(Debug, Default, Serialize)]
+struct Latency {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    p999: f64,
+    max: f64,
+}
+
+impl Latency {
+    fn from_stats(stats: &PipeStats) -> Self {
+        let micros = |d: Duration| d.as_secs_f64() * 1e6;
+        Self {
+            p50: micros(stats.latency.value_at_quantile(0.5)),
+            p90: micros(stats.latency.value_at_quantile(0.9)),
+            p99: micros(stats.latency.value_at_quantile(0.99)),
+            p999: micros(stats.latency.value_at_quantile(0.999)),
+            max: micros(stats.latency.max()),
+        }
+    }
+}
+
+/// Counts accumulated while a case runs.
+#[derive(Debug, Default)]
+struct Tally {
+    bytes: u64,
+    errors: u64,
+    overruns: u64,
+}
+
+pub fn run(cli: &Cli, args: &BenchArgs) -> CliResult {
+    let mut results = Vec::new();
+    for case in cases(args)? {
+        let result = match case.mode {
+            Mode::Read | Mode::Write => {
+                let device = crate::open(cli)?;
+                device.set_stats_enabled(true);
+                match case.mode {
+                    Mode::Read => bench_read(&device, args, case)?,
+                    _ => bench_write(&device, args, case)?,
+                }
+            }
+            Mode::Loopback => bench_loopback(crate::open(cli)?, args, case)?,
+        };
+        if cli.format == Format::Table {
+            eprintln!(
+                "{:?} {} bytes, stream size {}, depth {}: {:.1} MB/s",
+                case.mode,
+                case.transfer_size,
+                case.stream_size.unwrap_or(0),
+                case.depth,
+                result.mb_per_s,
+            );
+        }
+        results.push(result);
+    }
+
+    match cli.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&results)?),
+        Format::Table => {
+            let mut table = Table::new([
+                "MODE", "SIZE", "STREAM", "DEPTH", "MB/S", "XFERS", "P50 us", "P99 us", "P99.9 us",
+                "MAX us", "TIMEOUTS", "ABORTS", "ERRORS", "OVERRUNS",
+            ]);
+            for r in &results {
+                table.row([
+                    format!("{:?}", r.mode).to_lowercase(),
+                    r.transfer_size.to_string(),
+                    r.stream_size
+                        .map_or_else(|| "-".to_owned(), |s| s.to_string()),
+                    r.depth.to_string(),
+                    format!("{:.1}", r.mb_per_s),
+                    r.transfers.to_string(),
+                    format!("{:.0}", r.latency_us.p50),
+                    format!("{:.0}", r.latency_us.p99),
+                    format!("{:.0}", r.latency_us.p999),
+                    format!("{:.0}", r.latency_us.max),
+                    r.timeouts.to_string(),
+                    r.aborts.to_string(),
+                    r.errors.to_string(),
+                    r.overruns.to_string(),
+                ]);
+            }
+            table.print();
+        }
+    }
+    Ok(())
+}
+
+/// Expands the sweep into cases. Depths other than one only apply to reads.
+fn cases(args: &BenchArgs) -> CliResult<Vec<Case>> {
+    let mut cases = Vec::new();
+    for &mode in &args.mode {
+        for &transfer_size in &args.sizes {
+            for &stream_size in &args.stream_sizes {
+                let stream_size = match stream_size {
+                    0 => None,
+                    n => Some(
+                        u32::try_from(n).map_err(|_| format!("stream size {n} is too large"))?,
+                    ),
+                };
+                let depths = match mode {
+                    Mode::Read => args.depths.as_slice(),
+                    Mode::Write | Mode::Loopback => &[1],
+                };
+                for &depth in depths {
+                    cases.push(Case {
+                        mode,
+                        transfer_size,
+                        stream_size,
+                        depth,
+                    });
+                }
+            }
+        }
+    }
+    Ok(cases)
+}
+
+fn bench_read(device: &Device, args: &BenchArgs, case: Case) -> CliResult<CaseResult> {
+    let stream = device.stream(
+        args.in_pipe,
+        StreamConfig {
+            buffer_size: case.transfer_size,
+            buffers_in_flight: case.depth,
+            stream_size: case.stream_size,
+        },
+    )?;
+    let start = Instant::now();
+    let deadline = start + args.duration;
+    let mut tally = Tally::default();
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match stream.recv_timeout(remaining) {
+            Some(Ok(buffer)) => tally.bytes += buffer.len() as u64,
+            // The stream ends after an error.
+            Some(Err(_)) => {
+                tally.errors += 1;
+                break;
+            }
+            None => break,
+        }
+    }
+    let elapsed = start.elapsed();
+    tally.overruns = stream.overruns();
+    drop(stream);
+    Ok(result(case, elapsed, tally, device.stats(args.in_pipe)))
+}
+
+fn bench_write(device: &Device, args: &BenchArgs, case: Case) -> CliResult<CaseResult> {
+    device.set_stream_size(args.out_pipe, case.stream_size)?;
+    let buf = device.alloc_buf(case.transfer_size)?;
+    let start = Instant::now();
+    let mut tally = Tally::default();
+    while start.elapsed() < args.duration {
+        match device.write(args.out_pipe, &buf) {
+            Ok(n) => tally.bytes += n as u64,
+            Err(e) => {
+                tally.bytes += e.bytes_transferred().unwrap_or(0) as u64;
+                if !is_counted_by_stats(&e) {
+                    tally.errors += 1;
+                }
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+    device.set_stream_size(args.out_pipe, None)?;
+    Ok(result(case, elapsed, tally, device.stats(args.out_pipe)))
+}
+
+/// Writes on a second thread while reading on this one. Throughput and latency are
+/// those of the reads.
+fn bench_loopback(device: Device, args: &BenchArgs, case: Case) -> CliResult<CaseResult> {
+    device.set_stats_enabled(true);
+    device.set_stream_size(args.in_pipe, case.stream_size)?;
+    device.set_stream_size(args.out_pipe, case.stream_size)?;
+    let mut reader_buf = device.alloc_buf(case.transfer_size)?;
+    let writer_buf = device.alloc_buf(case.transfer_size)?;
+    let (readers, writers) = device.split();
+    let mut reader = readers
+        .into_iter()
+        .find(|reader| reader.pipe() == args.in_pipe)
+        .ok_or("the loopback IN pipe must be an IN pipe")?;
+    let mut writer = writers
+        .into_iter()
+        .find(|writer| writer.pipe() == args.out_pipe)
+        .ok_or("the loopback OUT pipe must be an OUT pipe")?;
+    let cancel = writer.cancel_handle();
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let writer_thread = thread::spawn({
+        let stop = stop.clone();
+        move || {
+            let mut errors = 0;
+            while !stop.load(Ordering::Relaxed) {
+                match writer.write(&writer_buf) {
+                    Ok(_) => {}
+                    Err(e) if e.is_cancelled() => break,
+                    Err(e) => errors += u64::from(!is_counted_by_stats(&e)),
+                }
+            }
+            errors
+        }
+    });
+
+    let start = Instant::now();
+    let mut tally = Tally::default();
+    while start.elapsed() < args.duration {
+        match reader.read(&mut reader_buf) {
+            Ok(n) => tally.bytes += n as u64,
+            Err(e) => {
+                tally.bytes += e.bytes_transferred().unwrap_or(0) as u64;
+                if !is_counted_by_stats(&e) {
+                    tally.errors += 1;
+                }
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    stop.store(true, Ordering::Relaxed);
+    cancel.cancel(args.out_pipe)?;
+    tally.errors += writer_thread
+        .join()
+        .map_err(|_| "loopback writer thread panicked")?;
+    Ok(result(case, elapsed, tally, reader.stats()))
+}
+
+/// Whether an error is already counted as a timeout or abort in [`PipeStats`].
+fn is_counted_by_stats(e: &ft60x_rs::Error) -> bool {
+    use ft60x_rs::D3xxError::*;
+    matches!(e.kind(), Timeout | OperationAborted | Cancelled)
+}
+
+fn result(case: Case, elapsed: Duration, tally: Tally, stats: Option<PipeStats>) -> CaseResult {
+    let count = |field: fn(&PipeStats) -> u64| stats.as_ref().map_or(0, field);
+    CaseResult {
+        mode: case.mode,
+        transfer_size: case.transfer_size,
+        stream_size: case.stream_size,
+        depth: case.depth,
+        bytes: tally.bytes,
+        seconds: elapsed.as_secs_f64(),
+        mb_per_s: megabytes_per_second(tally.bytes, elapsed),
+        transfers: count(|s| s.transfers),
+        latency_us: stats
+            .as_ref()
+            .map_or_else(Latency::default, Latency::from_stats),
+        timeouts: count(|s| s.timeouts),
+        aborts: count(|s| s.aborts),
+        errors: tally.errors,
+        overruns: tally.overruns,
+    }
+}
@@ -0,0 +1,636 @@
+//! `ft60x diag`
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use clap::{Args, ValueEnum};
+use ft60x_rs::{
+    raw, Device, DeviceSelector, OpenOptions, Pattern, PatternChecker, PatternGenerator, Pipe,
+    PipeStats, Stream, StreamConfig,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    link_speed, parse_pipe, parse_seconds, parse_size,
+    table::{format_fields, print_fields},
+    Cli, CliResult, Format,
+};
+
+/// Directory holding the files in the archive.
+const ROOT: &str = "ft60x-diag";
+/// Bytes per transfer in the data test.
+const TEST_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Args)]
+pub struct DiagArgs {
+    /// Archive to write. Defaults to `ft60x-diag-<UNIX TIME>.tar`.
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Data test to run.
+    #[arg(long, value_enum, default_value_t = TestKind::Prbs)]
+    test: TestKind,
+    /// The IN pipe used by the test.
+    #[arg(long = "in", value_name = "PIPE", value_parser = parse_pipe, default_value = "in0")]
+    in_pipe: Pipe,
+    /// The OUT pipe used by the loopback test.
+    #[arg(long = "out", value_name = "PIPE", value_parser = parse_pipe, default_value = "out0")]
+    out_pipe: Pipe,
+    /// Bytes checked by the test.
+    #[arg(long, value_parser = parse_size, default_value = "4M")]
+    test_bytes: usize,
+    /// Stop the test when no data arrives for this many seconds.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, default_value = "1")]
+    test_timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum TestKind {
+    /// Check a PRBS-31 pattern sent by the device on the IN pipe.
+    Prbs,
+    /// Write a PRBS-31 pattern to the OUT pipe and check it is read back from the IN
+    /// pipe. Requires loopback firmware on the FPGA.
+    Loopback,
+    /// Skip the test.
+    None,
+}
+
+/// The JSON report of `ft60x diag`.
+#[derive(Debug, Serialize)]
+struct DiagReport {
+    archive: PathBuf,
+    files: Vec<String>,
+}
+
+pub fn run(cli: &Cli, args: &DiagArgs) -> CliResult {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("ft60x-diag-{created}.tar")));
+
+    let library_version = ft60x_rs::d3xx_version();
+    let host = json!({
+        "ft60x_version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "library": cli.library.as_ref().map_or_else(
+            || "bundled".to_owned(),
+            |path| path.display().to_string(),
+        ),
+        "library_version": library_version,
+        "created": created,
+    });
+    // Listed before opening the device, so it shows whether another process has it open.
+    let devices = ft60x_rs::list_devices();
+    let mut summary = vec![
+        ("ft60x version", env!("CARGO_PKG_VERSION").to_owned()),
+        (
+            "Host",
+            format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        ),
+        ("Library version", library_version.to_string()),
+        (
+            "Devices",
+            devices
+                .as_ref()
+                .map_or_else(|e| e.to_string(), |devices| devices.len().to_string()),
+        ),
+    ];
+
+    let selector = cli.device.clone().unwrap_or(DeviceSelector::Index(0));
+    let (device, test, stats) = match OpenOptions::new().open(selector) {
+        Ok(device) => {
+            device.set_stats_enabled(true);
+            let report = device_report(&device, &mut summary);
+            let test = run_test(&device, args, &mut summary);
+            let stats = stats_report(&device);
+            (report, test, stats)
+        }
+        Err(e) => {
+            summary.push(("Device", e.to_string()));
+            (error_json(&e), Value::Null, Value::Null)
+        }
+    };
+
+    let files = [
+        ("host.json", json_file(&host)?),
+        ("devices.json", json_file(&query(devices))?),
+        ("device.json", json_file(&device)?),
+        ("test.json", json_file(&test)?),
+        ("stats.json", json_file(&stats)?),
+        ("summary.txt", format_fields(&summary).into_bytes()),
+    ];
+    let mut tar = TarWriter::new(BufWriter::new(File::create(&path)?), created);
+    for (name, data) in &files {
+        tar.append(&format!("{ROOT}/{name}"), data)?;
+    }
+    tar.finish()?;
+
+    match cli.format {
+        Format::Json => {
+            let report = DiagReport {
+                archive: path,
+                files: files
+                    .iter()
+                    .map(|(name, _)| format!("{ROOT}/{name}"))
+                    .collect(),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Format::Table => {
+            print_fields(&summary);
+            println!();
+            println!("Wrote {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Queries everything known about the open device. Failed queries are recorded in
+/// place of their result.
+fn device_report(device: &Device, summary: &mut Vec<(&str, String)>) -> Value {
+    let info = device.info();
+    let descriptor = device.device_descriptor();
+    let driver_version = device.driver_version();
+    let chip = device.chip_type().ok().map(|chip| format!("{chip:?}"));
+    let configuration = chip_configuration(device);
+    let link = info.as_ref().ok().and_then(link_speed);
+    let pipes = Pipe::ALL
+        .into_iter()
+        .map(|pipe| {
+            json!({
+                "pipe": pipe.to_string(),
+                "info": query(device.pipe_info(pipe)),
+                "timeout_ms": query(device.get_timeout(pipe).map(|t| t.as_millis() as u64)),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if let Ok(info) = &info {
+        summary.push(("Serial number", info.serial_number().to_owned()));
+        summary.push(("Description", info.description().to_owned()));
+    }
+    summary.push(("Chip", chip.clone().unwrap_or_else(|| "unknown".to_owned())));
+    summary.push(("Link", link.unwrap_or("unknown").to_owned()));
+    if let Ok(descriptor) = &descriptor {
+        summary.push((
+            "USB specification",
+            format!("{:#06x}", descriptor.usb_specification_number()),
+        ));
+    }
+    summary.push((
+        "Driver version",
+        driver_version
+            .as_ref()
+            .map_or_else(|e| e.to_string(), |version| version.to_string()),
+    ));
+    if let Ok(configuration) = &configuration {
+        let field = |key: &str| match &configuration[key] {
+            Value::Null => "unknown".to_owned(),
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        };
+        summary.push(("FIFO mode", field("fifo_mode")));
+        summary.push(("Channels", field("channels")));
+        summary.push(("FIFO clock (MHz)", field("fifo_clock_mhz")));
+    }
+
+    json!({
+        "info": query(info),
+        "chip": chip,
+        "link": link,
+        "descriptor": query(descriptor),
+        "driver_version": query(driver_version),
+        "chip_configuration": query(configuration),
+        "suspend_timeout_ms": query(device.suspend_timeout().map(|t| t.as_millis() as u64)),
+        "pipes": pipes,
+    })
+}
+
+/// Reads the chip configuration, naming the enumerated settings.
+fn chip_configuration(device: &Device) -> ft60x_rs::Result<Value> {
+    let config = device.chip_configuration()?;
+    let fifo_mode = match config.fifo_mode() {
+        raw::CONFIGURATION_FIFO_MODE_245 => Some("245"),
+        raw::CONFIGURATION_FIFO_MODE_600 => Some("600"),
+        _ => None,
+    };
+    let channels = match config.channel_config() {
+        raw::CONFIGURATION_CHANNEL_CONFIG_4 => Some("4"),
+        raw::CONFIGURATION_CHANNEL_CONFIG_2 => Some("2"),
+        raw::CONFIGURATION_CHANNEL_CONFIG_1 => Some("1"),
+        raw::CONFIGURATION_CHANNEL_CONFIG_1_OUTPIPE => Some("1, OUT only"),
+        raw::CONFIGURATION_CHANNEL_CONFIG_1_INPIPE => Some("1, IN only"),
+        _ => None,
+    };
+    Ok(json!({
+        "vendor_id": config.vendor_id(),
+        "product_id": config.product_id(),
+        "strings": config.strings(),
+        "power_attributes": config.power_attributes(),
+        "power_consumption": config.power_consumption(),
+        "fifo_clock": config.fifo_clock(),
+        "fifo_clock_mhz": config.fifo_clock_mhz(),
+        "fifo_mode_raw": config.fifo_mode(),
+        "fifo_mode": fifo_mode,
+        "channel_config": config.channel_config(),
+        "channels": channels,
+        "optional_features": config.optional_features(),
+        "battery_charging_gpio": config.battery_charging_gpio(),
+        "flash_eeprom_detection": config.flash_eeprom_detection(),
+        "msio_control": config.msio_control(),
+        "gpio_control": config.gpio_control(),
+    }))
+}
+
+/// Runs the data test. A failed test is recorded in the report and the summary;
+/// it does not fail the command.
+fn run_test(device: &Device, args: &DiagArgs, summary: &mut Vec<(&str, String)>) -> Value {
+    if args.test == TestKind::None {
+        summary.push(("Test", "skipped".to_owned()));
+        return json!({ "test": args.test });
+    }
+    let total = args.test_bytes.next_multiple_of(TEST_CHUNK).max(TEST_CHUNK);
+    let mut received = Received {
+        checker: PatternChecker::new(Pattern::Prbs31),
+        bytes: 0,
+        expected: total,
+    };
+    let mut written = 0;
+    let mut overruns = 0;
+    let start = Instant::now();
+    let result = (|| -> ft60x_rs::Result<()> {
+        let stream = device.stream(
+            args.in_pipe,
+            StreamConfig {
+                buffer_size: TEST_CHUNK,
+                ..Default::default()
+            },
+        )?;
+        if args.test == TestKind::Loopback {
+            let mut generator = PatternGenerator::new(Pattern::Prbs31, 1);
+            let mut buf = device.alloc_buf(TEST_CHUNK)?;
+            buf.set_len(TEST_CHUNK)?;
+            while written < total {
+                generator.fill(&mut buf)?;
+                written += device.write_timeout(args.out_pipe, &buf, args.test_timeout)?;
+                // Keep the stream from stalling while the device echoes the data.
+                while !received.is_complete() && received.receive(&stream, Duration::ZERO)? {}
+            }
+        }
+        while !received.is_complete() && received.receive(&stream, args.test_timeout)? {}
+        overruns = stream.overruns();
+        Ok(())
+    })();
+    let elapsed = start.elapsed();
+
+    let read = received.bytes;
+    let report = received.checker.report();
+    let passed = result.is_ok() && received.is_complete() && report.is_clean();
+    summary.push((
+        "Test",
+        format!(
+            "{:?} on {}: {}",
+            args.test,
+            args.in_pipe,
+            match (&result, passed) {
+                (Err(e), _) => format!("failed: {e}"),
+                (Ok(()), true) => "passed".to_owned(),
+                (Ok(()), false) if read < total => format!("{read} of {total} bytes received"),
+                (Ok(()), false) => format!("bit error rate {:.3e}", report.bit_error_rate()),
+            }
+        ),
+    ));
+    json!({
+        "test": args.test,
+        "pattern": Pattern::Prbs31,
+        "in_pipe": args.in_pipe.to_string(),
+        "out_pipe": (args.test == TestKind::Loopback).then(|| args.out_pipe.to_string()),
+        "passed": passed,
+        "bytes_expected": total,
+        "bytes_written": written,
+        "bytes_read": read,
+        "seconds": elapsed.as_secs_f64(),
+        "bit_error_rate": report.bit_error_rate(),
+        "overruns": overruns,
+        "report": report,
+        "error": result.err().map(|e| error_json(&e)),
+    })
+}
+
+/// The data received by the test, checked against the pattern as it arrives.
+struct Received {
+    checker: PatternChecker,
+    bytes: usize,
+    expected: usize,
+}
+
+impl Received {
+    fn is_complete(&self) -> bool {
+        self.bytes == self.expected
+    }
+
+    /// Checks the next buffer from the stream, ignoring data past the expected
+    /// length. Returns `false` if no buffer arrived within `timeout`.
+    fn receive(&mut self, stream: &Stream, timeout: Duration) -> ft60x_rs::Result<bool> {
+        let Some(buffer) = stream.recv_timeout(timeout) else {
+            return Ok(false);
+        };
+        let buffer = buffer?;
+        let n = buffer.len().min(self.expected - self.bytes);
+        self.checker.check(&buffer[..n]);
+        self.bytes += n;
+        Ok(true)
+    }
+}
+
+/// Transfer statistics of every pipe used since the device was opened.
+fn stats_report(device: &Device) -> Value {
+    Pipe::ALL
+        .into_iter()
+        .filter_map(|pipe| {
+            let stats = device.stats(pipe).filter(|stats| stats.transfers > 0)?;
+            Some((pipe.to_string(), stats_json(&stats)))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn stats_json(stats: &PipeStats) -> Value {
+    let micros = |d: Duration| d.as_secs_f64() * 1e6;
+    json!({
+        "bytes": stats.bytes,
+        "transfers": stats.transfers,
+        "timeouts": stats.timeouts,
+        "aborts": stats.aborts,
+        "short_reads": stats.short_reads,
+        "largest_gap_us": micros(stats.largest_gap),
+        "latency_us": {
+            "count": stats.latency.count(),
+            "min": micros(stats.latency.min()),
+            "mean": micros(stats.latency.mean()),
+            "p50": micros(stats.latency.value_at_quantile(0.5)),
+            "p99": micros(stats.latency.value_at_quantile(0.99)),
+            "max": micros(stats.latency.max()),
+        },
+    })
+}
+
+/// The result of a query, or the error it failed with.
+fn query<T: Serialize>(result: ft60x_rs::Result<T>) -> Value {
+    match result {
+        Ok(value) => serde_json::to_value(value).unwrap_or(Value::Null),
+        Err(e) => error_json(&e),
+    }
+}
+
+fn error_json(e: &ft60x_rs::Error) -> Value {
+    json!({ "error": e.kind(), "message": e.to_string() })
+}
+
+fn json_file(value: &Value) -> serde_json::Result<Vec<u8>> {
+    let mut data = serde_json::to_vec_pretty(value)?;
+    data.push(b'\n');
+    Ok(data)
+}
+
+/// Writes regular files to a POSIX ustar archive.
+struct TarWriter<W: Write> {
+    inner: W,
+    /// Modification time of every file, in seconds since the Unix epoch.
+    mtime: u64,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(inner: W, mtime: u64) -> Self {
+        Self { inner, mtime }
+    }
+
+    /// Appends a file. Paths longer than 100 bytes are split at a `/` between the
+    /// name and prefix fields.
+    ///
+    /// # Errors
+    /// [`io::ErrorKind::InvalidInput`] if the path cannot be split so, or the file is
+    /// 8 GiB or larger.
+    fn append(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        let (prefix, name) = split_path(path)?;
+        if data.len() as u64 >= 1 << 33 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path} is too large for a tar archive"),
+            ));
+        }
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[108..116].copy_from_slice(b"0000000\0");
+        header[116..124].copy_from_slice(b"0000000\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        header[136..148].copy_from_slice(format!("{:011o}\0", self.mtime).as_bytes());
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        // The checksum is computed with its own field filled with spaces.
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(data)?;
+        let padding = data.len().next_multiple_of(512) - data.len();
+        self.inner.write_all(&[0; 512][..padding])
+    }
+
+    /// Writes the end-of-archive marker and flushes the archive.
+    fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&[0; 1024])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Splits `path` into the ustar prefix and name fields, of up to 155 and 100 bytes.
+/// The `/` between them is not stored.
+fn split_path(path: &str) -> io::Result<(&str, &str)> {
+    if path.len() <= 100 {
+        return Ok(("", path));
+    }
+    path.match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && !name.is_empty() && name.len() <= 100)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path} is too long for a tar archive"),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, process::Command};
+
+    use clap::Parser;
+
+    use super::*;
+
+    /// Extracts `archive` into `directory` with the system `tar`, and returns the
+    /// paths it lists.
+    fn untar(archive: &Path, directory: &Path) -> Vec<String> {
+        let list = Command::new("tar")
+            .arg("-tf")
+            .arg(archive)
+            .output()
+            .unwrap();
+        assert!(list.status.success(), "{list:?}");
+        let status = Command::new("tar")
+            .arg("-xf")
+            .arg(archive)
+            .arg("-C")
+            .arg(directory)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        String::from_utf8(list.stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn tar_round_trip() {
+        let long_directory = format!("{ROOT}/{}", "d".repeat(120));
+        let files = [
+            (format!("{ROOT}/empty.txt"), Vec::new()),
+            (format!("{ROOT}/one.txt"), vec![b'x']),
+            (format!("{ROOT}/511.bin"), vec![1; 511]),
+            (format!("{ROOT}/512.bin"), vec![2; 512]),
+            (format!("{ROOT}/513.bin"), vec![3; 513]),
+            // Exactly fills the name field, with no terminating NUL.
+            ("n".repeat(100), b"hundred".to_vec()),
+            (
+                format!("{long_directory}/{}", "f".repeat(100)),
+                b"long".to_vec(),
+            ),
+        ];
+        let directory = tempfile::tempdir().unwrap();
+        let archive = directory.path().join("test.tar");
+        let mut tar = TarWriter::new(File::create(&archive).unwrap(), 1_700_000_000);
+        for (path, data) in &files {
+            tar.append(path, data).unwrap();
+        }
+        tar.finish().unwrap();
+
+        // Each file takes a header and its data padded to whole blocks, and the
+        // archive ends with two zero blocks.
+        let bytes = fs::read(&archive).unwrap();
+        let expected_len: usize = files
+            .iter()
+            .map(|(_, data)| 512 + data.len().next_multiple_of(512))
+            .sum::<usize>()
+            + 1024;
+        assert_eq!(bytes.len(), expected_len);
+        assert!(bytes[bytes.len() - 1024..].iter().all(|&b| b == 0));
+        // The padding after each file is zero.
+        let mut offset = 0;
+        for (_, data) in &files {
+            let padded = data.len().next_multiple_of(512);
+            let padding = &bytes[offset + 512 + data.len()..offset + 512 + padded];
+            assert!(padding.iter().all(|&b| b == 0));
+            offset += 512 + padded;
+        }
+
+        let extracted = directory.path().join("extracted");
+        fs::create_dir(&extracted).unwrap();
+        let listed = untar(&archive, &extracted);
+        let paths: Vec<&String> = files.iter().map(|(path, _)| path).collect();
+        assert_eq!(listed.iter().collect::<Vec<_>>(), paths);
+        for (path, data) in &files {
+            assert_eq!(&fs::read(extracted.join(path)).unwrap(), data, "{path}");
+        }
+    }
+
+    #[test]
+    fn tar_rejects_unsplittable_paths() {
+        let mut tar = TarWriter::new(Vec::new(), 0);
+        for path in [
+            "x".repeat(101),
+            format!("{ROOT}/{}", "x".repeat(101)),
+            format!("{}/name", "p".repeat(156)),
+        ] {
+            let err = tar.append(&path, b"").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        // Nothing was written for the rejected files.
+        assert_eq!(tar.finish().unwrap(), [0; 1024]);
+    }
+
+    /// Runs `ft60x diag` against whatever the bundled library finds, usually no
+    /// device, and checks the archive holds a report for each part.
+    #[test]
+    fn diag_archive() {
+        if let Err(e) = ft60x_rs::load_bundled_dylib() {
+            assert!(
+                matches!(e.kind(), ft60x_rs::D3xxError::LibraryAlreadyLoaded),
+                "cannot load the D3XX library: {e}"
+            );
+        }
+        let directory = tempfile::tempdir().unwrap();
+        let archive = directory.path().join("diag.tar");
+        let cli = Cli::try_parse_from([
+            "ft60x".as_ref(),
+            "--format".as_ref(),
+            "json".as_ref(),
+            "diag".as_ref(),
+            "--test".as_ref(),
+            "none".as_ref(),
+            "--output".as_ref(),
+            archive.as_os_str(),
+        ])
+        .unwrap();
+        let crate::Command::Diag(args) = &cli.command else {
+            unreachable!()
+        };
+        run(&cli, args).unwrap();
+
+        let listed = untar(&archive, directory.path());
+        let names = [
+            "host.json",
+            "devices.json",
+            "device.json",
+            "test.json",
+            "stats.json",
+            "summary.txt",
+        ];
+        assert_eq!(listed, names.map(|name| format!("{ROOT}/{name}")));
+        let root = directory.path().join(ROOT);
+        let read_json = |name: &str| -> Value {
+            serde_json::from_slice(&fs::read(root.join(name)).unwrap()).unwrap()
+        };
+        let host = read_json("host.json");
+        assert_eq!(host["ft60x_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(host["library"], "bundled");
+        assert!(host["library_version"]["major"].is_u64());
+        let devices = read_json("devices.json");
+        assert!(devices.is_array() || devices["error"]["name"].is_string());
+        let device = read_json("device.json");
+        // With no device the open error is recorded in place of the report.
+        assert!(device["info"].is_object() || device["error"]["name"].is_string());
+        read_json("test.json");
+        read_json("stats.json");
+        let summary = fs::read_to_string(root.join("summary.txt")).unwrap();
+        assert!(summary.contains("Library version"));
+        assert!(summary.contains("Devices"));
+    }
+}
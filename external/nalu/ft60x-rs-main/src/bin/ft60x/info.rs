@@ -0,0 +1,117 @@
+//! `ft60x info`
+
+use ft60x_rs::{DeviceSelector, Pipe};
+use serde_json::json;
+
+use crate::{
+    link_speed,
+    table::{print_fields, Table},
+    Cli, CliResult, Format,
+};
+
+pub fn run(cli: &Cli, selector: Option<&DeviceSelector>) -> CliResult {
+    let device = crate::open_selected(selector.or(cli.device.as_ref()))?;
+    let info = device.info()?;
+    let descriptor = device.device_descriptor()?;
+    let driver_version = device.driver_version()?;
+    let library_version = ft60x_rs::d3xx_version();
+    let link = link_speed(&info);
+    let chip = device.chip_type().ok().map(|chip| format!("{chip:?}"));
+    // Not every platform's D3XX library supports reading the suspend timeout.
+    let suspend_timeout = device.suspend_timeout().ok();
+    // Pipes which are not enabled by the channel configuration have no information.
+    let pipes = Pipe::ALL
+        .into_iter()
+        .map(|pipe| Ok((pipe, device.get_timeout(pipe)?, device.pipe_info(pipe).ok())))
+        .collect::<ft60x_rs::Result<Vec<_>>>()?;
+
+    match cli.format {
+        Format::Json => {
+            let report = json!({
+                "device": info,
+                "chip": chip,
+                "link": link,
+                "driver_version": driver_version.to_string(),
+                "library_version": library_version.to_string(),
+                "suspend_timeout_ms": suspend_timeout.map(|t| t.as_millis() as u64),
+                "descriptor": {
+                    "usb_specification": descriptor.usb_specification_number(),
+                    "class": descriptor.class_code(),
+                    "subclass": descriptor.subclass_code(),
+                    "protocol": descriptor.protocol_code(),
+                    "max_packet_size": descriptor.max_packet_size(),
+                    "vendor_id": descriptor.vendor_id(),
+                    "product_id": descriptor.product_id(),
+                    "release": descriptor.release_number(),
+                    "configurations": descriptor.num_configurations(),
+                },
+                "pipes": pipes.iter().map(|(pipe, timeout, pipe_info)| json!({
+                    "pipe": pipe.to_string(),
+                    "address": *pipe as u8,
+                    "channel": pipe.channel(),
+                    "timeout_ms": timeout.as_millis() as u64,
+                    "info": pipe_info,
+                })).collect::<Vec<_>>(),
+            });
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Format::Table => {
+            print_fields(&[
+                ("Serial number", info.serial_number().to_owned()),
+                ("Description", info.description().to_owned()),
+                ("Chip", chip.unwrap_or_else(|| "unknown".to_owned())),
+                (
+                    "VID:PID",
+                    format!("{:04x}:{:04x}", info.vendor_id(), info.product_id()),
+                ),
+                ("Link", link.unwrap_or("unknown").to_owned()),
+                (
+                    "USB specification",
+                    format!("{:#06x}", descriptor.usb_specification_number()),
+                ),
+                ("Release", format!("{:#06x}", descriptor.release_number())),
+                (
+                    "Max packet size (EP0)",
+                    descriptor.max_packet_size().to_string(),
+                ),
+                ("Driver version", driver_version.to_string()),
+                ("Library version", library_version.to_string()),
+                (
+                    "Suspend timeout",
+                    suspend_timeout.map_or_else(|| "n/a".to_owned(), |t| format!("{t:?}")),
+                ),
+            ]);
+            println!();
+            let mut table = Table::new([
+                "PIPE",
+                "ADDRESS",
+                "CHANNEL",
+                "TIMEOUT",
+                "TYPE",
+                "MAX PACKET",
+                "INTERVAL",
+            ]);
+            for (pipe, timeout, pipe_info) in &pipes {
+                let (type_, max_packet, interval) = match pipe_info {
+                    Some(pipe_info) => (
+                        format!("{:?}", pipe_info.type_()),
+                        pipe_info.maximum_packet_size().to_string(),
+                        pipe_info.interval().to_string(),
+                    ),
+                    None => ("disabled".to_owned(), "-".to_owned(), "-".to_owned()),
+                };
+                table.row([
+                    pipe.to_string(),
+                    format!("{:#04x}", *pipe as u8),
+                    pipe.channel().to_string(),
+                    format!("{timeout:?}"),
+                    type_,
+                    max_packet,
+                    interval,
+                ]);
+            }
+            table.print();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! `ft60x list`
+
+use crate::{table::Table, Cli, CliResult, Format};
+
+pub fn run(cli: &Cli) -> CliResult {
+    let devices = ft60x_rs::list_devices()?;
+    match cli.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&devices)?),
+        Format::Table => {
+            let mut table =
+                Table::new(["INDEX", "SERIAL", "DESCRIPTION", "CHIP", "VID:PID", "OPEN"]);
+            for device in &devices {
+                table.row([
+                    device.index().to_string(),
+                    device.serial_number().to_owned(),
+                    device.description().to_owned(),
+                    device
+                        .chip_type()
+                        .map_or_else(|| "unknown".to_owned(), |chip| format!("{chip:?}")),
+                    format!("{:04x}:{:04x}", device.vendor_id(), device.product_id()),
+                    if device.is_open() { "yes" } else { "no" }.to_owned(),
+                ]);
+            }
+            table.print();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! `ft60x`: command-line tool for inspecting FT60x devices and making raw transfers.
+//!
+//! Built with the `cli` feature:
+//!
+//! ```text
+//! cargo install ft60x-rs --features cli
+//! ft60x list
+//! ft60x -d 000000000001 read in0 --seconds 10 -o capture.bin
+//! ```
+
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use ft60x_rs::{Device, DeviceInfo, DeviceSelector, OpenOptions, Pipe};
+
+mod bench;
+mod diag;
+mod info;
+mod list;
+mod pattern;
+mod record;
+mod table;
+#[cfg(feature = "tui")]
+mod top;
+mod transfer;
+
+/// Result type for subcommands. Errors are printed by `main`.
+type CliResult<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "ft60x",
+    version,
+    about = "Inspect FT600/FT601 devices and make raw transfers"
+)]
+struct Cli {
+    /// Load the D3XX library from this path instead of the bundled copy.
+    #[arg(long, global = true, value_name = "PATH")]
+    library: Option<PathBuf>,
+
+    /// Device to use: a serial number, `index:N` or `desc:DESCRIPTION`.
+    /// Defaults to the first device.
+    #[arg(short, long, global = true, value_name = "SELECTOR", value_parser = parse_selector)]
+    device: Option<DeviceSelector>,
+
+    /// Output format for reports.
+    #[arg(short, long, global = true, value_enum, default_value_t = Format::Table)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Output format for reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Human-readable table.
+    Table,
+    /// JSON, for scripts.
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List connected devices.
+    List,
+    /// Show descriptors, versions, link speed and pipe settings of a device.
+    Info {
+        /// Device to inspect; overrides `--device`.
+        #[arg(value_parser = parse_selector)]
+        selector: Option<DeviceSelector>,
+    },
+    /// Read from an IN pipe to a file or stdout.
+    Read(transfer::ReadArgs),
+    /// Write a file or stdin to an OUT pipe.
+    Write(transfer::WriteArgs),
+    /// Discard data buffered in an IN pipe.
+    Flush {
+        /// The IN pipe, e.g. `in0` or `0x82`.
+        #[arg(value_parser = parse_pipe)]
+        pipe: Pipe,
+    },
+    /// Abort pending transfers on a pipe.
+    Abort {
+        /// The pipe, e.g. `out0` or `0x02`.
+        #[arg(value_parser = parse_pipe)]
+        pipe: Pipe,
+    },
+    /// Record an IN pipe to rotating, optionally compressed files with a manifest.
+    Record(record::RecordArgs),
+    /// Measure throughput and latency over a sweep of transfer settings.
+    Bench(bench::BenchArgs),
+    /// Check or generate a counter or PRBS-31 test pattern.
+    #[command(subcommand)]
+    Pattern(pattern::PatternCommand),
+    /// Live view of attached devices, pipe throughput and errors, and incoming data.
+    #[cfg(feature = "tui")]
+    Top(top::TopArgs),
+    /// Power cycle the USB port of the device, forcing it to re-enumerate.
+    CyclePort,
+    /// Collect descriptors, configuration, versions, the device list and the results
+    /// of a short data test into an archive to attach to a support request.
+    Diag(diag::DiagArgs),
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> CliResult {
+    match &cli.library {
+        Some(path) => ft60x_rs::load_dylib(path)?,
+        None => ft60x_rs::load_bundled_dylib()?,
+    }
+
+    match &cli.command {
+        Command::List => list::run(&cli),
+        Command::Info { selector } => info::run(&cli, selector.as_ref()),
+        Command::Read(args) => transfer::read(&cli, args),
+        Command::Write(args) => transfer::write(&cli, args),
+        Command::Flush { pipe } => Ok(open(&cli)?.flush(*pipe)?),
+        Command::Abort { pipe } => Ok(open(&cli)?.abort_transfers(*pipe)?),
+        Command::Record(args) => record::run(&cli, args),
+        Command::Bench(args) => bench::run(&cli, args),
+        Command::Pattern(command) => pattern::run(&cli, command),
+        #[cfg(feature = "tui")]
+        Command::Top(args) => top::run(&cli, args),
+        Command::CyclePort => Ok(open(&cli)?.power_cycle_port()?),
+        Command::Diag(args) => diag::run(&cli, args),
+    }
+}
+
+/// Opens the device chosen with `--device`, or the first device.
+fn open(cli: &Cli) -> CliResult<Device> {
+    open_selected(cli.device.as_ref())
+}
+
+fn open_selected(selector: Option<&DeviceSelector>) -> CliResult<Device> {
+    let selector = selector.cloned().unwrap_or(DeviceSelector::Index(0));
+    Ok(OpenOptions::new().open(selector)?)
+}
+
+/// The link speed given by the flags in the device list, if it is known.
+fn link_speed(info: &DeviceInfo) -> Option<&'static str> {
+    info.link_speed().map(|speed| speed.name())
+}
+
+/// Parses a device selector: `index:N`, `desc:DESCRIPTION`, `serial:SERIAL` or a
+/// bare serial number.
+fn parse_selector(s: &str) -> Result<DeviceSelector, String> {
+    if let Some(index) = s.strip_prefix("index:") {
+        let index = index
+            .parse()
+            .map_err(|_| format!("invalid device index `{index}`"))?;
+        Ok(DeviceSelector::Index(index))
+    } else if let Some(description) = s.strip_prefix("desc:") {
+        Ok(DeviceSelector::Description(description.to_owned()))
+    } else {
+        Ok(DeviceSelector::SerialNumber(
+            s.strip_prefix("serial:").unwrap_or(s).to_owned(),
+        ))
+    }
+}
+
+/// Parses a pipe name such as `in0` or an endpoint address such as `0x82`.
+fn parse_pipe(s: &str) -> Result<Pipe, String> {
+    s.parse().map_err(|_| {
+        format!("unknown pipe `{s}`; expected in0-in3, out0-out3 or an endpoint address")
+    })
+}
+
+/// Parses a byte count with an optional binary suffix: `512`, `64K`, `1M`, `2G`.
+fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size `{s}`"))
+}
+
+/// Parses a duration in seconds, which may be fractional.
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid number of seconds `{s}`"))
+}
+
+/// Parses a duration in milliseconds.
+fn parse_millis(s: &str) -> Result<Duration, String> {
+    s.parse()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("invalid number of milliseconds `{s}`"))
+}
+
+/// Formats a transfer rate in MB/s (10^6 bytes per second).
+fn megabytes_per_second(bytes: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        secs if secs > 0.0 => bytes as f64 / secs / 1e6,
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,219 @@
+//! `ft60x pattern check` and `ft60x pattern generate`
+
+use std::time::{Duration, Instant};
+
+use clap::{Args, Subcommand};
+use ft60x_rs::{Pattern, PatternChecker, PatternGenerator, PatternReport, Pipe, StreamConfig};
+use serde::Serialize;
+
+use crate::{
+    megabytes_per_second, parse_pipe, parse_size,
+    table::print_fields,
+    transfer::{bus_width, Limit},
+    Cli, CliResult, Format,
+};
+
+#[derive(Debug, Subcommand)]
+pub enum PatternCommand {
+    /// Check the pattern read from an IN pipe. Fails if any error is found.
+    Check(CheckArgs),
+    /// Write the pattern to an OUT pipe.
+    Generate(GenerateArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// The IN pipe, e.g. `in0` or `0x82`.
+    #[arg(value_parser = parse_pipe)]
+    pipe: Pipe,
+    /// The expected pattern: `counter` or `prbs31`.
+    #[arg(short, long, value_parser = parse_pattern, default_value = "prbs31")]
+    pattern: Pattern,
+    #[command(flatten)]
+    limit: Limit,
+    /// Bytes requested per read. Must be a multiple of 1024.
+    #[arg(long, value_parser = parse_size, default_value = "1M")]
+    chunk_size: usize,
+    /// Largest number of consecutive dropped words to recognize.
+    #[arg(long)]
+    max_skip: Option<u32>,
+    /// Consecutive word errors after which the checker resynchronizes.
+    #[arg(long, default_value_t = 4)]
+    resync_threshold: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct GenerateArgs {
+    /// The OUT pipe, e.g. `out0` or `0x02`.
+    #[arg(value_parser = parse_pipe)]
+    pipe: Pipe,
+    /// The pattern to write: `counter` or `prbs31`.
+    #[arg(short, long, value_parser = parse_pattern, default_value = "prbs31")]
+    pattern: Pattern,
+    /// The first word of the pattern.
+    #[arg(long, default_value_t = 1)]
+    seed: u32,
+    #[command(flatten)]
+    limit: Limit,
+    /// Bytes sent per write. Must be a multiple of 1024.
+    #[arg(long, value_parser = parse_size, default_value = "1M")]
+    chunk_size: usize,
+}
+
+/// The JSON report of `ft60x pattern check`.
+#[derive(Debug, Serialize)]
+struct CheckReport<'a> {
+    pattern: Pattern,
+    bytes: u64,
+    seconds: f64,
+    bit_error_rate: f64,
+    /// Times the host fell behind the device; data may have been lost.
+    overruns: u64,
+    #[serde(flatten)]
+    report: &'a PatternReport,
+}
+
+pub fn run(cli: &Cli, command: &PatternCommand) -> CliResult {
+    match command {
+        PatternCommand::Check(args) => check(cli, args),
+        PatternCommand::Generate(args) => generate(cli, args),
+    }
+}
+
+fn check(cli: &Cli, args: &CheckArgs) -> CliResult {
+    let device = crate::open(cli)?;
+    let mut checker = PatternChecker::new(args.pattern);
+    checker.set_resync_threshold(args.resync_threshold);
+    if let Some(max_skip) = args.max_skip {
+        checker.set_max_skip(max_skip);
+    }
+    let stream = device.stream(
+        args.pipe,
+        StreamConfig {
+            buffer_size: args.chunk_size,
+            ..Default::default()
+        },
+    )?;
+
+    let start = Instant::now();
+    let deadline = args.limit.seconds.map(|seconds| start + seconds);
+    let mut total = 0;
+    loop {
+        let remaining = args.limit.bytes.map(|bytes| bytes - total);
+        if remaining == Some(0) {
+            break;
+        }
+        let timeout = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(timeout) => timeout,
+                None => break,
+            },
+            None => Duration::MAX,
+        };
+        let Some(buffer) = stream.recv_timeout(timeout) else {
+            break;
+        };
+        let buffer = buffer?;
+        let n = remaining.map_or(buffer.len(), |remaining| buffer.len().min(remaining));
+        checker.check(&buffer[..n]);
+        total += n;
+    }
+    let elapsed = start.elapsed();
+    let overruns = stream.overruns();
+    drop(stream);
+
+    let report = checker.report();
+    match cli.format {
+        Format::Json => {
+            let report = CheckReport {
+                pattern: args.pattern,
+                bytes: total as u64,
+                seconds: elapsed.as_secs_f64(),
+                bit_error_rate: report.bit_error_rate(),
+                overruns,
+                report,
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Format::Table => print_fields(&[
+            ("Pattern", format!("{:?}", args.pattern)),
+            ("Bytes", total.to_string()),
+            (
+                "Throughput",
+                format!("{:.1} MB/s", megabytes_per_second(total as u64, elapsed)),
+            ),
+            ("Words", report.words.to_string()),
+            ("Bit errors", report.bit_errors.to_string()),
+            ("Bit error rate", format!("{:.3e}", report.bit_error_rate())),
+            ("Word errors", report.word_errors.to_string()),
+            (
+                "First error at byte",
+                report
+                    .first_error_offset
+                    .map_or_else(|| "-".to_owned(), |offset| offset.to_string()),
+            ),
+            ("Dropped words", report.dropped_words.to_string()),
+            ("Duplicated words", report.duplicated_words.to_string()),
+            ("Resyncs", report.resyncs.to_string()),
+            ("Overruns", overruns.to_string()),
+        ]),
+    }
+
+    if report.words == 0 {
+        return Err("no data received".into());
+    }
+    if !report.is_clean() {
+        return Err("the stream does not match the pattern".into());
+    }
+    Ok(())
+}
+
+fn generate(cli: &Cli, args: &GenerateArgs) -> CliResult {
+    let device = crate::open(cli)?;
+    let mut generator = PatternGenerator::new(args.pattern, args.seed);
+    let mut buf = device.alloc_buf(args.chunk_size)?;
+    // Whole pattern words, in whole FIFO words.
+    let granularity = bus_width(&device).max(4);
+
+    let start = Instant::now();
+    let deadline = args.limit.seconds.map(|seconds| start + seconds);
+    let mut total = 0;
+    loop {
+        let remaining = args.limit.bytes.map(|bytes| bytes.saturating_sub(total));
+        if remaining == Some(0) || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+        let len = remaining.map_or(buf.capacity(), |remaining| {
+            remaining.min(buf.capacity()).next_multiple_of(granularity)
+        });
+        buf.set_len(len)?;
+        generator.fill(&mut buf)?;
+        let written = device.write(args.pipe, &buf)?;
+        total += written;
+        if written < len {
+            return Err(
+                format!("short write: {written} of {len} bytes after {total} bytes").into(),
+            );
+        }
+    }
+
+    let elapsed = start.elapsed();
+    eprintln!(
+        "wrote {total} bytes of {:?} to {} in {elapsed:.2?} ({:.2} MB/s)",
+        args.pattern,
+        args.pipe,
+        megabytes_per_second(total as u64, elapsed),
+    );
+    Ok(())
+}
+
+/// Parses a pattern name.
+fn parse_pattern(s: &str) -> Result<Pattern, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "counter" => Ok(Pattern::Counter),
+        "prbs31" | "prbs-31" => Ok(Pattern::Prbs31),
+        _ => Err(format!(
+            "unknown pattern `{s}`; expected `counter` or `prbs31`"
+        )),
+    }
+}
@@ -0,0 +1,240 @@
+//! `ft60x record`
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use clap::{Args, ValueEnum};
+use ft60x_rs::{
+    recorder::{Compression, FsyncPolicy, Recorder, RecorderConfig},
+    Pipe, StreamConfig,
+};
+use serde::Serialize;
+
+use crate::{
+    megabytes_per_second, parse_pipe, parse_seconds, parse_size,
+    table::{print_fields, Table},
+    Cli, CliResult, Format,
+};
+
+/// Set by the SIGINT handler.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Args)]
+pub struct RecordArgs {
+    /// The IN pipe, e.g. `in0` or `0x82`.
+    #[arg(value_parser = parse_pipe)]
+    pipe: Pipe,
+    /// Directory for the segments and manifest.
+    #[arg(short, long, value_name = "DIR")]
+    output: PathBuf,
+    /// Prefix of the file names.
+    #[arg(long, default_value = "ft60x")]
+    prefix: String,
+    /// Start a new segment after this many bytes of data; `0` for no size limit.
+    #[arg(long, value_parser = parse_size, default_value = "1G")]
+    segment_size: usize,
+    /// Start a new segment after this many seconds.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    segment_time: Option<Duration>,
+    /// Compression of the segments.
+    #[arg(short, long, value_enum, default_value_t = CompressionArg::None)]
+    compression: CompressionArg,
+    /// Zstandard compression level.
+    #[cfg_attr(not(feature = "zstd"), allow(dead_code))]
+    #[arg(long, default_value_t = 3)]
+    level: i32,
+    /// When to sync segments to disk: `never`, `segment` or an interval in seconds.
+    #[arg(long, value_parser = parse_fsync, default_value = "segment")]
+    fsync: FsyncPolicy,
+    /// Stop when less than this much space is left on the disk.
+    #[arg(long, value_parser = parse_size, default_value = "1G")]
+    min_free: usize,
+    /// Stop after this many bytes. Without a limit, recording runs until Ctrl-C.
+    #[arg(long, value_parser = parse_size)]
+    bytes: Option<usize>,
+    /// Stop after this many seconds.
+    #[arg(long, value_parser = parse_seconds)]
+    seconds: Option<Duration>,
+    /// Bytes requested per read. Must be a multiple of 1024.
+    #[arg(long, value_parser = parse_size, default_value = "1M")]
+    chunk_size: usize,
+    /// Buffers queued for the disk before reading stalls.
+    #[arg(long, default_value_t = 256)]
+    queue_len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CompressionArg {
+    None,
+    Lz4,
+    /// Requires a build with the `zstd` feature.
+    Zstd,
+}
+
+/// The JSON report of `ft60x record`.
+#[derive(Debug, Serialize)]
+struct RecordReport {
+    directory: PathBuf,
+    bytes: u64,
+    stored_bytes: u64,
+    seconds: f64,
+    /// Times the host fell behind the device; data may have been lost.
+    overruns: u64,
+    segments: Vec<SegmentReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct SegmentReport {
+    file: String,
+    bytes: u64,
+    stored_bytes: u64,
+    sha256: String,
+}
+
+pub fn run(cli: &Cli, args: &RecordArgs) -> CliResult {
+    let mut config = RecorderConfig::new(&args.output);
+    config.prefix.clone_from(&args.prefix);
+    config.segment_bytes = match args.segment_size {
+        0 => None,
+        n => Some(n as u64),
+    };
+    config.segment_duration = args.segment_time;
+    config.compression = match args.compression {
+        CompressionArg::None => Compression::None,
+        CompressionArg::Lz4 => Compression::Lz4,
+        #[cfg(feature = "zstd")]
+        CompressionArg::Zstd => Compression::Zstd(args.level),
+        #[cfg(not(feature = "zstd"))]
+        CompressionArg::Zstd => return Err("built without the `zstd` feature".into()),
+    };
+    config.fsync = args.fsync;
+    config.min_free_bytes = args.min_free as u64;
+    config.queue_len = args.queue_len;
+
+    let device = crate::open(cli)?;
+    let mut recorder = Recorder::new(config)?;
+    let mut stream = device.stream(
+        args.pipe,
+        StreamConfig {
+            buffer_size: args.chunk_size,
+            ..Default::default()
+        },
+    )?;
+    catch_interrupt();
+
+    let start = Instant::now();
+    let deadline = args.seconds.map(|seconds| start + seconds);
+    let limit = args.bytes.map(|bytes| bytes as u64);
+    let mut last_progress = start;
+    // The segments written so far are closed and reported even if reading fails.
+    let drained = recorder.drain(&mut stream, |stats| {
+        if cli.format == Format::Table && last_progress.elapsed() >= Duration::from_secs(1) {
+            last_progress = Instant::now();
+            eprint!(
+                "\r{} MB in {} segments, {:.1} MB/s, {} MB stored, {} queued   ",
+                stats.bytes / 1_000_000,
+                stats.segments,
+                megabytes_per_second(stats.bytes, start.elapsed()),
+                stats.stored_bytes / 1_000_000,
+                stats.queued,
+            );
+        }
+        INTERRUPTED.load(Ordering::Relaxed)
+            || limit.is_some_and(|limit| stats.bytes >= limit)
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    });
+    let overruns = stream.overruns();
+    drop(stream);
+    let segments = match (recorder.finish(), &drained) {
+        (Ok(segments), _) => segments,
+        (Err(e), Ok(())) => return Err(e.into()),
+        (Err(e), Err(error)) => {
+            return Err(format!("{error}; closing the recording also failed: {e}").into())
+        }
+    };
+    let elapsed = start.elapsed();
+    if cli.format == Format::Table {
+        eprintln!();
+    }
+
+    let bytes = segments.iter().map(|s| s.bytes).sum();
+    let stored_bytes = segments.iter().map(|s| s.stored_bytes).sum();
+    match cli.format {
+        Format::Json => {
+            let report = RecordReport {
+                directory: args.output.clone(),
+                bytes,
+                stored_bytes,
+                seconds: elapsed.as_secs_f64(),
+                overruns,
+                segments: segments
+                    .iter()
+                    .map(|s| SegmentReport {
+                        file: s.file_name.clone(),
+                        bytes: s.bytes,
+                        stored_bytes: s.stored_bytes,
+                        sha256: s.sha256_hex(),
+                    })
+                    .collect(),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Format::Table => {
+            let mut table = Table::new(["FILE", "BYTES", "STORED", "SHA256"]);
+            for s in &segments {
+                table.row([
+                    s.file_name.clone(),
+                    s.bytes.to_string(),
+                    s.stored_bytes.to_string(),
+                    s.sha256_hex(),
+                ]);
+            }
+            table.print();
+            println!();
+            print_fields(&[
+                ("Directory", args.output.display().to_string()),
+                ("Bytes", bytes.to_string()),
+                ("Stored bytes", stored_bytes.to_string()),
+                (
+                    "Throughput",
+                    format!("{:.1} MB/s", megabytes_per_second(bytes, elapsed)),
+                ),
+                ("Overruns", overruns.to_string()),
+            ]);
+        }
+    }
+    drained?;
+    Ok(())
+}
+
+/// Parses `never`, `segment` or an fsync interval in seconds.
+fn parse_fsync(s: &str) -> Result<FsyncPolicy, String> {
+    match s {
+        "never" => Ok(FsyncPolicy::Never),
+        "segment" => Ok(FsyncPolicy::Segment),
+        _ => parse_seconds(s).map(FsyncPolicy::Interval).map_err(|_| {
+            format!("invalid fsync policy `{s}`; expected `never`, `segment` or seconds")
+        }),
+    }
+}
+
+/// Makes the first Ctrl-C stop recording cleanly, so the last segment is closed and
+/// the manifest completed. A second Ctrl-C terminates the process.
+#[cfg(unix)]
+fn catch_interrupt() {
+    extern "C" fn handle(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+    unsafe {
+        libc::signal(libc::SIGINT, handle as *const () as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_interrupt() {}
@@ -0,0 +1,69 @@
+//! Plain-text tables and key/value listings.
+
+/// A table with left-aligned columns, printed with two spaces between columns.
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<I, S>(headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            headers: headers.into_iter().map(Into::into).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row<I, S>(&mut self, cells: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.rows
+            .push(cells.into_iter().map(|cell| cell.to_string()).collect());
+    }
+
+    pub fn print(&self) {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate() {
+                match widths.get_mut(i) {
+                    Some(width) => *width = (*width).max(cell.chars().count()),
+                    None => widths.push(cell.chars().count()),
+                }
+            }
+        }
+        print_row(&self.headers, &widths);
+        for row in &self.rows {
+            print_row(row, &widths);
+        }
+    }
+}
+
+fn print_row(cells: &[String], widths: &[usize]) {
+    let line = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, &width)| format!("{cell:width$}"))
+        .collect::<Vec<_>>()
+        .join("  ");
+    println!("{}", line.trim_end());
+}
+
+/// Prints `key: value` lines with the values aligned.
+pub fn print_fields(fields: &[(&str, String)]) {
+    print!("{}", format_fields(fields));
+}
+
+/// Formats `key: value` lines as printed by [`print_fields`].
+pub fn format_fields(fields: &[(&str, String)]) -> String {
+    let width = fields.iter().map(|(key, _)| key.len()).max().unwrap_or(0) + 1;
+    fields
+        .iter()
+        .map(|(key, value)| format!("{:width$} {value}\n", format!("{key}:")))
+        .collect()
+}
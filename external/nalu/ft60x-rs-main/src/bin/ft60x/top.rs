@@ -0,0 +1,409 @@
+//! `ft60x top`
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use ft60x_rs::{D3xxError, Device, DeviceInfo, Pipe, PipeStats, Stream, StreamConfig};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+
+use crate::{link_speed, parse_millis, parse_pipe, parse_size, Cli, CliResult};
+
+/// How often the device list is rebuilt.
+const LIST_INTERVAL: Duration = Duration::from_secs(2);
+/// Bytes kept from the most recent buffer for the hex view.
+const PEEK_LEN: usize = 1024;
+/// Bytes per line of the hex view.
+const PEEK_WIDTH: usize = 16;
+
+#[derive(Debug, Args)]
+pub struct TopArgs {
+    /// IN pipes to read from while monitoring. Reading consumes the data, so nothing
+    /// else may be reading from the device.
+    #[arg(long, value_delimiter = ',', value_parser = parse_pipe, default_value = "in0")]
+    pipes: Vec<Pipe>,
+    /// Only list the attached devices, without opening one.
+    #[arg(long)]
+    list_only: bool,
+    /// Refresh interval in milliseconds.
+    #[arg(long, value_name = "MS", value_parser = parse_millis, default_value = "500")]
+    interval: Duration,
+    /// Bytes requested per read. Must be a multiple of 1024.
+    #[arg(long, value_parser = parse_size, default_value = "1M")]
+    chunk_size: usize,
+}
+
+/// State shared with a reader thread.
+#[derive(Default)]
+struct ReaderState {
+    /// The start of the most recent buffer.
+    peek: Vec<u8>,
+    overruns: u64,
+    /// Whether the last read timed out, and no data has arrived since.
+    idle: bool,
+    /// The error which ended the stream.
+    error: Option<String>,
+}
+
+/// Drains one IN pipe on a background thread.
+struct Reader {
+    pipe: Pipe,
+    state: Arc<Mutex<ReaderState>>,
+    thread: JoinHandle<()>,
+}
+
+/// Throughput and error rates of a pipe over the last refresh interval.
+#[derive(Default)]
+struct Rates {
+    bytes_per_second: f64,
+    transfers_per_second: f64,
+    timeouts_per_second: f64,
+    aborts_per_second: f64,
+}
+
+struct Monitor {
+    device: Device,
+    serial: String,
+    readers: Vec<Reader>,
+    stop: Arc<AtomicBool>,
+    previous: Vec<(Pipe, PipeStats)>,
+    rates: Vec<(Pipe, Rates, PipeStats)>,
+    sampled: Instant,
+}
+
+struct App {
+    devices: Vec<DeviceInfo>,
+    listed: Instant,
+    monitor: Option<Monitor>,
+    /// Why no device is being monitored, or the last failure to list devices.
+    status: Option<String>,
+}
+
+pub fn run(cli: &Cli, args: &TopArgs) -> CliResult {
+    let mut app = App {
+        devices: ft60x_rs::list_devices()?,
+        listed: Instant::now(),
+        monitor: None,
+        status: None,
+    };
+    if !args.list_only {
+        match start_monitor(cli, args) {
+            Ok(monitor) => app.monitor = Some(monitor),
+            Err(e) => app.status = Some(format!("not monitoring: {e}")),
+        }
+    }
+
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, &mut app, args);
+    ratatui::restore();
+    if let Some(monitor) = app.monitor {
+        monitor.stop.store(true, Ordering::Relaxed);
+        for reader in monitor.readers {
+            let _ = reader.thread.join();
+        }
+    }
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App, args: &TopArgs) -> CliResult {
+    loop {
+        if app.listed.elapsed() >= LIST_INTERVAL {
+            match ft60x_rs::list_devices() {
+                Ok(devices) => app.devices = devices,
+                Err(e) => app.status = Some(format!("listing devices failed: {e}")),
+            }
+            app.listed = Instant::now();
+        }
+        if let Some(monitor) = &mut app.monitor {
+            monitor.sample();
+        }
+        terminal.draw(|frame| draw(frame, app))?;
+
+        let deadline = Instant::now() + args.interval;
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            if !event::poll(timeout)? {
+                break;
+            }
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press
+                    && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc))
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn start_monitor(cli: &Cli, args: &TopArgs) -> CliResult<Monitor> {
+    let device = crate::open(cli)?;
+    device.set_stats_enabled(true);
+    let serial = device.info()?.serial_number().to_owned();
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut readers = Vec::new();
+    for &pipe in &args.pipes {
+        let mut stream = device.stream(
+            pipe,
+            StreamConfig {
+                buffer_size: args.chunk_size,
+                ..Default::default()
+            },
+        )?;
+        let state = Arc::new(Mutex::new(ReaderState::default()));
+        let thread = thread::spawn({
+            let state = state.clone();
+            let stop = stop.clone();
+            move || read(&mut stream, &state, &stop)
+        });
+        readers.push(Reader {
+            pipe,
+            state,
+            thread,
+        });
+    }
+    let previous = snapshot(&device);
+    Ok(Monitor {
+        device,
+        serial,
+        readers,
+        stop,
+        previous,
+        rates: Vec::new(),
+        sampled: Instant::now(),
+    })
+}
+
+fn read(stream: &mut Stream, state: &Mutex<ReaderState>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        match stream.recv_timeout(Duration::from_millis(100)) {
+            Some(Ok(buffer)) => {
+                let mut state = state.lock().unwrap();
+                state.peek.clear();
+                state
+                    .peek
+                    .extend_from_slice(&buffer[..buffer.len().min(PEEK_LEN)]);
+                state.overruns = stream.overruns();
+                state.idle = false;
+            }
+            // The stream ends after an error. A timeout only means the device had no
+            // data, so the stream is started again.
+            Some(Err(e)) if matches!(e.kind(), D3xxError::Timeout) => {
+                state.lock().unwrap().idle = true;
+                if let Err(e) = stream.restart() {
+                    state.lock().unwrap().error = Some(e.to_string());
+                    return;
+                }
+            }
+            Some(Err(e)) => {
+                state.lock().unwrap().error = Some(e.to_string());
+                return;
+            }
+            None => {}
+        }
+    }
+}
+
+/// The statistics of every pipe which has been used.
+fn snapshot(device: &Device) -> Vec<(Pipe, PipeStats)> {
+    Pipe::ALL
+        .into_iter()
+        .filter_map(|pipe| Some((pipe, device.stats(pipe)?)))
+        .filter(|(_, stats)| stats.transfers > 0)
+        .collect()
+}
+
+impl Monitor {
+    /// Updates the rates from the change in statistics since the previous sample.
+    fn sample(&mut self) {
+        let current = snapshot(&self.device);
+        let seconds = self.sampled.elapsed().as_secs_f64().max(1e-3);
+        self.sampled = Instant::now();
+        self.rates = current
+            .iter()
+            .map(|(pipe, now)| {
+                let before = self.previous.iter().find(|(p, _)| p == pipe);
+                let delta = |field: fn(&PipeStats) -> u64| {
+                    let before = before.map_or(0, |(_, stats)| field(stats));
+                    field(now).saturating_sub(before) as f64 / seconds
+                };
+                let rates = Rates {
+                    bytes_per_second: delta(|s| s.bytes),
+                    transfers_per_second: delta(|s| s.transfers),
+                    timeouts_per_second: delta(|s| s.timeouts),
+                    aborts_per_second: delta(|s| s.aborts),
+                };
+                (*pipe, rates, now.clone())
+            })
+            .collect();
+        self.previous = current;
+    }
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let pipe_rows = app.monitor.as_ref().map_or(0, |m| m.rates.len().max(1));
+    let [devices_area, pipes_area, peek_area, footer_area] = Layout::vertical([
+        Constraint::Length(app.devices.len().max(1) as u16 + 3),
+        Constraint::Length(pipe_rows as u16 + 3),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    frame.render_widget(devices_table(app), devices_area);
+    if let Some(monitor) = &app.monitor {
+        frame.render_widget(pipes_table(monitor), pipes_area);
+        let lines = peek_area.height.saturating_sub(2) as usize;
+        frame.render_widget(peek(monitor, lines), peek_area);
+    }
+
+    let footer = match &app.status {
+        Some(status) => Line::from(format!(" q quit | {status}")).red(),
+        None => Line::from(" q quit").dim(),
+    };
+    frame.render_widget(footer, footer_area);
+}
+
+fn devices_table(app: &App) -> Table<'static> {
+    let monitored = app.monitor.as_ref().map(|m| m.serial.as_str());
+    let rows = app.devices.iter().map(|info| {
+        let link = link_speed(info).unwrap_or("-");
+        let row = Row::new([
+            info.index().to_string(),
+            info.serial_number().to_owned(),
+            info.description().to_owned(),
+            info.chip_type()
+                .map_or_else(|| "unknown".to_owned(), |chip| format!("{chip:?}")),
+            link.to_owned(),
+            if info.is_open() { "yes" } else { "no" }.to_owned(),
+        ]);
+        if Some(info.serial_number()) == monitored {
+            row.bold()
+        } else {
+            row
+        }
+    });
+    Table::new(
+        rows,
+        [
+            Constraint::Length(5),
+            Constraint::Length(16),
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Length(5),
+            Constraint::Length(5),
+        ],
+    )
+    .header(Row::new(["INDEX", "SERIAL", "DESCRIPTION", "CHIP", "LINK", "OPEN"]).bold())
+    .block(Block::bordered().title(" Devices "))
+}
+
+fn pipes_table(monitor: &Monitor) -> Table<'static> {
+    let rows = monitor.rates.iter().map(|(pipe, rates, stats)| {
+        let reader = monitor.readers.iter().find(|r| r.pipe == *pipe);
+        let state = reader.map(|r| r.state.lock().unwrap());
+        let overruns = state.as_ref().map_or(0, |s| s.overruns);
+        let failed = state.as_ref().is_some_and(|s| s.error.is_some());
+        let row = Row::new([
+            pipe.to_string(),
+            format!("{:.1}", rates.bytes_per_second / 1e6),
+            format!("{:.0}", rates.transfers_per_second),
+            format!("{:.1}", rates.timeouts_per_second),
+            format!("{:.1}", rates.aborts_per_second),
+            overruns.to_string(),
+            format!(
+                "{:.0}",
+                stats.latency.value_at_quantile(0.99).as_secs_f64() * 1e6
+            ),
+            format!("{:.1}", stats.bytes as f64 / 1e6),
+        ]);
+        if failed || rates.timeouts_per_second > 0.0 || rates.aborts_per_second > 0.0 {
+            row.red()
+        } else {
+            row
+        }
+    });
+    let title = format!(" Pipes of {} ", monitor.serial);
+    Table::new(
+        rows,
+        [
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(8),
+            Constraint::Length(10),
+            Constraint::Length(9),
+            Constraint::Length(9),
+            Constraint::Length(9),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(
+        Row::new([
+            "PIPE",
+            "MB/S",
+            "XFER/S",
+            "TIMEOUT/S",
+            "ABORT/S",
+            "OVERRUNS",
+            "P99 us",
+            "TOTAL MB",
+        ])
+        .bold(),
+    )
+    .block(Block::bordered().title(title))
+}
+
+/// A hex and ASCII dump of the start of the most recent buffer of the first pipe.
+fn peek(monitor: &Monitor, lines: usize) -> Paragraph<'static> {
+    let Some(reader) = monitor.readers.first() else {
+        return Paragraph::new("").block(Block::bordered().title(" Data "));
+    };
+    let state = reader.state.lock().unwrap();
+    let mut text: Vec<Line> = Vec::new();
+    if let Some(error) = &state.error {
+        text.push(Line::from(format!("stream stopped: {error}")).red());
+    } else if state.idle {
+        text.push(Line::from("idle: no data within the pipe timeout").yellow());
+    }
+    for (i, chunk) in state.peek.chunks(PEEK_WIDTH).enumerate() {
+        if text.len() >= lines {
+            break;
+        }
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        text.push(Line::from(format!(
+            "{:08x}  {:<width$}  |{ascii}|",
+            i * PEEK_WIDTH,
+            hex.join(" "),
+            width = PEEK_WIDTH * 3 - 1,
+        )));
+    }
+    if state.peek.is_empty() && state.error.is_none() {
+        text.push(Line::from("no data received yet").dim());
+    }
+    let title = format!(" Most recent data on {} ", reader.pipe);
+    Paragraph::new(text).block(Block::bordered().title(title))
+}
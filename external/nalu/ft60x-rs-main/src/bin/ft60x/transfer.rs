@@ -0,0 +1,188 @@
+//! `ft60x read` and `ft60x write`
+
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use ft60x_rs::{D3xxError, Device, Pipe};
+
+use crate::{
+    megabytes_per_second, parse_millis, parse_pipe, parse_seconds, parse_size, Cli, CliResult,
+};
+
+/// When to stop a transfer; at least one limit is required.
+#[derive(Debug, Args)]
+pub struct Limit {
+    /// Stop after this many bytes, e.g. `64M`.
+    #[arg(long, value_parser = parse_size, required_unless_present = "seconds")]
+    pub bytes: Option<usize>,
+    /// Stop after this many seconds. Reads which time out are not errors when a
+    /// time limit is given.
+    #[arg(long, value_parser = parse_seconds)]
+    pub seconds: Option<Duration>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReadArgs {
+    /// The IN pipe, e.g. `in0` or `0x82`.
+    #[arg(value_parser = parse_pipe)]
+    pipe: Pipe,
+    #[command(flatten)]
+    limit: Limit,
+    /// Output file; `-` or omitted for stdout.
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Bytes requested per read. Must be a multiple of 1024.
+    #[arg(long, value_parser = parse_size, default_value = "1M")]
+    chunk_size: usize,
+    /// Timeout for each read in milliseconds, instead of the pipe timeout.
+    #[arg(long, value_name = "MS", value_parser = parse_millis)]
+    timeout: Option<Duration>,
+}
+
+#[derive(Debug, Args)]
+pub struct WriteArgs {
+    /// The OUT pipe, e.g. `out0` or `0x02`.
+    #[arg(value_parser = parse_pipe)]
+    pipe: Pipe,
+    /// Input file; `-` for stdin.
+    file: PathBuf,
+    /// Bytes sent per write. Must be a multiple of 1024.
+    #[arg(long, value_parser = parse_size, default_value = "1M")]
+    chunk_size: usize,
+    /// Timeout for each write in milliseconds, instead of the pipe timeout.
+    #[arg(long, value_name = "MS", value_parser = parse_millis)]
+    timeout: Option<Duration>,
+    /// Zero-pad the end of the input to a multiple of the FIFO bus width.
+    #[arg(long)]
+    pad: bool,
+}
+
+pub fn read(cli: &Cli, args: &ReadArgs) -> CliResult {
+    let device = crate::open(cli)?;
+    let granularity = bus_width(&device);
+    let mut buf = device.alloc_buf(args.chunk_size)?;
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) if path.as_os_str() != "-" => Box::new(File::create(path)?),
+        _ => Box::new(io::stdout().lock()),
+    };
+    let pipe_timeout = device.get_timeout(args.pipe)?;
+
+    let start = Instant::now();
+    let deadline = args.limit.seconds.map(|seconds| start + seconds);
+    let mut total = 0;
+    let mut timeouts = 0;
+    loop {
+        let remaining = args.limit.bytes.map(|bytes| bytes - total);
+        if remaining == Some(0) {
+            break;
+        }
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            break;
+        }
+
+        let len = remaining.map_or(buf.capacity(), |remaining| {
+            remaining.min(buf.capacity()).next_multiple_of(granularity)
+        });
+        buf.set_len(len)?;
+        let mut timeout = args.timeout.unwrap_or(pipe_timeout);
+        if let Some(deadline) = deadline {
+            // A zero timeout would wait forever.
+            timeout = timeout.min(deadline - now).max(Duration::from_millis(1));
+        }
+
+        let n = match device.read_timeout(args.pipe, &mut buf, timeout) {
+            Ok(n) => n,
+            Err(e) if deadline.is_some() && matches!(e.kind(), D3xxError::Timeout) => {
+                timeouts += 1;
+                e.bytes_transferred().unwrap_or(0)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let n = remaining.map_or(n, |remaining| n.min(remaining));
+        output.write_all(&buf[..n])?;
+        total += n;
+    }
+    output.flush()?;
+
+    let elapsed = start.elapsed();
+    eprintln!(
+        "read {total} bytes from {} in {elapsed:.2?} ({:.2} MB/s, {timeouts} timeouts)",
+        args.pipe,
+        megabytes_per_second(total as u64, elapsed),
+    );
+    Ok(())
+}
+
+pub fn write(cli: &Cli, args: &WriteArgs) -> CliResult {
+    let device = crate::open(cli)?;
+    let granularity = bus_width(&device);
+    let mut buf = device.alloc_buf(args.chunk_size)?;
+    let mut input: Box<dyn Read> = match args.file.as_os_str() == "-" {
+        true => Box::new(io::stdin().lock()),
+        false => Box::new(File::open(&args.file)?),
+    };
+
+    let start = Instant::now();
+    let mut total = 0;
+    loop {
+        buf.reset_len();
+        let mut n = fill(&mut input, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        if n % granularity != 0 {
+            if !args.pad {
+                return Err(format!(
+                    "input length is not a multiple of the {granularity}-byte bus width; \
+                     use --pad to zero-pad it"
+                )
+                .into());
+            }
+            let padded = n.next_multiple_of(granularity);
+            buf[n..padded].fill(0);
+            n = padded;
+        }
+
+        let written = match args.timeout {
+            Some(timeout) => device.write_timeout(args.pipe, &buf[..n], timeout)?,
+            None => device.write(args.pipe, &buf[..n])?,
+        };
+        total += written;
+        if written < n {
+            return Err(format!("short write: {written} of {n} bytes after {total} bytes").into());
+        }
+    }
+
+    let elapsed = start.elapsed();
+    eprintln!(
+        "wrote {total} bytes to {} in {elapsed:.2?} ({:.2} MB/s)",
+        args.pipe,
+        megabytes_per_second(total as u64, elapsed),
+    );
+    Ok(())
+}
+
+/// The FIFO bus width of the device, or 1 if the chip type is unknown.
+pub fn bus_width(device: &Device) -> usize {
+    device.chip_type().map_or(1, |chip| chip.bus_width())
+}
+
+/// Reads until `buf` is full or the input ends, returning the number of bytes read.
+fn fill(input: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
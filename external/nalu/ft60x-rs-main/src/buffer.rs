@@ -0,0 +1,403 @@
+//! Aligned transfer buffers.
+//!
+//! The D3XX driver transfers data fastest when buffers are page-aligned and sized to
+//! a multiple of the 1024-byte SuperSpeed packet. [`AlignedBuf`] enforces both, and
+//! additionally requires its length to be a multiple of the FIFO bus width of the
+//! chip: 2 bytes for the FT600 and 4 bytes for the FT601.
+//!
+//! [`Device::read`](crate::Device::read) and [`Device::write`](crate::Device::write)
+//! accept any byte slice, including an [`AlignedBuf`], but reject lengths which are
+//! not a multiple of the bus width with [`D3xxError::MisalignedLength`].
+//!
+//! ```no_run
+//! # fn main() -> ft60x_rs::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! use ft60x_rs::Pipe;
+//!
+//! let pool = device.buffer_pool(64 * 1024, 4)?;
+//! let mut buf = pool.try_get().unwrap();
+//! let n = device.read(Pipe::In0, &mut buf)?;
+//! buf.set_len(n)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    alloc::{self, Layout},
+    fmt::Debug,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use crate::{ChipType, D3xxError, Result};
+
+/// Size of a USB 3 SuperSpeed bulk packet. Buffer capacities are a multiple of this.
+pub const PACKET_SIZE: usize = 1024;
+
+/// Alignment of buffer allocations.
+pub const PAGE_SIZE: usize = 4096;
+
+/// A page-aligned, heap-allocated transfer buffer.
+///
+/// The capacity is fixed when the buffer is created and is a multiple of
+/// [`PACKET_SIZE`]. The length, which is what the buffer dereferences to, starts out
+/// equal to the capacity and may be reduced with [`AlignedBuf::set_len`], e.g. to the
+/// number of bytes returned by a read.
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    capacity: usize,
+    granularity: usize,
+}
+
+impl AlignedBuf {
+    /// Allocates a zeroed buffer for the given chip.
+    ///
+    /// # Errors
+    /// [`D3xxError::MisalignedLength`] if `capacity` is zero or not a multiple of
+    /// [`PACKET_SIZE`].
+    pub fn new(chip: ChipType, capacity: usize) -> Result<Self> {
+        Self::with_granularity(capacity, chip.bus_width())
+    }
+
+    pub(crate) fn with_granularity(capacity: usize, granularity: usize) -> Result<Self> {
+        if capacity == 0 || !capacity.is_multiple_of(PACKET_SIZE) {
+            Err(D3xxError::MisalignedLength)?;
+        }
+        let layout = Layout::from_size_align(capacity, PAGE_SIZE)
+            .map_err(|_| D3xxError::InsufficientResources)?;
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).ok_or(D3xxError::InsufficientResources)?;
+        Ok(Self {
+            ptr,
+            len: capacity,
+            capacity,
+            granularity,
+        })
+    }
+
+    /// The size of the allocation.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the length of the buffer.
+    ///
+    /// # Errors
+    /// [`D3xxError::MisalignedLength`] if `len` is not a multiple of the bus width,
+    /// or [`D3xxError::InvalidParameter`] if it exceeds the capacity.
+    pub fn set_len(&mut self, len: usize) -> Result<()> {
+        if len > self.capacity {
+            Err(D3xxError::InvalidParameter)?;
+        }
+        if !len.is_multiple_of(self.granularity) {
+            Err(D3xxError::MisalignedLength)?;
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    /// Restores the length to the full capacity.
+    pub fn reset_len(&mut self) {
+        self.len = self.capacity;
+    }
+
+    /// Sets the length to the number of bytes a transfer returned.
+    pub(crate) fn set_filled(&mut self, len: usize) {
+        self.len = len.min(self.capacity);
+    }
+
+    /// Pointer to the start of the allocation, valid for `capacity` bytes.
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::from_size_align(self.capacity, PAGE_SIZE).unwrap()
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout()) }
+    }
+}
+
+impl Debug for AlignedBuf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AlignedBuf")
+            .field("len", &self.len)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+// The buffer owns its allocation exclusively.
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+/// State shared between a pool and its buffers.
+struct PoolInner {
+    free: Mutex<Vec<AlignedBuf>>,
+    /// Signalled when a buffer is returned, or when the pool is closed.
+    returned: Condvar,
+    closed: AtomicBool,
+}
+
+/// A fixed set of [`AlignedBuf`]s which are reused rather than reallocated.
+///
+/// Buffers are handed out as [`PooledBuf`]s, which return to the pool when dropped.
+/// Cloning the pool produces another handle to the same set of buffers.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+    buffer_size: usize,
+    count: usize,
+}
+
+impl BufferPool {
+    /// Allocates `count` buffers of `buffer_size` bytes for the given chip.
+    ///
+    /// # Errors
+    /// See [`AlignedBuf::new`].
+    pub fn new(chip: ChipType, buffer_size: usize, count: usize) -> Result<Self> {
+        Self::with_granularity(buffer_size, count, chip.bus_width())
+    }
+
+    pub(crate) fn with_granularity(
+        buffer_size: usize,
+        count: usize,
+        granularity: usize,
+    ) -> Result<Self> {
+        let buffers = (0..count)
+            .map(|_| AlignedBuf::with_granularity(buffer_size, granularity))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            inner: Arc::new(PoolInner {
+                free: Mutex::new(buffers),
+                returned: Condvar::new(),
+                closed: AtomicBool::new(false),
+            }),
+            buffer_size,
+            count,
+        })
+    }
+
+    /// The capacity of each buffer.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// The total number of buffers in the pool.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The number of buffers not currently in use.
+    pub fn available(&self) -> usize {
+        self.inner.free.lock().unwrap().len()
+    }
+
+    /// Takes a buffer if one is available. The buffer's length is reset to its capacity.
+    pub fn try_get(&self) -> Option<PooledBuf> {
+        let buf = self.inner.free.lock().unwrap().pop()?;
+        Some(self.wrap(buf))
+    }
+
+    /// Takes a buffer, waiting for one to be returned if none is available.
+    ///
+    /// Returns `None` if the pool is closed.
+    pub fn get(&self) -> Option<PooledBuf> {
+        let mut free = self.inner.free.lock().unwrap();
+        loop {
+            if self.inner.closed.load(Ordering::Relaxed) {
+                return None;
+            }
+            if let Some(buf) = free.pop() {
+                return Some(self.wrap(buf));
+            }
+            free = self.inner.returned.wait(free).unwrap();
+        }
+    }
+
+    /// Closes the pool, waking any thread blocked in [`BufferPool::get`]. Buffers may
+    /// still be returned to a closed pool, but [`BufferPool::get`] returns `None`.
+    pub fn close(&self) {
+        // Lock `free` so that a thread about to wait can't miss the notification.
+        let _free = self.inner.free.lock().unwrap();
+        self.inner.closed.store(true, Ordering::Relaxed);
+        self.inner.returned.notify_all();
+    }
+
+    fn wrap(&self, mut buf: AlignedBuf) -> PooledBuf {
+        buf.reset_len();
+        PooledBuf {
+            buf: Some(buf),
+            pool: self.inner.clone(),
+        }
+    }
+}
+
+impl Debug for BufferPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("buffer_size", &self.buffer_size)
+            .field("count", &self.count)
+            .field("available", &self.available())
+            .finish()
+    }
+}
+
+/// A buffer borrowed from a [`BufferPool`]. Dereferences to an [`AlignedBuf`].
+pub struct PooledBuf {
+    buf: Option<AlignedBuf>,
+    pool: Arc<PoolInner>,
+}
+
+impl Deref for PooledBuf {
+    type Target = AlignedBuf;
+
+    fn deref(&self) -> &AlignedBuf {
+        self.buf.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut AlignedBuf {
+        self.buf.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        if let Some(buf) = self.buf.take() {
+            self.pool.free.lock().unwrap().push(buf);
+            self.pool.returned.notify_one();
+        }
+    }
+}
+
+impl Debug for PooledBuf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn capacity_must_be_whole_packets() {
+        for capacity in [0, 1000, PACKET_SIZE + 4] {
+            let err = AlignedBuf::new(ChipType::Ft601, capacity).unwrap_err();
+            assert!(matches!(err.kind(), D3xxError::MisalignedLength));
+        }
+    }
+
+    #[test]
+    fn page_aligned_and_zeroed() {
+        for capacity in [PACKET_SIZE, 3 * PACKET_SIZE, 64 * 1024] {
+            let mut buf = AlignedBuf::new(ChipType::Ft600, capacity).unwrap();
+            assert_eq!(buf.as_mut_ptr() as usize % PAGE_SIZE, 0);
+            assert_eq!(buf.len(), capacity);
+            assert_eq!(buf.capacity(), capacity);
+            assert!(buf.iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn set_len_checks_granularity() {
+        let mut buf = AlignedBuf::new(ChipType::Ft601, PACKET_SIZE).unwrap();
+        let err = buf.set_len(6).unwrap_err();
+        assert!(matches!(err.kind(), D3xxError::MisalignedLength));
+        let err = buf.set_len(PACKET_SIZE + 4).unwrap_err();
+        assert!(matches!(err.kind(), D3xxError::InvalidParameter));
+        // A failed call leaves the length alone.
+        assert_eq!(buf.len(), PACKET_SIZE);
+
+        buf.set_len(8).unwrap();
+        assert_eq!(buf.len(), 8);
+        buf.set_len(0).unwrap();
+        assert!(buf.is_empty());
+        buf.reset_len();
+        assert_eq!(buf.len(), PACKET_SIZE);
+
+        // An FT600 transfers 16-bit words.
+        let mut buf = AlignedBuf::new(ChipType::Ft600, PACKET_SIZE).unwrap();
+        buf.set_len(6).unwrap();
+        assert!(buf.set_len(7).is_err());
+    }
+
+    #[test]
+    fn try_get_empty_pool() {
+        let pool = BufferPool::new(ChipType::Ft601, PACKET_SIZE, 2).unwrap();
+        let mut first = pool.try_get().unwrap();
+        let _second = pool.try_get().unwrap();
+        assert_eq!(pool.available(), 0);
+        assert!(pool.try_get().is_none());
+
+        // A returned buffer is handed out again at full length.
+        first.set_len(4).unwrap();
+        drop(first);
+        assert_eq!(pool.available(), 1);
+        assert_eq!(pool.try_get().unwrap().len(), PACKET_SIZE);
+        assert_eq!(pool.count(), 2);
+    }
+
+    #[test]
+    fn get_waits_for_a_returned_buffer() {
+        let pool = BufferPool::new(ChipType::Ft601, PACKET_SIZE, 1).unwrap();
+        let held = pool.try_get().unwrap();
+        let waiter = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.get().map(|buf| buf.len()))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+        drop(held);
+        assert_eq!(waiter.join().unwrap(), Some(PACKET_SIZE));
+    }
+
+    #[test]
+    fn close_wakes_blocked_get() {
+        let pool = BufferPool::new(ChipType::Ft601, PACKET_SIZE, 1).unwrap();
+        let held = pool.try_get().unwrap();
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let pool = pool.clone();
+                thread::spawn(move || pool.get().is_none())
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(50));
+        pool.close();
+        for waiter in waiters {
+            assert!(waiter.join().unwrap());
+        }
+
+        // Buffers still return to a closed pool, but are not handed out by `get`.
+        drop(held);
+        assert_eq!(pool.available(), 1);
+        assert!(pool.get().is_none());
+    }
+}
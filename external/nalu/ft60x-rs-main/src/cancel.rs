@@ -0,0 +1,107 @@
+//! Cancellation of blocking transfers from another thread.
+//!
+//! [`Device::read`](crate::Device::read) and [`Device::write`](crate::Device::write)
+//! block until the transfer completes or the pipe timeout expires. A [`CancelHandle`]
+//! releases a blocked transfer immediately by calling `FT_AbortPipe` on its pipe.
+//! The cancelled call returns [`D3xxError::Cancelled`](crate::D3xxError::Cancelled)
+//! rather than the [`OperationAborted`](crate::D3xxError::OperationAborted) status
+//! reported by the library, and the pipe's recovery policy is not applied.
+//!
+//! A cancellation request stays pending until a transfer on the pipe observes it.
+//! If no transfer is in progress when [`CancelHandle::cancel`] is called, the next
+//! transfer on that pipe returns `Cancelled` without touching the device. A transfer
+//! which completes before the abort reaches it returns its data as usual, and the
+//! request then applies to the next transfer.
+//!
+//! [`CancelHandle::cancel_all`] therefore leaves a request pending on every pipe
+//! which had no transfer in progress. Call [`CancelHandle::clear_all`] once the
+//! cancelled work has stopped and before the device is used again, or the next
+//! transfer on each of those pipes fails with `Cancelled`.
+//!
+//! A transfer checks for a pending request just before calling the driver. If the
+//! request arrives between that check and the call, the first abort has nothing to
+//! release; [`CancelHandle::cancel`] then keeps aborting the pipe for a short time
+//! until the transfer returns, so it may block for up to about 100 ms.
+//!
+//! ```no_run
+//! # fn main() -> ft60x_rs::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! use ft60x_rs::Pipe;
+//!
+//! let cancel = device.cancel_handle();
+//! std::thread::spawn(move || {
+//!     // e.g. on Ctrl-C
+//!     cancel.cancel_all()
+//! });
+//! let mut buf = vec![0; 4096];
+//! match device.read(Pipe::In0, &mut buf) {
+//!     Err(e) if e.is_cancelled() => println!("stopped"),
+//!     other => println!("{:?}", other),
+//! }
+//! // Drop the requests left on the pipes which were not being read.
+//! device.cancel_handle().clear_all();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::{Arc, Weak};
+
+use crate::{DeviceHandle, Pipe, Result};
+
+/// Cancels transfers on a device from any thread.
+///
+/// Obtained from [`Device::cancel_handle`](crate::Device::cancel_handle) or from a
+/// split half. The handle does not keep the device open; once the device has been
+/// dropped, cancelling does nothing.
+#[derive(Clone, Debug)]
+pub struct CancelHandle {
+    device: Weak<DeviceHandle>,
+}
+
+impl CancelHandle {
+    pub(crate) fn new(device: &Arc<DeviceHandle>) -> Self {
+        Self {
+            device: Arc::downgrade(device),
+        }
+    }
+
+    /// Cancels the current or next transfer on the given pipe.
+    pub fn cancel(&self, pipe: Pipe) -> Result<()> {
+        match self.device.upgrade() {
+            Some(device) => device.cancel(pipe),
+            None => Ok(()),
+        }
+    }
+
+    /// Cancels the current or next transfer on every pipe.
+    ///
+    /// All pipes are cancelled even if aborting one of them fails; the first error
+    /// is returned. Requests stay pending on idle pipes until a transfer observes
+    /// them or they are [cleared](CancelHandle::clear_all).
+    pub fn cancel_all(&self) -> Result<()> {
+        let mut result = Ok(());
+        for pipe in Pipe::ALL {
+            let r = self.cancel(pipe);
+            if result.is_ok() {
+                result = r;
+            }
+        }
+        result
+    }
+
+    /// Withdraws a pending cancellation request on the given pipe, returning whether
+    /// there was one. A transfer which has already been aborted is not affected.
+    pub fn clear(&self, pipe: Pipe) -> bool {
+        self.device
+            .upgrade()
+            .is_some_and(|device| device.take_cancelled(pipe))
+    }
+
+    /// Withdraws pending cancellation requests on every pipe, e.g. after
+    /// [`cancel_all`](CancelHandle::cancel_all) once the cancelled work has stopped.
+    pub fn clear_all(&self) {
+        for pipe in Pipe::ALL {
+            self.clear(pipe);
+        }
+    }
+}
@@ -0,0 +1,1008 @@
+//! Raw capture files (`.ftcap`).
+//!
+//! A capture records every transfer made on a device, so that what the host saw
+//! during a run can be examined, or replayed, later. [`CaptureWriter`] appends
+//! records as transfers complete and [`CaptureReader`] gives random access to them.
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! use ft60x_rs::{
+//!     capture::{CaptureHeader, CaptureReader, CaptureWriter},
+//!     Pipe,
+//! };
+//!
+//! let mut capture = CaptureWriter::create("run.ftcap", &CaptureHeader::from_device(&device)?)?;
+//! let mut buf = device.alloc_buf(64 * 1024)?;
+//! for _ in 0..100 {
+//!     capture.read(&device, Pipe::In0, &mut buf)?;
+//! }
+//! capture.finish()?;
+//!
+//! let mut capture = CaptureReader::open("run.ftcap")?;
+//! for record in capture.records() {
+//!     let record = record?;
+//!     println!("{:?} {:?}: {} bytes", record.timestamp, record.pipe, record.data.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Format
+//!
+//! Version 1 of the format is laid out as follows. All integers are little-endian.
+//!
+//! ```text
+//! file    = header record* [index trailer]
+//!
+//! header  = magic "FTCAP\r\n\x1a"   8 bytes
+//!           version                 u16     currently 1
+//!           reserved                u16     0
+//!           body length             u32
+//!           body
+//! body    = created                 u64     ns since the Unix epoch
+//!           library version         u32     as returned by FT_GetLibraryVersion
+//!           driver version          u32     as returned by FT_GetDriverVersion
+//!           flags                   u32     device info list entry
+//!           type                    u32
+//!           vendor id               u16
+//!           product id              u16
+//!           location id             u32
+//!           serial number           u16 length + UTF-8
+//!           description             u16 length + UTF-8
+//!           device descriptor       u8 length + USB device descriptor
+//!
+//! record  = kind                    u8      1
+//!           pipe                    u8      endpoint address
+//!           reserved                u16     0
+//!           status                  u32     0 on success, see Record::status
+//!           timestamp               u64     ns from the start of the capture
+//!           wall clock              u64     ns since the Unix epoch
+//!           requested               u32     bytes requested
+//!           length                  u32     bytes transferred
+//!           payload                 length bytes
+//!
+//! index   = kind                    u8      0xff
+//!           reserved                [u8; 3]
+//!           count                   u32
+//!           offsets                 count × u64, file offset of each record
+//! trailer = index offset            u64
+//!           magic "FTCAPIDX"        8 bytes
+//! ```
+//!
+//! Timestamps are taken when the transfer was started. Readers ignore header body
+//! bytes beyond the fields they know, so fields may be appended without changing
+//! the version. The index and trailer are written by [`CaptureWriter::finish`]; if
+//! they are missing, e.g. because the acquisition crashed, [`CaptureReader`] rebuilds
+//! the index by scanning the records and stops at a truncated record.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    ffi::types, D3xxError, Device, DeviceDescriptor, DeviceInfo, Error, Pipe, Result, Version,
+};
+
+/// Magic bytes at the start of a capture file.
+pub const MAGIC: &[u8; 8] = b"FTCAP\r\n\x1a";
+/// Magic bytes at the end of a capture file which has an index.
+pub const INDEX_MAGIC: &[u8; 8] = b"FTCAPIDX";
+/// The format version written by this crate.
+pub const VERSION: u16 = 1;
+
+/// [`Record::status`] of a transfer cancelled through a
+/// [`CancelHandle`](crate::CancelHandle).
+pub const STATUS_CANCELLED: u32 = 0xffff_fffe;
+/// [`Record::status`] of a transfer which failed with an error which has no D3XX
+/// status code.
+pub const STATUS_OTHER: u32 = 0xffff_ffff;
+
+const RECORD_TRANSFER: u8 = 1;
+const RECORD_INDEX: u8 = 0xff;
+const RECORD_HEADER_LEN: u64 = 32;
+const TRAILER_LEN: u64 = 16;
+
+/// The device a capture was made from, stored at the start of the file.
+#[derive(Debug, Clone)]
+pub struct CaptureHeader {
+    /// When the capture was started.
+    pub created: SystemTime,
+    /// The device list entry of the device.
+    pub device_info: DeviceInfo,
+    /// The USB device descriptor of the device.
+    pub descriptor: DeviceDescriptor,
+    /// The version of the D3XX library.
+    pub library_version: Version,
+    /// The version of the D3XX driver.
+    pub driver_version: Version,
+}
+
+impl CaptureHeader {
+    /// Collects the header for a capture of `device`, starting now.
+    pub fn from_device(device: &Device) -> Result<Self> {
+        Ok(Self {
+            created: SystemTime::now(),
+            device_info: device.info()?,
+            descriptor: device.device_descriptor()?,
+            library_version: crate::d3xx_version(),
+            driver_version: device.driver_version()?,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let info = &self.device_info;
+        let mut body = Vec::new();
+        body.extend_from_slice(&unix_nanos(self.created).to_le_bytes());
+        body.extend_from_slice(&version_to_u32(&self.library_version).to_le_bytes());
+        body.extend_from_slice(&version_to_u32(&self.driver_version).to_le_bytes());
+        body.extend_from_slice(&info.flags.to_le_bytes());
+        body.extend_from_slice(&info.type_.to_le_bytes());
+        body.extend_from_slice(&info.vendor_id.to_le_bytes());
+        body.extend_from_slice(&info.product_id.to_le_bytes());
+        body.extend_from_slice(&info.location_identifier.to_le_bytes());
+        for s in [&info.serial_number, &info.description] {
+            let bytes = &s.as_bytes()[..s.len().min(u16::MAX as usize)];
+            body.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+            body.extend_from_slice(bytes);
+        }
+        let descriptor = descriptor_to_bytes(&self.descriptor);
+        body.push(descriptor.len() as u8);
+        body.extend_from_slice(&descriptor);
+        body
+    }
+
+    fn decode(body: &[u8]) -> io::Result<Self> {
+        let mut body = Decoder(body);
+        let created = UNIX_EPOCH + Duration::from_nanos(body.u64()?);
+        let library_version = Version::new(body.u32()?);
+        let driver_version = Version::new(body.u32()?);
+        let flags = body.u32()?;
+        let type_ = body.u32()?;
+        let vendor_id = body.u16()?;
+        let product_id = body.u16()?;
+        let location_identifier = body.u32()?;
+        let serial_number = body.string()?;
+        let description = body.string()?;
+        let len = body.u8()? as usize;
+        let descriptor = descriptor_from_bytes(body.bytes(len)?);
+        Ok(Self {
+            created,
+            device_info: DeviceInfo {
+                index: 0,
+                flags,
+                type_,
+                vendor_id,
+                product_id,
+                location_identifier,
+                serial_number,
+                description,
+                is_open: false,
+                handle: 0,
+            },
+            descriptor,
+            library_version,
+            driver_version,
+        })
+    }
+}
+
+/// One transfer in a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The pipe the transfer was made on.
+    pub pipe: Pipe,
+    /// Zero if the transfer succeeded, the D3XX status code if it failed, or one of
+    /// [`STATUS_CANCELLED`] and [`STATUS_OTHER`].
+    pub status: u32,
+    /// When the transfer was started, relative to the start of the capture.
+    pub timestamp: Duration,
+    /// When the transfer was started, by the wall clock.
+    pub wall_time: SystemTime,
+    /// The number of bytes requested.
+    pub requested: usize,
+    /// The bytes transferred. For a failed transfer, those transferred before it
+    /// failed.
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    /// The error the transfer failed with, or `None` if it succeeded.
+    pub fn error(&self) -> Option<D3xxError> {
+        match self.status {
+            0 => None,
+            status @ 1..=32 => Some(D3xxError::from(status)),
+            STATUS_CANCELLED => Some(D3xxError::Cancelled),
+            _ => Some(D3xxError::OtherError),
+        }
+    }
+
+    /// The status to record for the outcome of a transfer.
+    fn status_of(result: std::result::Result<usize, &Error>) -> u32 {
+        match result {
+            Ok(_) => 0,
+            Err(e) => match e.kind() {
+                D3xxError::Cancelled => STATUS_CANCELLED,
+                kind => kind.error_code().unwrap_or(STATUS_OTHER),
+            },
+        }
+    }
+}
+
+/// Writes a capture file record by record.
+///
+/// Records are written as soon as they are added, so a capture which is not
+/// [finished](CaptureWriter::finish) can still be read back.
+pub struct CaptureWriter<W: Write> {
+    inner: W,
+    epoch: Instant,
+    position: u64,
+    index: Vec<u64>,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    /// Creates a capture file at `path`, replacing any existing file.
+    pub fn create(path: impl AsRef<Path>, header: &CaptureHeader) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Starts a capture by writing the header to `inner`. Timestamps are measured
+    /// from now.
+    pub fn new(mut inner: W, header: &CaptureHeader) -> io::Result<Self> {
+        let body = header.encode();
+        inner.write_all(MAGIC)?;
+        inner.write_all(&VERSION.to_le_bytes())?;
+        inner.write_all(&0u16.to_le_bytes())?;
+        inner.write_all(&(body.len() as u32).to_le_bytes())?;
+        inner.write_all(&body)?;
+        Ok(Self {
+            inner,
+            epoch: Instant::now(),
+            position: 16 + body.len() as u64,
+            index: Vec::new(),
+        })
+    }
+
+    /// The instant record timestamps are measured from.
+    pub fn epoch(&self) -> Instant {
+        self.epoch
+    }
+
+    /// The number of records written.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether no records have been written.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Reads from `pipe` on `device` and records the transfer, whether or not it
+    /// succeeds. See [`Device::read`].
+    pub fn read(&mut self, device: &Device, pipe: Pipe, buf: &mut [u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let result = device.read(pipe, buf);
+        let transferred = transferred(&result);
+        self.record(
+            pipe,
+            start,
+            buf.len(),
+            result.as_ref().copied(),
+            &buf[..transferred],
+        )?;
+        Ok(result?)
+    }
+
+    /// Writes to `pipe` on `device` and records the transfer, whether or not it
+    /// succeeds. See [`Device::write`].
+    pub fn write(&mut self, device: &Device, pipe: Pipe, buf: &[u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let result = device.write(pipe, buf);
+        let transferred = transferred(&result);
+        self.record(
+            pipe,
+            start,
+            buf.len(),
+            result.as_ref().copied(),
+            &buf[..transferred],
+        )?;
+        Ok(result?)
+    }
+
+    /// Records a transfer made some other way, e.g. through a
+    /// [`PipeReader`](crate::PipeReader) or a [`Stream`](crate::Stream).
+    ///
+    /// `start` is when the transfer was started, `requested` the number of bytes
+    /// requested and `data` the bytes transferred.
+    pub fn record(
+        &mut self,
+        pipe: Pipe,
+        start: Instant,
+        requested: usize,
+        result: std::result::Result<usize, &Error>,
+        data: &[u8],
+    ) -> io::Result<()> {
+        self.append(&RecordRef {
+            pipe,
+            status: Record::status_of(result),
+            timestamp: start.saturating_duration_since(self.epoch),
+            wall_time: SystemTime::now()
+                .checked_sub(start.elapsed())
+                .unwrap_or(UNIX_EPOCH),
+            requested,
+            data,
+        })
+    }
+
+    /// Appends a record as it is, e.g. when copying records between captures.
+    pub fn append_record(&mut self, record: &Record) -> io::Result<()> {
+        self.append(&RecordRef {
+            pipe: record.pipe,
+            status: record.status,
+            timestamp: record.timestamp,
+            wall_time: record.wall_time,
+            requested: record.requested,
+            data: &record.data,
+        })
+    }
+
+    fn append(&mut self, record: &RecordRef) -> io::Result<()> {
+        let length = u32::try_from(record.data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too large"))?;
+        let mut header = [0; RECORD_HEADER_LEN as usize];
+        header[0] = RECORD_TRANSFER;
+        header[1] = record.pipe as u8;
+        header[4..8].copy_from_slice(&record.status.to_le_bytes());
+        header[8..16].copy_from_slice(&duration_nanos(record.timestamp).to_le_bytes());
+        header[16..24].copy_from_slice(&unix_nanos(record.wall_time).to_le_bytes());
+        header[24..28].copy_from_slice(&saturating_u32(record.requested).to_le_bytes());
+        header[28..32].copy_from_slice(&length.to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(record.data)?;
+        self.index.push(self.position);
+        self.position += RECORD_HEADER_LEN + u64::from(length);
+        Ok(())
+    }
+
+    /// Flushes buffered records to the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Writes the index and trailer, and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let count = u32::try_from(self.index.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many records"))?;
+        self.inner.write_all(&[RECORD_INDEX, 0, 0, 0])?;
+        self.inner.write_all(&count.to_le_bytes())?;
+        for offset in &self.index {
+            self.inner.write_all(&offset.to_le_bytes())?;
+        }
+        self.inner.write_all(&self.position.to_le_bytes())?;
+        self.inner.write_all(INDEX_MAGIC)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> std::fmt::Debug for CaptureWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CaptureWriter")
+            .field("records", &self.index.len())
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+/// A record borrowed from the caller, to avoid copying payloads when writing.
+struct RecordRef<'a> {
+    pipe: Pipe,
+    status: u32,
+    timestamp: Duration,
+    wall_time: SystemTime,
+    requested: usize,
+    data: &'a [u8],
+}
+
+/// The fixed-size part of a record.
+struct RecordHeader {
+    pipe: Pipe,
+    status: u32,
+    timestamp: Duration,
+    wall_time: SystemTime,
+    requested: usize,
+    length: u32,
+}
+
+/// Reads records from a capture file in any order.
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// use std::{io::Cursor, time::{Duration, SystemTime}};
+/// use ft60x_rs::{capture::Record, CaptureHeader, CaptureReader, CaptureWriter, Pipe};
+///
+/// let header = CaptureHeader {
+///     created: SystemTime::now(),
+///     device_info: Default::default(),
+///     descriptor: Default::default(),
+///     library_version: Default::default(),
+///     driver_version: Default::default(),
+/// };
+/// let mut writer = CaptureWriter::new(Vec::new(), &header)?;
+/// for i in 0..3 {
+///     writer.append_record(&Record {
+///         pipe: Pipe::In0,
+///         status: 0,
+///         timestamp: Duration::from_millis(i),
+///         wall_time: SystemTime::now(),
+///         requested: 4,
+///         data: vec![i as u8; 4],
+///     })?;
+/// }
+/// let mut file = writer.finish()?;
+///
+/// let mut reader = CaptureReader::new(Cursor::new(&file))?;
+/// assert_eq!(reader.len(), 3);
+/// assert_eq!(reader.record(2)?.data, [2; 4]);
+/// assert_eq!(reader.position_at(Duration::from_micros(500))?, 1);
+///
+/// // Without the index, e.g. after a crash, the records are scanned.
+/// file.truncate(file.len() - 60); // the index, trailer and part of the last record
+/// let reader = CaptureReader::new(Cursor::new(&file))?;
+/// assert!(!reader.is_complete());
+/// assert_eq!(reader.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+pub struct CaptureReader<R> {
+    inner: R,
+    header: CaptureHeader,
+    version: u16,
+    index: Vec<u64>,
+    complete: bool,
+    /// The length of the file when it was opened, which bounds the lengths read
+    /// from it.
+    len: u64,
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Opens the capture file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> CaptureReader<R> {
+    /// Reads the header and index of a capture.
+    ///
+    /// # Errors
+    /// [`io::ErrorKind::InvalidData`] if `inner` is not a capture, was written by
+    /// a newer, incompatible version of the format, or its header is truncated.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let len = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(0))?;
+        let mut fixed = [0; 16];
+        inner.read_exact(&mut fixed)?;
+        if &fixed[..8] != MAGIC {
+            return Err(invalid_data("not a capture file"));
+        }
+        let version = u16::from_le_bytes([fixed[8], fixed[9]]);
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported capture version {version}"
+            )));
+        }
+        let body_len = u32::from_le_bytes(fixed[12..16].try_into().unwrap());
+        let first_record = 16 + u64::from(body_len);
+        if first_record > len {
+            return Err(invalid_data("truncated capture header"));
+        }
+        let mut body = vec![0; body_len as usize];
+        inner.read_exact(&mut body)?;
+        let header = CaptureHeader::decode(&body)?;
+
+        let mut reader = Self {
+            inner,
+            header,
+            version,
+            index: Vec::new(),
+            complete: true,
+            len,
+        };
+        // A missing or damaged index is rebuilt from the records.
+        if !reader.read_index().unwrap_or(false) {
+            reader.complete = false;
+            reader.scan(first_record)?;
+        }
+        Ok(reader)
+    }
+
+    /// The header of the capture.
+    pub fn header(&self) -> &CaptureHeader {
+        &self.header
+    }
+
+    /// The format version of the file.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// The number of records.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether the capture has no records.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Whether the capture was [finished](CaptureWriter::finish). If not, the index
+    /// was rebuilt by scanning the file, and any truncated last record is ignored.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Reads the record at `index`.
+    ///
+    /// # Errors
+    /// [`io::ErrorKind::InvalidData`] if the record is corrupt or extends past the
+    /// end of the file.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn record(&mut self, index: usize) -> io::Result<Record> {
+        let header = self.record_header(index)?;
+        let payload = self.index[index] + RECORD_HEADER_LEN;
+        if u64::from(header.length) > self.len.saturating_sub(payload) {
+            return Err(invalid_data(format!("truncated record {index}")));
+        }
+        let mut data = vec![0; header.length as usize];
+        self.inner.read_exact(&mut data)?;
+        Ok(Record {
+            pipe: header.pipe,
+            status: header.status,
+            timestamp: header.timestamp,
+            wall_time: header.wall_time,
+            requested: header.requested,
+            data,
+        })
+    }
+
+    /// The timestamp of the record at `index`, without reading its payload.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn timestamp(&mut self, index: usize) -> io::Result<Duration> {
+        Ok(self.record_header(index)?.timestamp)
+    }
+
+    /// The pipe of the record at `index`, without reading its payload.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn pipe(&mut self, index: usize) -> io::Result<Pipe> {
+        Ok(self.record_header(index)?.pipe)
+    }
+
+    /// The index of the first record with a timestamp at or after `timestamp`, or
+    /// [`len`](CaptureReader::len) if there is none.
+    pub fn position_at(&mut self, timestamp: Duration) -> io::Result<usize> {
+        let (mut low, mut high) = (0, self.index.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.timestamp(mid)? < timestamp {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Iterates over the records in order.
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records {
+            reader: self,
+            next: 0,
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn record_header(&mut self, index: usize) -> io::Result<RecordHeader> {
+        let offset = self.index[index];
+        self.inner.seek(SeekFrom::Start(offset))?;
+        read_record_header(&mut self.inner)?
+            .ok_or_else(|| invalid_data(format!("no record at offset {offset}")))
+    }
+
+    /// Reads the index from the end of the file. Returns `false` if there is none.
+    fn read_index(&mut self) -> io::Result<bool> {
+        let len = self.len;
+        if len < TRAILER_LEN {
+            return Ok(false);
+        }
+        self.inner.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        let mut trailer = [0; TRAILER_LEN as usize];
+        self.inner.read_exact(&mut trailer)?;
+        if &trailer[8..] != INDEX_MAGIC {
+            return Ok(false);
+        }
+        let index_offset = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        self.inner.seek(SeekFrom::Start(index_offset))?;
+        let mut fixed = [0; 8];
+        self.inner.read_exact(&mut fixed)?;
+        if fixed[0] != RECORD_INDEX {
+            return Err(invalid_data("corrupt capture index"));
+        }
+        let count = u32::from_le_bytes(fixed[4..].try_into().unwrap()) as u64;
+        if index_offset.checked_add(8 + count * 8 + TRAILER_LEN) != Some(len) {
+            return Err(invalid_data("corrupt capture index"));
+        }
+        let mut offsets = vec![0; count as usize * 8];
+        self.inner.read_exact(&mut offsets)?;
+        self.index = offsets
+            .chunks_exact(8)
+            .map(|offset| u64::from_le_bytes(offset.try_into().unwrap()))
+            .collect();
+        Ok(true)
+    }
+
+    /// Builds the index by walking the records from `offset`.
+    fn scan(&mut self, mut offset: u64) -> io::Result<()> {
+        let len = self.len;
+        self.index.clear();
+        loop {
+            self.inner.seek(SeekFrom::Start(offset))?;
+            let header = match read_record_header(&mut self.inner) {
+                Ok(Some(header)) => header,
+                // The end of the records, or a truncated record header.
+                Ok(None) => break,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            let next = offset + RECORD_HEADER_LEN + u64::from(header.length);
+            if next > len {
+                break;
+            }
+            self.index.push(offset);
+            offset = next;
+        }
+        Ok(())
+    }
+}
+
+impl<R> std::fmt::Debug for CaptureReader<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CaptureReader")
+            .field("header", &self.header)
+            .field("version", &self.version)
+            .field("records", &self.index.len())
+            .field("complete", &self.complete)
+            .finish()
+    }
+}
+
+/// Iterator over the records of a capture, created by [`CaptureReader::records`].
+pub struct Records<'a, R> {
+    reader: &'a mut CaptureReader<R>,
+    next: usize,
+}
+
+impl<R: Read + Seek> Iterator for Records<'_, R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.reader.len() {
+            return None;
+        }
+        self.next += 1;
+        Some(self.reader.record(self.next - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.reader.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Reads a record header. Returns `None` at the index, which follows the last record.
+fn read_record_header(reader: &mut impl Read) -> io::Result<Option<RecordHeader>> {
+    let mut header = [0; RECORD_HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    match header[0] {
+        RECORD_TRANSFER => {}
+        RECORD_INDEX => return Ok(None),
+        kind => return Err(invalid_data(format!("unknown record kind {kind:#04x}"))),
+    }
+    let pipe = Pipe::ALL
+        .into_iter()
+        .find(|&pipe| pipe as u8 == header[1])
+        .ok_or_else(|| invalid_data(format!("invalid pipe {:#04x}", header[1])))?;
+    let u32_at = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+    let u64_at = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+    Ok(Some(RecordHeader {
+        pipe,
+        status: u32_at(4),
+        timestamp: Duration::from_nanos(u64_at(8)),
+        wall_time: UNIX_EPOCH + Duration::from_nanos(u64_at(16)),
+        requested: u32_at(24) as usize,
+        length: u32_at(28),
+    }))
+}
+
+/// Number of bytes transferred by a call which may have failed part way.
+fn transferred(result: &Result<usize>) -> usize {
+    match result {
+        Ok(n) => *n,
+        Err(e) => e.bytes_transferred().unwrap_or(0),
+    }
+}
+
+/// Reads fields from a header body.
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid_data("truncated capture header"));
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec())
+            .map_err(|_| invalid_data("invalid UTF-8 in capture header"))
+    }
+}
+
+/// The descriptor in USB wire format.
+fn descriptor_to_bytes(descriptor: &DeviceDescriptor) -> [u8; 18] {
+    let d = &descriptor.inner;
+    let mut bytes = [0; 18];
+    bytes[0] = d.bLength;
+    bytes[1] = d.bDescriptorType;
+    bytes[2..4].copy_from_slice(&d.bcdUSB.to_le_bytes());
+    bytes[4] = d.bDeviceClass;
+    bytes[5] = d.bDeviceSubClass;
+    bytes[6] = d.bDeviceProtocol;
+    bytes[7] = d.bMaxPacketSize0;
+    bytes[8..10].copy_from_slice(&d.idVendor.to_le_bytes());
+    bytes[10..12].copy_from_slice(&d.idProduct.to_le_bytes());
+    bytes[12..14].copy_from_slice(&d.bcdDevice.to_le_bytes());
+    bytes[14] = d.iManufacturer;
+    bytes[15] = d.iProduct;
+    bytes[16] = d.iSerialNumber;
+    bytes[17] = d.bNumConfigurations;
+    bytes
+}
+
+/// Parses a descriptor in USB wire format. Missing trailing fields are zero.
+fn descriptor_from_bytes(bytes: &[u8]) -> DeviceDescriptor {
+    let mut padded = [0; 18];
+    let n = bytes.len().min(18);
+    padded[..n].copy_from_slice(&bytes[..n]);
+    let b = padded;
+    DeviceDescriptor {
+        inner: types::FT_DEVICE_DESCRIPTOR {
+            bLength: b[0],
+            bDescriptorType: b[1],
+            bcdUSB: u16::from_le_bytes([b[2], b[3]]),
+            bDeviceClass: b[4],
+            bDeviceSubClass: b[5],
+            bDeviceProtocol: b[6],
+            bMaxPacketSize0: b[7],
+            idVendor: u16::from_le_bytes([b[8], b[9]]),
+            idProduct: u16::from_le_bytes([b[10], b[11]]),
+            bcdDevice: u16::from_le_bytes([b[12], b[13]]),
+            iManufacturer: b[14],
+            iProduct: b[15],
+            iSerialNumber: b[16],
+            bNumConfigurations: b[17],
+        },
+    }
+}
+
+fn version_to_u32(version: &Version) -> u32 {
+    u32::from_be_bytes([version.major, version.minor, version.svn, version.build])
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, duration_nanos)
+}
+
+fn duration_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+fn saturating_u32(n: usize) -> u32 {
+    n.try_into().unwrap_or(u32::MAX)
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn header() -> CaptureHeader {
+        let device_info = DeviceInfo {
+            flags: 4,
+            type_: 600,
+            vendor_id: 0x0403,
+            product_id: 0x601f,
+            location_identifier: 17,
+            serial_number: "000000000001".to_owned(),
+            description: "FTDI SuperSpeed-FIFO Bridge".to_owned(),
+            ..Default::default()
+        };
+        let mut descriptor = DeviceDescriptor::default();
+        descriptor.inner.bLength = 18;
+        descriptor.inner.bDescriptorType = 1;
+        descriptor.inner.bcdUSB = 0x0310;
+        descriptor.inner.idVendor = 0x0403;
+        descriptor.inner.idProduct = 0x601f;
+        descriptor.inner.bNumConfigurations = 1;
+        CaptureHeader {
+            created: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            device_info,
+            descriptor,
+            library_version: Version::new(0x0103_0004),
+            driver_version: Version::new(0x0103_0002),
+        }
+    }
+
+    fn records() -> Vec<Record> {
+        (0..5u8)
+            .map(|i| Record {
+                pipe: if i % 2 == 0 { Pipe::In0 } else { Pipe::Out1 },
+                status: if i == 3 { STATUS_CANCELLED } else { 0 },
+                timestamp: Duration::from_millis(u64::from(i) * 10),
+                wall_time: UNIX_EPOCH + Duration::from_secs(1_700_000_000 + u64::from(i)),
+                requested: 64,
+                data: vec![i; usize::from(i) * 16],
+            })
+            .collect()
+    }
+
+    /// A capture of [`records`], finished if `finish` is set.
+    fn capture(finish: bool) -> Vec<u8> {
+        let mut writer = CaptureWriter::new(Vec::new(), &header()).unwrap();
+        for record in records() {
+            writer.append_record(&record).unwrap();
+        }
+        assert_eq!(writer.len(), 5);
+        if finish {
+            writer.finish().unwrap()
+        } else {
+            writer.inner
+        }
+    }
+
+    /// Whether `file` is complete, and its records.
+    fn read_all(file: &[u8]) -> io::Result<(bool, Vec<Record>)> {
+        let mut reader = CaptureReader::new(Cursor::new(file))?;
+        let records = reader.records().collect::<io::Result<_>>()?;
+        Ok((reader.is_complete(), records))
+    }
+
+    #[test]
+    fn round_trip() {
+        let file = capture(true);
+        let mut reader = CaptureReader::new(Cursor::new(&file)).unwrap();
+        let read = reader.records().collect::<io::Result<Vec<_>>>().unwrap();
+        assert!(reader.is_complete());
+        assert_eq!(reader.version(), VERSION);
+        assert_eq!(read, records());
+        assert!(matches!(read[3].error(), Some(D3xxError::Cancelled)));
+        assert!(read[0].error().is_none());
+
+        let (written, header) = (header(), reader.header());
+        assert_eq!(header.created, written.created);
+        assert_eq!(header.device_info, written.device_info);
+        assert_eq!(
+            descriptor_to_bytes(&header.descriptor),
+            descriptor_to_bytes(&written.descriptor)
+        );
+        assert_eq!(header.library_version, written.library_version);
+        assert_eq!(header.driver_version, written.driver_version);
+
+        assert_eq!(reader.pipe(1).unwrap(), Pipe::Out1);
+        assert_eq!(reader.timestamp(4).unwrap(), Duration::from_millis(40));
+        assert_eq!(reader.position_at(Duration::from_millis(15)).unwrap(), 2);
+        assert_eq!(reader.position_at(Duration::from_secs(1)).unwrap(), 5);
+    }
+
+    #[test]
+    fn unfinished_capture_is_scanned() {
+        let file = capture(false);
+        let (complete, read) = read_all(&file).unwrap();
+        assert!(!complete);
+        assert_eq!(read, records());
+    }
+
+    #[test]
+    fn truncated_record_is_ignored() {
+        let file = capture(false);
+        // Part of the last record's payload, and then part of its header.
+        for cut in [10, 80] {
+            let (complete, read) = read_all(&file[..file.len() - cut]).unwrap();
+            assert!(!complete);
+            assert_eq!(read, records()[..4]);
+        }
+    }
+
+    #[test]
+    fn truncated_header() {
+        let file = capture(true);
+        for len in [0, 8, 20] {
+            assert!(CaptureReader::new(Cursor::new(&file[..len])).is_err());
+        }
+
+        // A body length beyond the end of the file is rejected before it is read.
+        let mut file = file[..16].to_vec();
+        file[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = CaptureReader::new(Cursor::new(&file)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn corrupt_record_length() {
+        // An indexed record whose length reaches past the end of the file.
+        let mut file = capture(true);
+        let mut reader = CaptureReader::new(Cursor::new(&file)).unwrap();
+        let offset = reader.index[2] as usize;
+        file[offset + 28..offset + 32].copy_from_slice(&u32::MAX.to_le_bytes());
+        reader = CaptureReader::new(Cursor::new(&file)).unwrap();
+        assert!(reader.is_complete());
+        assert_eq!(reader.record(1).unwrap(), records()[1]);
+        let err = reader.record(2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn not_a_capture() {
+        let mut file = capture(true);
+        file[0] = b'X';
+        let err = CaptureReader::new(Cursor::new(&file)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut file = capture(true);
+        file[8..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let err = CaptureReader::new(Cursor::new(&file)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt::{Debug, Display};
 
 use crate::ffi::types::FT_STATUS;
+use crate::Pipe;
 
 /// Error type corresponding to possible [`FT_STATUS`] errors
 #[derive(thiserror::Error, Debug)]
@@ -43,9 +44,26 @@ pub enum D3xxError {
 
     // Errors not defined by the D3XX library
     LibraryAccessFailed(#[from] libloading::Error),
-    UnpackingFailed(#[from] std::io::Error),
+    /// The bundled D3XX library could not be written to a temporary directory.
+    UnpackingFailed(#[source] std::io::Error),
     LibraryAlreadyLoaded,
     LibraryNotLoaded,
+    /// The transfer was cancelled through a [`CancelHandle`](crate::CancelHandle).
+    Cancelled,
+    /// The device is not connected using a USB3 cable, but
+    /// [`OpenOptions::require_usb3`](crate::OpenOptions::require_usb3) was set.
+    Usb3Required,
+    /// A setting did not read back with the value it was set to.
+    SettingNotApplied,
+    /// A buffer length is not a multiple of the FIFO bus width, or a buffer capacity
+    /// is not a multiple of the USB packet size. See [`AlignedBuf`](crate::AlignedBuf).
+    MisalignedLength,
+    /// A write to a [`ReplayDevice`](crate::ReplayDevice) did not match the write
+    /// recorded in the capture.
+    ReplayMismatch,
+    /// An I/O operation outside the D3XX library failed, such as accessing a file or
+    /// spawning a thread.
+    Io(#[from] std::io::Error),
 }
 
 impl D3xxError {
@@ -86,13 +104,123 @@ impl D3xxError {
             _ => None,
         }
     }
+
+    /// Check if the operation which produced this error may succeed if it is
+    /// simply attempted again, without resetting the pipe or the device.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            D3xxError::Timeout
+                | D3xxError::Busy
+                | D3xxError::IoPending
+                | D3xxError::IoIncomplete
+                | D3xxError::InsufficientResources
+                | D3xxError::NoSystemResources
+        )
+    }
+
+    /// Check if the error leaves the device unusable. The device must be
+    /// re-opened (or its port power cycled) before it can be used again.
+    ///
+    /// Errors which are neither retryable nor fatal usually require the affected
+    /// pipe to be aborted or flushed before continuing.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            D3xxError::InvalidHandle
+                | D3xxError::DeviceNotFound
+                | D3xxError::DeviceNotOpened
+                | D3xxError::DeviceNotConnected
+                | D3xxError::IncorrectDevicePath
+                | D3xxError::LibraryAccessFailed(_)
+                | D3xxError::UnpackingFailed(_)
+                | D3xxError::LibraryNotLoaded
+        )
+    }
+
+    /// The closest matching [`std::io::ErrorKind`] for this error.
+    pub fn io_error_kind(&self) -> std::io::ErrorKind {
+        use std::io::ErrorKind;
+        match self {
+            D3xxError::Timeout => ErrorKind::TimedOut,
+            D3xxError::DeviceNotFound | D3xxError::IncorrectDevicePath => ErrorKind::NotFound,
+            D3xxError::DeviceNotConnected => ErrorKind::NotConnected,
+            D3xxError::InvalidHandle
+            | D3xxError::InvalidParameter
+            | D3xxError::InvalidBaudRate
+            | D3xxError::InvalidArgs
+            | D3xxError::ReservedPipe
+            | D3xxError::InvalidControlRequestDirection
+            | D3xxError::InvalidControLRequestType
+            | D3xxError::MisalignedLength => ErrorKind::InvalidInput,
+            D3xxError::ReplayMismatch => ErrorKind::InvalidData,
+            D3xxError::NotSupported => ErrorKind::Unsupported,
+            D3xxError::OperationAborted | D3xxError::Cancelled => ErrorKind::Interrupted,
+            D3xxError::IoPending | D3xxError::IoIncomplete | D3xxError::Busy => {
+                ErrorKind::WouldBlock
+            }
+            D3xxError::HandleEof => ErrorKind::UnexpectedEof,
+            D3xxError::InsufficientResources | D3xxError::NoSystemResources => {
+                ErrorKind::OutOfMemory
+            }
+            D3xxError::UnpackingFailed(e) | D3xxError::Io(e) => e.kind(),
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// The name of the variant, e.g. `"DeviceNotFound"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::InvalidHandle => "InvalidHandle",
+            Self::DeviceNotFound => "DeviceNotFound",
+            Self::DeviceNotOpened => "DeviceNotOpened",
+            Self::IoError => "IoError",
+            Self::InsufficientResources => "InsufficientResources",
+            Self::InvalidParameter => "InvalidParameter",
+            Self::InvalidBaudRate => "InvalidBaudRate",
+            Self::DeviceNotOpenedForErase => "DeviceNotOpenedForErase",
+            Self::DeviceNotOpenedForWrite => "DeviceNotOpenedForWrite",
+            Self::FailedToWriteDevice => "FailedToWriteDevice",
+            Self::EEPROMReadFailed => "EEPROMReadFailed",
+            Self::EEPROMWriteFailed => "EEPROMWriteFailed",
+            Self::EEPROMEraseFailed => "EEPROMEraseFailed",
+            Self::EEPROMNotPresent => "EEPROMNotPresent",
+            Self::EEPROMNotProgrammed => "EEPROMNotProgrammed",
+            Self::InvalidArgs => "InvalidArgs",
+            Self::NotSupported => "NotSupported",
+            Self::NoMoreItems => "NoMoreItems",
+            Self::Timeout => "Timeout",
+            Self::OperationAborted => "OperationAborted",
+            Self::ReservedPipe => "ReservedPipe",
+            Self::InvalidControlRequestDirection => "InvalidControlRequestDirection",
+            Self::InvalidControLRequestType => "InvalidControLRequestType",
+            Self::IoPending => "IoPending",
+            Self::IoIncomplete => "IoIncomplete",
+            Self::HandleEof => "HandleEof",
+            Self::Busy => "Busy",
+            Self::NoSystemResources => "NoSystemResources",
+            Self::DeviceListNotReady => "DeviceListNotReady",
+            Self::DeviceNotConnected => "DeviceNotConnected",
+            Self::IncorrectDevicePath => "IncorrectDevicePath",
+            Self::OtherError => "OtherError",
+            Self::LibraryAccessFailed(_) => "LibraryAccessFailed",
+            Self::UnpackingFailed(_) => "UnpackingFailed",
+            Self::LibraryAlreadyLoaded => "LibraryAlreadyLoaded",
+            Self::LibraryNotLoaded => "LibraryNotLoaded",
+            Self::Cancelled => "Cancelled",
+            Self::Usb3Required => "Usb3Required",
+            Self::SettingNotApplied => "SettingNotApplied",
+            Self::MisalignedLength => "MisalignedLength",
+            Self::ReplayMismatch => "ReplayMismatch",
+            Self::Io(_) => "Io",
+        }
+    }
 }
 
 impl From<FT_STATUS> for D3xxError {
     /// Convert from a raw status value to a `D3xxError`.
     ///
-    /// # Panics
-    /// Panics if the given value is not a valid status value.
+    /// Values not defined by the D3XX library are converted to `OtherError`.
     fn from(id: FT_STATUS) -> Self {
         match id {
             1 => D3xxError::InvalidHandle,
@@ -126,8 +254,7 @@ impl From<FT_STATUS> for D3xxError {
             29 => D3xxError::DeviceListNotReady,
             30 => D3xxError::DeviceNotConnected,
             31 => D3xxError::IncorrectDevicePath,
-            32 => D3xxError::OtherError,
-            _ => panic!("Unknown value {}", id),
+            _ => D3xxError::OtherError,
         }
     }
 }
@@ -135,43 +262,10 @@ impl From<FT_STATUS> for D3xxError {
 impl Display for D3xxError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = match self {
-            Self::InvalidHandle => "InvalidHandle".to_owned(),
-            Self::DeviceNotFound => "DeviceNotFound".to_owned(),
-            Self::DeviceNotOpened => "DeviceNotOpened".to_owned(),
-            Self::IoError => "IoError".to_owned(),
-            Self::InsufficientResources => "InsufficientResources".to_owned(),
-            Self::InvalidParameter => "InvalidParameter".to_owned(),
-            Self::InvalidBaudRate => "InvalidBaudRate".to_owned(),
-            Self::DeviceNotOpenedForErase => "DeviceNotOpenedForErase".to_owned(),
-            Self::DeviceNotOpenedForWrite => "DeviceNotOpenedForWrite".to_owned(),
-            Self::FailedToWriteDevice => "FailedToWriteDevice".to_owned(),
-            Self::EEPROMReadFailed => "EEPROMReadFailed".to_owned(),
-            Self::EEPROMWriteFailed => "EEPROMWriteFailed".to_owned(),
-            Self::EEPROMEraseFailed => "EEPROMEraseFailed".to_owned(),
-            Self::EEPROMNotPresent => "EEPROMNotPresent".to_owned(),
-            Self::EEPROMNotProgrammed => "EEPROMNotProgrammed".to_owned(),
-            Self::InvalidArgs => "InvalidArgs".to_owned(),
-            Self::NotSupported => "NotSupported".to_owned(),
-            Self::NoMoreItems => "NoMoreItems".to_owned(),
-            Self::Timeout => "Timeout".to_owned(),
-            Self::OperationAborted => "OperationAborted".to_owned(),
-            Self::ReservedPipe => "ReservedPipe".to_owned(),
-            Self::InvalidControlRequestDirection => "InvalidControlRequestDirection".to_owned(),
-            Self::InvalidControLRequestType => "InvalidControLRequestType".to_owned(),
-            Self::IoPending => "IoPending".to_owned(),
-            Self::IoIncomplete => "IoIncomplete".to_owned(),
-            Self::HandleEof => "HandleEof".to_owned(),
-            Self::Busy => "Busy".to_owned(),
-            Self::NoSystemResources => "NoSystemResources".to_owned(),
-            Self::DeviceListNotReady => "DeviceListNotReady".to_owned(),
-            Self::DeviceNotConnected => "DeviceNotConnected".to_owned(),
-            Self::IncorrectDevicePath => "IncorrectDevicePath".to_owned(),
-            Self::OtherError => "OtherError".to_owned(),
-
             Self::LibraryAccessFailed(e) => format!("LibraryAccessFailed - {}", e),
             Self::UnpackingFailed(e) => format!("UnpackingFailed - {}", e),
-            Self::LibraryAlreadyLoaded => "LibraryAlreadyLoaded".to_owned(),
-            Self::LibraryNotLoaded => "LibraryNotLoaded".to_owned(),
+            Self::Io(e) => format!("Io - {}", e),
+            _ => self.name().to_owned(),
         };
         let code = self
             .error_code()
@@ -180,3 +274,295 @@ impl Display for D3xxError {
         write!(f, "{} (error code {})", name, code)
     }
 }
+
+impl From<D3xxError> for std::io::Error {
+    fn from(e: D3xxError) -> Self {
+        std::io::Error::new(e.io_error_kind(), e)
+    }
+}
+
+/// The serialized form of a [`D3xxError`]:
+///
+/// ```json
+/// { "name": "Timeout", "code": 19, "detail": null }
+/// ```
+///
+/// `name` is the variant name, `code` the D3XX status value, or `null` for errors not
+/// defined by the library, and `detail` the message of the underlying error of
+/// `LibraryAccessFailed`, `UnpackingFailed` and `Io`.
+///
+/// Errors are deserialized by name. The underlying error of `UnpackingFailed` and
+/// `Io` is restored as an [`std::io::Error`] of kind `Other` holding the detail message;
+/// that of `LibraryAccessFailed` cannot be restored and is replaced by
+/// [`libloading::Error::DlOpenUnknown`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ErrorFields {
+    name: String,
+    code: Option<u32>,
+    #[serde(default)]
+    detail: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for D3xxError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let detail = match self {
+            Self::LibraryAccessFailed(e) => Some(e.to_string()),
+            Self::UnpackingFailed(e) | Self::Io(e) => Some(e.to_string()),
+            _ => None,
+        };
+        ErrorFields {
+            name: self.name().to_owned(),
+            code: self.error_code(),
+            detail,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for D3xxError {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = ErrorFields::deserialize(deserializer)?;
+        let error = match fields.name.as_str() {
+            "LibraryAccessFailed" => Self::LibraryAccessFailed(libloading::Error::DlOpenUnknown),
+            "UnpackingFailed" => {
+                Self::UnpackingFailed(std::io::Error::other(fields.detail.unwrap_or_default()))
+            }
+            "LibraryAlreadyLoaded" => Self::LibraryAlreadyLoaded,
+            "LibraryNotLoaded" => Self::LibraryNotLoaded,
+            "Cancelled" => Self::Cancelled,
+            "Usb3Required" => Self::Usb3Required,
+            "SettingNotApplied" => Self::SettingNotApplied,
+            "MisalignedLength" => Self::MisalignedLength,
+            "ReplayMismatch" => Self::ReplayMismatch,
+            "Io" => Self::Io(std::io::Error::other(fields.detail.unwrap_or_default())),
+            name => (1..=32)
+                .map(|code: FT_STATUS| Self::from(code))
+                .find(|e| e.name() == name)
+                .ok_or_else(|| {
+                    serde::de::Error::custom(format_args!("unknown D3XX error `{}`", name))
+                })?,
+        };
+        Ok(error)
+    }
+}
+
+// =============================================================================
+/// Error returned by the functions in this crate.
+///
+/// Wraps a [`D3xxError`] together with context describing where it occurred:
+/// the D3XX function which failed, the pipe and device involved, and the number
+/// of bytes transferred before the failure. Context which is not applicable or
+/// not known is left as `None`.
+#[derive(Debug)]
+pub struct Error {
+    kind: D3xxError,
+    function: Option<&'static str>,
+    pipe: Option<Pipe>,
+    serial_number: Option<String>,
+    bytes_transferred: Option<usize>,
+}
+
+impl Error {
+    /// Create a new error without any context.
+    pub fn new(kind: D3xxError) -> Error {
+        Self {
+            kind,
+            function: None,
+            pipe: None,
+            serial_number: None,
+            bytes_transferred: None,
+        }
+    }
+
+    /// The underlying error.
+    pub fn kind(&self) -> &D3xxError {
+        &self.kind
+    }
+
+    /// Consumes the error, returning the underlying error.
+    pub fn into_kind(self) -> D3xxError {
+        self.kind
+    }
+
+    /// Name of the D3XX function which failed, e.g. `"FT_ReadPipe"`.
+    pub fn function(&self) -> Option<&'static str> {
+        self.function
+    }
+
+    /// The pipe involved in the failed operation.
+    pub fn pipe(&self) -> Option<Pipe> {
+        self.pipe
+    }
+
+    /// Serial number of the device involved in the failed operation.
+    pub fn serial_number(&self) -> Option<&str> {
+        self.serial_number.as_deref()
+    }
+
+    /// Number of bytes transferred before the failure. Only set for reads and writes.
+    pub fn bytes_transferred(&self) -> Option<usize> {
+        self.bytes_transferred
+    }
+
+    /// See [`D3xxError::is_retryable`].
+    pub fn is_retryable(&self) -> bool {
+        self.kind.is_retryable()
+    }
+
+    /// See [`D3xxError::is_fatal`].
+    pub fn is_fatal(&self) -> bool {
+        self.kind.is_fatal()
+    }
+
+    /// Check if the transfer was cancelled through a [`CancelHandle`](crate::CancelHandle).
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.kind, D3xxError::Cancelled)
+    }
+
+    pub(crate) fn with_function(mut self, function: &'static str) -> Error {
+        self.function.get_or_insert(function);
+        self
+    }
+
+    pub(crate) fn with_pipe(mut self, pipe: Pipe) -> Error {
+        self.pipe.get_or_insert(pipe);
+        self
+    }
+
+    pub(crate) fn with_serial_number(mut self, serial_number: Option<&str>) -> Error {
+        if self.serial_number.is_none() {
+            self.serial_number = serial_number.map(str::to_owned);
+        }
+        self
+    }
+
+    pub(crate) fn with_bytes_transferred(mut self, bytes_transferred: usize) -> Error {
+        self.bytes_transferred.get_or_insert(bytes_transferred);
+        self
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(function) = self.function {
+            write!(f, "{} failed: ", function)?;
+        }
+        write!(f, "{}", self.kind)?;
+
+        let mut context = Vec::new();
+        if let Some(pipe) = self.pipe {
+            context.push(format!("pipe {:?}", pipe));
+        }
+        if let Some(serial_number) = &self.serial_number {
+            context.push(format!("device {}", serial_number));
+        }
+        if let Some(n) = self.bytes_transferred {
+            context.push(format!("{} bytes transferred", n));
+        }
+        if !context.is_empty() {
+            write!(f, " [{}]", context.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+
+impl From<D3xxError> for Error {
+    fn from(kind: D3xxError) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<libloading::Error> for Error {
+    fn from(e: libloading::Error) -> Self {
+        Self::new(e.into())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(e.into())
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        std::io::Error::new(e.kind.io_error_kind(), e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_status_is_other_error() {
+        assert!(matches!(D3xxError::from(19), D3xxError::Timeout));
+        assert!(matches!(D3xxError::from(32), D3xxError::OtherError));
+        assert!(matches!(D3xxError::from(0xdead), D3xxError::OtherError));
+    }
+
+    #[test]
+    fn io_error_keeps_source() {
+        let e = Error::from(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "no access",
+        ));
+        assert!(matches!(e.kind(), D3xxError::Io(_)));
+        assert_eq!(
+            e.kind().io_error_kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            std::io::Error::from(e).kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let errors = (1..=32)
+            .map(|code: FT_STATUS| D3xxError::from(code))
+            .chain([
+                D3xxError::LibraryAccessFailed(libloading::Error::DlOpenUnknown),
+                D3xxError::UnpackingFailed(std::io::Error::other("disk full")),
+                D3xxError::LibraryAlreadyLoaded,
+                D3xxError::LibraryNotLoaded,
+                D3xxError::Cancelled,
+                D3xxError::Usb3Required,
+                D3xxError::SettingNotApplied,
+                D3xxError::MisalignedLength,
+                D3xxError::ReplayMismatch,
+                D3xxError::Io(std::io::Error::other("broken pipe")),
+            ]);
+        for error in errors {
+            let json = serde_json::to_value(&error).unwrap();
+            assert_eq!(json["name"], error.name());
+            assert_eq!(json["code"], serde_json::json!(error.error_code()));
+            let decoded: D3xxError = serde_json::from_value(json.clone()).unwrap();
+            assert_eq!(decoded.name(), error.name());
+            assert_eq!(decoded.error_code(), error.error_code());
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+        }
+
+        let json = serde_json::json!({ "name": "Timeout", "code": 19 });
+        let decoded: D3xxError = serde_json::from_value(json).unwrap();
+        assert!(matches!(decoded, D3xxError::Timeout));
+
+        let json = serde_json::json!({ "name": "Io", "code": null, "detail": "broken pipe" });
+        let decoded: D3xxError = serde_json::from_value(json).unwrap();
+        assert!(matches!(decoded, D3xxError::Io(e) if e.to_string() == "broken pipe"));
+
+        let json = serde_json::json!({ "name": "NoSuchError", "code": null });
+        assert!(serde_json::from_value::<D3xxError>(json).is_err());
+    }
+}
@@ -3,7 +3,8 @@
 //! This module provides a semi-safe wrapper around the D3XX dynamic library. The
 //! functions and types defined in this module are not intended to be used directly
 //! by the users of this crate. Instead, the functions and types defined in the
-//! [top level](crate) should be used.
+//! [top level](crate) should be used. For functionality the top level does not cover,
+//! everything in this module is re-exported through [`raw`](crate::raw).
 //!
 //! D3xx functions can be found in the [`lib`](lib) module. The initial call to these
 //! functions will attempt to load the D3xx dynamic library, and subsequent calls will
@@ -19,15 +20,17 @@
 use libloading::Symbol;
 
 use crate::assets::d3xx_lib;
-use crate::Result;
+use crate::{D3xxError, Result};
 
 /// Helper function for loading a D3xx function by name from the library.
 ///
 /// # Errors
-/// Returns [`D3xxError::LibraryNotLoaded`] if the library could not be loaded.
-fn d3xx_fn<T>(name: &str) -> Result<Symbol<T>> {
+/// - [`D3xxError::LibraryNotLoaded`] if the library could not be loaded.
+/// - [`D3xxError::NotSupported`] if the library does not export the function. Not every
+///   function is available on every platform.
+fn d3xx_fn<T>(name: &str) -> Result<Symbol<'_, T>> {
     let library = d3xx_lib()?;
-    let function = unsafe { library.get::<T>(name.as_bytes())? };
+    let function = unsafe { library.get::<T>(name.as_bytes()) }.or(Err(D3xxError::NotSupported))?;
     Ok(function)
 }
 
@@ -37,135 +40,355 @@ pub(crate) fn ptr_mut<T, U>(x: &mut T) -> *mut U {
     x as *mut _ as *mut U
 }
 
+/// Emits the event for a completed D3XX call.
+#[cfg(feature = "tracing")]
+fn trace_status(status: types::FT_STATUS, elapsed: std::time::Duration) {
+    if status == 0 {
+        tracing::trace!(status, ?elapsed, "returned");
+    } else {
+        let error = D3xxError::from(status);
+        tracing::debug!(status, %error, ?elapsed, "returned");
+    }
+}
+
 /// Bindings to D3XX functions.
 ///
-/// Prototypes for these functions are defined in the `FTD3XX.h` header file.
+/// Prototypes for these functions are defined in the `FTD3XX.h` header file. Functions
+/// are resolved the first time they are called; calling a function which the loaded
+/// library does not export returns [`D3xxError::NotSupported`](crate::D3xxError::NotSupported).
 #[allow(non_snake_case, unused)]
 pub(crate) mod lib {
-    use libc::{c_uchar, c_ulong, c_ushort, c_void};
+    use libc::{c_int, c_uchar, c_ushort, c_void};
     use libloading::{Library, Symbol};
     use once_cell::sync::OnceCell;
 
     use super::d3xx_fn;
     use super::types::{
-        FT_DEVICE_DESCRIPTOR, FT_DEVICE_LIST_INFO_NODE, FT_HANDLE, FT_PIPE_INFORMATION, FT_STATUS,
+        DWORD, FT_CONFIGURATION_DESCRIPTOR, FT_DEVICE_DESCRIPTOR, FT_DEVICE_LIST_INFO_NODE,
+        FT_HANDLE, FT_INTERFACE_DESCRIPTOR, FT_NOTIFICATION_CALLBACK, FT_PIPE_INFORMATION,
+        FT_SETUP_PACKET, FT_STATUS, FT_STRING_DESCRIPTOR, ULONG,
     };
-    use crate::{D3xxError, Result};
+    use crate::{D3xxError, Error, Result};
 
     /// Macro for generating a wrapper function for a D3XX function.
     ///
+    /// Errors returned by the generated function carry the name of the D3XX function.
+    /// With the `tracing` feature enabled, each call runs in a `TRACE` span named after
+    /// the function with its arguments as fields, and emits an event with the returned
+    /// status and the duration of the call (at `DEBUG` level if the call failed).
+    /// Functions which return nothing rather than an `FT_STATUS` are declared with a
+    /// leading `void`. Attributes such as `#[cfg(...)]` may precede the name.
+    ///
     /// # Examples
     ///
-    /// ```
-    /// wrap_d3xx!(FT_ListDevices, pArg1: *mut c_void, pArg2: *mut c_void, flags: c_ulong);
+    /// ```ignore
+    /// wrap_d3xx!(FT_ListDevices, pArg1: *mut c_void, pArg2: *mut c_void, flags: ULONG);
     /// ```
     ///
     /// This will generate the following function:
     ///
-    /// ```
-    /// unsafe fn FT_ListDevices(pArg1: *mut c_void, pArg2: *mut c_void, flags: c_ulong) -> Result<()> {
-    ///     type F = unsafe extern "C" fn(*mut c_void, *mut c_void, c_ulong) -> FT_STATUS;
+    /// ```ignore
+    /// unsafe fn FT_ListDevices(pArg1: *mut c_void, pArg2: *mut c_void, flags: ULONG) -> Result<()> {
+    ///     type F = unsafe extern "C" fn(*mut c_void, *mut c_void, ULONG) -> FT_STATUS;
     ///     static SYMBOL: OnceCell<Symbol<F>> = OnceCell::new();
     ///
-    ///     let func = SYMBOL.get_or_try_init(|| d3xx_fn::<F>(stringify!($name)))?;
+    ///     let func = SYMBOL
+    ///         .get_or_try_init(|| d3xx_fn::<F>("FT_ListDevices"))
+    ///         .map_err(|e| e.with_function("FT_ListDevices"))?;
     ///     let res = unsafe { func(pArg1, pArg2, flags) };
     ///     if res != 0 {
-    ///        return Err(D3xxError::from(res as u32).into());
+    ///        return Err(Error::from(D3xxError::from(res)).with_function("FT_ListDevices"));
     ///     }
     ///     Ok(())
     /// }
     /// ```
     macro_rules! wrap_d3xx {
-        ($name:ident, $($arg:ident: $ty:ty),*) => {
-            pub(crate) unsafe fn $name($($arg: $ty),*) -> Result<()> {
+        ($(#[$attr:meta])* void $name:ident, $($arg:ident: $ty:ty),*) => {
+            $(#[$attr])*
+            #[doc = concat!("Calls `", stringify!($name), "`.")]
+            ///
+            /// # Safety
+            /// The arguments must satisfy the requirements given in the D3XX
+            /// Programmer's Guide. In particular, pointers must be valid for the
+            /// duration of the call, and handles must be open.
+            pub unsafe fn $name($($arg: $ty),*) -> Result<()> {
+                type F = unsafe extern "C" fn($($ty),*);
+                static SYMBOL: OnceCell<Symbol<F>> = OnceCell::new();
+
+                let func = SYMBOL
+                    .get_or_try_init(|| d3xx_fn::<F>(stringify!($name)))
+                    .map_err(|e| e.with_function(stringify!($name)))?;
+                #[cfg(feature = "tracing")]
+                let _span = tracing::trace_span!(stringify!($name), $($arg = ?$arg),*).entered();
+                #[cfg(feature = "tracing")]
+                let start = std::time::Instant::now();
+                unsafe { func($($arg),*) };
+                #[cfg(feature = "tracing")]
+                tracing::trace!(elapsed = ?start.elapsed(), "returned");
+                Ok(())
+            }
+        };
+        ($(#[$attr:meta])* $name:ident, $($arg:ident: $ty:ty),*) => {
+            $(#[$attr])*
+            #[doc = concat!("Calls `", stringify!($name), "`.")]
+            ///
+            /// # Safety
+            /// The arguments must satisfy the requirements given in the D3XX
+            /// Programmer's Guide. In particular, pointers must be valid for the
+            /// duration of the call, and handles must be open.
+            #[allow(clippy::too_many_arguments)]
+            pub unsafe fn $name($($arg: $ty),*) -> Result<()> {
                 type F = unsafe extern "C" fn($($ty),*) -> FT_STATUS;
                 static SYMBOL: OnceCell<Symbol<F>> = OnceCell::new();
 
-                let func = SYMBOL.get_or_try_init(|| d3xx_fn::<F>(stringify!($name)))?;
+                let func = SYMBOL
+                    .get_or_try_init(|| d3xx_fn::<F>(stringify!($name)))
+                    .map_err(|e| e.with_function(stringify!($name)))?;
+                #[cfg(feature = "tracing")]
+                let _span = tracing::trace_span!(stringify!($name), $($arg = ?$arg),*).entered();
+                #[cfg(feature = "tracing")]
+                let start = std::time::Instant::now();
                 let res = unsafe { func($($arg),*) };
+                #[cfg(feature = "tracing")]
+                super::trace_status(res, start.elapsed());
                 if res != 0 {
-                    return Err(D3xxError::from(res));
+                    return Err(Error::from(D3xxError::from(res)).with_function(stringify!($name)));
                 }
                 Ok(())
             }
-        }
+        };
     }
 
+    // Device enumeration
     wrap_d3xx!(
         FT_ListDevices,
         pArg1: *mut c_void,
         pArg2: *mut c_void,
-        flags: c_ulong
+        flags: ULONG
     );
-    wrap_d3xx!(FT_CreateDeviceInfoList, lpdwNumDevs: *mut c_ulong);
+    wrap_d3xx!(FT_CreateDeviceInfoList, lpdwNumDevs: *mut ULONG);
     wrap_d3xx!(
         FT_GetDeviceInfoList,
         ptDest: *mut FT_DEVICE_LIST_INFO_NODE,
-        lpdwNumDevs: *mut c_ulong
+        lpdwNumDevs: *mut ULONG
     );
     wrap_d3xx!(
         FT_GetDeviceInfoDetail,
-        dwIndex: c_ulong,
-        lpdwFlags: *mut c_ulong,
-        lpdwType: *mut c_ulong,
-        lpdwID: *mut c_ulong,
-        lpdwLocId: *mut c_ulong,
+        dwIndex: ULONG,
+        lpdwFlags: *mut ULONG,
+        lpdwType: *mut ULONG,
+        lpdwID: *mut ULONG,
+        lpdwLocId: *mut ULONG,
         lpSerialNumber: *mut c_void,
         lpDescription: *mut c_void,
         pftHandle: *mut FT_HANDLE
     );
+
+    // Opening and closing
     wrap_d3xx!(
         FT_Create,
         pvArg: *mut c_void,
-        dwFlags: c_ulong,
+        dwFlags: ULONG,
         pftHandle: *mut FT_HANDLE
     );
     wrap_d3xx!(FT_Close, ftHandle: FT_HANDLE);
+    wrap_d3xx!(
+        FT_IsDevicePath,
+        handle: FT_HANDLE,
+        pucDevicePath: *const c_uchar
+    );
+
+    // Versions
     wrap_d3xx!(
         FT_GetDriverVersion,
         handle: FT_HANDLE,
-        lpdwVersion: *mut c_ulong
+        lpdwVersion: *mut ULONG
     );
+    wrap_d3xx!(FT_GetLibraryVersion, version: *mut ULONG);
     wrap_d3xx!(
+        FT_GetFirmwareVersion,
+        handle: FT_HANDLE,
+        pulFirmwareVersion: *mut ULONG
+    );
+
+    // Transfers
+    //
+    // On Windows the synchronous transfer functions take an optional `OVERLAPPED`,
+    // and the pipe is identified by its endpoint address. On Linux and macOS they
+    // take a timeout in milliseconds instead, and the `Ex` variants identify the
+    // pipe by its FIFO channel (0 to 3).
+    wrap_d3xx!(
+        #[cfg(windows)]
+        FT_WritePipe,
+        handle: FT_HANDLE,
+        ucPipeId: u8,
+        pucBuffer: *const c_uchar,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
+        pOverlapped: *mut c_void
+    );
+    wrap_d3xx!(
+        #[cfg(not(windows))]
+        FT_WritePipe,
+        handle: FT_HANDLE,
+        ucPipeId: u8,
+        pucBuffer: *const c_uchar,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
+        dwTimeoutInMs: DWORD
+    );
+    wrap_d3xx!(
+        #[cfg(windows)]
         FT_WritePipeEx,
         handle: FT_HANDLE,
         ucPipeId: u8,
         pucBuffer: *const c_uchar,
-        ulBufferLength: c_ulong,
-        pulBytesTransferred: *mut c_ulong,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
         pOverlapped: *mut c_void
     );
     wrap_d3xx!(
+        #[cfg(not(windows))]
+        FT_WritePipeEx,
+        handle: FT_HANDLE,
+        ucFifoId: u8,
+        pucBuffer: *const c_uchar,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
+        dwTimeoutInMs: DWORD
+    );
+    wrap_d3xx!(
+        #[cfg(windows)]
         FT_ReadPipe,
         handle: FT_HANDLE,
         ucPipeId: u8,
         pucBuffer: *mut c_uchar,
-        ulBufferLength: c_ulong,
-        pulBytesTransferred: *mut c_ulong,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
         pOverlapped: *mut c_void
     );
     wrap_d3xx!(
+        #[cfg(not(windows))]
+        FT_ReadPipe,
+        handle: FT_HANDLE,
+        ucPipeId: u8,
+        pucBuffer: *mut c_uchar,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
+        dwTimeoutInMs: DWORD
+    );
+    wrap_d3xx!(
+        #[cfg(windows)]
         FT_ReadPipeEx,
         handle: FT_HANDLE,
         ucPipeId: u8,
         pucBuffer: *mut c_uchar,
-        ulBufferLength: c_ulong,
-        pulBytesTransferred: *mut c_ulong,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
+        pOverlapped: *mut c_void
+    );
+    wrap_d3xx!(
+        #[cfg(not(windows))]
+        FT_ReadPipeEx,
+        handle: FT_HANDLE,
+        ucFifoId: u8,
+        pucBuffer: *mut c_uchar,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
+        dwTimeoutInMs: DWORD
+    );
+    wrap_d3xx!(
+        FT_WritePipeAsync,
+        handle: FT_HANDLE,
+        ucFifoId: u8,
+        pucBuffer: *const c_uchar,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
+        pOverlapped: *mut c_void
+    );
+    wrap_d3xx!(
+        FT_ReadPipeAsync,
+        handle: FT_HANDLE,
+        ucFifoId: u8,
+        pucBuffer: *mut c_uchar,
+        ulBufferLength: ULONG,
+        pulBytesTransferred: *mut ULONG,
+        pOverlapped: *mut c_void
+    );
+    wrap_d3xx!(
+        FT_GetOverlappedResult,
+        handle: FT_HANDLE,
+        pOverlapped: *mut c_void,
+        pulLengthTransferred: *mut ULONG,
+        bWait: c_int
+    );
+    wrap_d3xx!(
+        FT_InitializeOverlapped,
+        handle: FT_HANDLE,
         pOverlapped: *mut c_void
     );
+    wrap_d3xx!(
+        FT_ReleaseOverlapped,
+        handle: FT_HANDLE,
+        pOverlapped: *mut c_void
+    );
+
+    // Pipe control
     wrap_d3xx!(FT_FlushPipe, handle: FT_HANDLE, ucPipeID: c_uchar);
+    wrap_d3xx!(FT_AbortPipe, handle: FT_HANDLE, ucPipeID: c_uchar);
     wrap_d3xx!(
         FT_SetPipeTimeout,
         handle: FT_HANDLE,
         ucPipeID: c_uchar,
-        ulTimeoutInMs: c_ulong
+        ulTimeoutInMs: ULONG
     );
     wrap_d3xx!(
         FT_GetPipeTimeout,
         handle: FT_HANDLE,
         ucPipeId: c_uchar,
-        pTimeoutInMs: *mut c_ulong
+        pTimeoutInMs: *mut ULONG
     );
+    wrap_d3xx!(
+        FT_SetStreamPipe,
+        handle: FT_HANDLE,
+        bAllWritePipes: c_uchar,
+        bAllReadPipes: c_uchar,
+        ucPipeID: c_uchar,
+        ulStreamSize: ULONG
+    );
+    wrap_d3xx!(
+        FT_ClearStreamPipe,
+        handle: FT_HANDLE,
+        bAllWritePipes: c_uchar,
+        bAllReadPipes: c_uchar,
+        ucPipeID: c_uchar
+    );
+    wrap_d3xx!(
+        FT_GetReadQueueStatus,
+        handle: FT_HANDLE,
+        ucFifoId: c_uchar,
+        lpdwBytesInQueue: *mut ULONG
+    );
+    wrap_d3xx!(
+        FT_GetWriteQueueStatus,
+        handle: FT_HANDLE,
+        ucFifoId: c_uchar,
+        lpdwBytesInQueue: *mut ULONG
+    );
+    wrap_d3xx!(
+        FT_GetUnsentBuffer,
+        handle: FT_HANDLE,
+        ucFifoId: c_uchar,
+        byBuffer: *mut c_uchar,
+        lpdwBufferLength: *mut ULONG
+    );
+    wrap_d3xx!(
+        FT_SetTransferParams,
+        pConf: *mut c_void,
+        dwFifoId: ULONG
+    );
+
+    // Descriptors
     wrap_d3xx!(
         FT_GetVIDPID,
         handle: FT_HANDLE,
@@ -178,22 +401,22 @@ pub(crate) mod lib {
         pDescriptor: *mut FT_DEVICE_DESCRIPTOR
     );
     wrap_d3xx!(
-        FT_SetStreamPipe,
+        FT_GetConfigurationDescriptor,
         handle: FT_HANDLE,
-        bAllWritePipes: c_uchar,
-        bAllReadPipes: c_uchar,
-        ucPipeID: c_uchar,
-        ulStreamSize: c_ulong
+        pDescriptor: *mut FT_CONFIGURATION_DESCRIPTOR
     );
     wrap_d3xx!(
-        FT_ClearStreamPipe,
+        FT_GetInterfaceDescriptor,
         handle: FT_HANDLE,
-        bAllWritePipes: c_uchar,
-        bAllReadPipes: c_uchar,
-        ucPipeID: c_uchar
+        ucInterfaceIndex: c_uchar,
+        pDescriptor: *mut FT_INTERFACE_DESCRIPTOR
+    );
+    wrap_d3xx!(
+        FT_GetStringDescriptor,
+        handle: FT_HANDLE,
+        ucStringIndex: c_uchar,
+        pDescriptor: *mut FT_STRING_DESCRIPTOR
     );
-    wrap_d3xx!(FT_AbortPipe, handle: FT_HANDLE, ucPipeID: c_uchar);
-    wrap_d3xx!(FT_CycleDevicePort, handle: FT_HANDLE);
     wrap_d3xx!(
         FT_GetPipeInformation,
         handle: FT_HANDLE,
@@ -201,7 +424,71 @@ pub(crate) mod lib {
         ucPipeIndex: c_uchar,
         pPipeInformation: *mut FT_PIPE_INFORMATION
     );
-    wrap_d3xx!(FT_GetLibraryVersion, version: *mut c_ulong);
+    wrap_d3xx!(
+        FT_GetDescriptor,
+        handle: FT_HANDLE,
+        ucDescriptorType: c_uchar,
+        ucIndex: c_uchar,
+        pucBuffer: *mut c_uchar,
+        ulBufferLength: ULONG,
+        pulLengthTransferred: *mut ULONG
+    );
+    wrap_d3xx!(
+        FT_ControlTransfer,
+        handle: FT_HANDLE,
+        tSetupPacket: FT_SETUP_PACKET,
+        pucBuffer: *mut c_uchar,
+        ulBufferLength: ULONG,
+        pulLengthTransferred: *mut ULONG
+    );
+
+    // Chip configuration
+    wrap_d3xx!(
+        FT_GetChipConfiguration,
+        handle: FT_HANDLE,
+        pvConfiguration: *mut c_void
+    );
+    wrap_d3xx!(
+        FT_SetChipConfiguration,
+        handle: FT_HANDLE,
+        pvConfiguration: *mut c_void
+    );
+
+    // Notifications
+    wrap_d3xx!(
+        FT_SetNotificationCallback,
+        handle: FT_HANDLE,
+        pCallback: FT_NOTIFICATION_CALLBACK,
+        pvCallbackContext: *mut c_void
+    );
+    wrap_d3xx!(void FT_ClearNotificationCallback, handle: FT_HANDLE);
+
+    // GPIO
+    wrap_d3xx!(
+        FT_EnableGPIO,
+        handle: FT_HANDLE,
+        u32Mask: u32,
+        u32Dir: u32
+    );
+    wrap_d3xx!(
+        FT_WriteGPIO,
+        handle: FT_HANDLE,
+        u32Mask: u32,
+        u32Data: u32
+    );
+    wrap_d3xx!(FT_ReadGPIO, handle: FT_HANDLE, pu32Data: *mut u32);
+    wrap_d3xx!(
+        FT_SetGPIOPull,
+        handle: FT_HANDLE,
+        u32Mask: u32,
+        u32Pull: u32
+    );
+
+    // Power management
+    wrap_d3xx!(FT_SetSuspendTimeout, handle: FT_HANDLE, Timeout: ULONG);
+    wrap_d3xx!(FT_GetSuspendTimeout, handle: FT_HANDLE, Timeout: *mut ULONG);
+    wrap_d3xx!(FT_ResetDevicePort, handle: FT_HANDLE);
+    wrap_d3xx!(FT_CycleDevicePort, handle: FT_HANDLE);
 }
 
 /// Types used by D3XX functions.
@@ -210,41 +497,39 @@ pub(crate) mod lib {
 #[allow(non_camel_case_types)]
 pub mod types {
     use libc::*;
-    use std::{ffi::CStr, fmt::Debug};
-
-    use crate::{error::D3xxError, Result};
+    use std::fmt::Debug;
 
     #[allow(non_snake_case)]
     #[repr(C)]
     #[derive(Debug, Default, Clone)]
-    pub(crate) struct FT_DEVICE_DESCRIPTOR {
-        pub(crate) bLength: c_uchar,
-        pub(crate) bDescriptorType: c_uchar,
-        pub(crate) bcdUSB: c_ushort,
-        pub(crate) bDeviceClass: c_uchar,
-        pub(crate) bDeviceSubClass: c_uchar,
-        pub(crate) bDeviceProtocol: c_uchar,
-        pub(crate) bMaxPacketSize0: c_uchar,
-        pub(crate) idVendor: c_ushort,
-        pub(crate) idProduct: c_ushort,
-        pub(crate) bcdDevice: c_ushort,
-        pub(crate) iManufacturer: c_uchar,
-        pub(crate) iProduct: c_uchar,
-        pub(crate) iSerialNumber: c_uchar,
-        pub(crate) bNumConfigurations: c_uchar,
+    pub struct FT_DEVICE_DESCRIPTOR {
+        pub bLength: c_uchar,
+        pub bDescriptorType: c_uchar,
+        pub bcdUSB: c_ushort,
+        pub bDeviceClass: c_uchar,
+        pub bDeviceSubClass: c_uchar,
+        pub bDeviceProtocol: c_uchar,
+        pub bMaxPacketSize0: c_uchar,
+        pub idVendor: c_ushort,
+        pub idProduct: c_ushort,
+        pub bcdDevice: c_ushort,
+        pub iManufacturer: c_uchar,
+        pub iProduct: c_uchar,
+        pub iSerialNumber: c_uchar,
+        pub bNumConfigurations: c_uchar,
     }
 
     #[allow(non_snake_case)]
     #[repr(C)]
     #[derive(Clone)]
-    pub(crate) struct FT_DEVICE_LIST_INFO_NODE {
-        pub(crate) Flags: c_ulong,
-        pub(crate) Type: c_ulong,
-        pub(crate) ID: c_ulong,
-        pub(crate) LocId: c_ulong,
-        pub(crate) SerialNumber: [c_uchar; 16],
-        pub(crate) Description: [c_uchar; 32],
-        pub(crate) ftHandle: FT_HANDLE,
+    pub struct FT_DEVICE_LIST_INFO_NODE {
+        pub Flags: ULONG,
+        pub Type: ULONG,
+        pub ID: ULONG,
+        pub LocId: ULONG,
+        pub SerialNumber: [c_uchar; 16],
+        pub Description: [c_uchar; 32],
+        pub ftHandle: FT_HANDLE,
     }
 
     impl Debug for FT_DEVICE_LIST_INFO_NODE {
@@ -254,8 +539,8 @@ pub mod types {
                 .field("Type", &self.Type)
                 .field("ID", &self.ID)
                 .field("LocId", &self.LocId)
-                .field("SerialNumber", &c_str_to_string(&self.SerialNumber))
-                .field("Description", &c_str_to_string(&self.Description))
+                .field("SerialNumber", &fixed_c_str_to_string(&self.SerialNumber))
+                .field("Description", &fixed_c_str_to_string(&self.Description))
                 .field("ftHandle", &self.ftHandle)
                 .finish()
         }
@@ -278,26 +563,184 @@ pub mod types {
     #[allow(non_snake_case)]
     #[repr(C)]
     #[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
-    pub(crate) struct FT_PIPE_INFORMATION {
-        pub(crate) PipeType: c_int,
-        pub(crate) PipeID: c_uchar,
-        pub(crate) MaximumPacketSize: c_ushort,
-        pub(crate) Interval: c_uchar,
+    pub struct FT_PIPE_INFORMATION {
+        pub PipeType: c_int,
+        pub PipeID: c_uchar,
+        pub MaximumPacketSize: c_ushort,
+        pub Interval: c_uchar,
+    }
+
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    #[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+    pub struct FT_CONFIGURATION_DESCRIPTOR {
+        pub bLength: c_uchar,
+        pub bDescriptorType: c_uchar,
+        pub wTotalLength: c_ushort,
+        pub bNumInterfaces: c_uchar,
+        pub bConfigurationValue: c_uchar,
+        pub iConfiguration: c_uchar,
+        pub bmAttributes: c_uchar,
+        pub MaxPower: c_uchar,
+    }
+
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    #[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+    pub struct FT_INTERFACE_DESCRIPTOR {
+        pub bLength: c_uchar,
+        pub bDescriptorType: c_uchar,
+        pub bInterfaceNumber: c_uchar,
+        pub bAlternateSetting: c_uchar,
+        pub bNumEndpoints: c_uchar,
+        pub bInterfaceClass: c_uchar,
+        pub bInterfaceSubClass: c_uchar,
+        pub bInterfaceProtocol: c_uchar,
+        pub iInterface: c_uchar,
+    }
+
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    pub struct FT_STRING_DESCRIPTOR {
+        pub bLength: c_uchar,
+        pub bDescriptorType: c_uchar,
+        pub szString: [c_ushort; 256],
+    }
+
+    impl Default for FT_STRING_DESCRIPTOR {
+        fn default() -> Self {
+            Self {
+                bLength: 0,
+                bDescriptorType: 0,
+                szString: [0; 256],
+            }
+        }
+    }
+
+    /// State of an asynchronous transfer. Must be initialized with
+    /// `FT_InitializeOverlapped` and must not move while a transfer is pending.
+    ///
+    /// This has the layout of the Windows `OVERLAPPED` structure, which is at least as
+    /// large as the structure of the same name used by the Linux driver.
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct OVERLAPPED {
+        pub Internal: usize,
+        pub InternalHigh: usize,
+        pub Offset: DWORD,
+        pub OffsetHigh: DWORD,
+        pub hEvent: *mut c_void,
+    }
+
+    impl Default for OVERLAPPED {
+        fn default() -> Self {
+            Self {
+                Internal: 0,
+                InternalHigh: 0,
+                Offset: 0,
+                OffsetHigh: 0,
+                hEvent: std::ptr::null_mut(),
+            }
+        }
+    }
+
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    #[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+    pub struct FT_SETUP_PACKET {
+        pub RequestType: c_uchar,
+        pub Request: c_uchar,
+        pub Value: c_ushort,
+        pub Index: c_ushort,
+        pub Length: c_ushort,
     }
 
+    /// Chip configuration, read and written using `FT_GetChipConfiguration` and
+    /// `FT_SetChipConfiguration`.
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    pub struct FT_60XCONFIGURATION {
+        pub VendorID: c_ushort,
+        pub ProductID: c_ushort,
+        pub StringDescriptors: [c_uchar; 128],
+        pub Reserved: c_uchar,
+        pub PowerAttributes: c_uchar,
+        pub PowerConsumption: c_ushort,
+        pub Reserved2: c_uchar,
+        pub FIFOClock: c_uchar,
+        pub FIFOMode: c_uchar,
+        pub ChannelConfig: c_uchar,
+        pub OptionalFeatureSupport: c_ushort,
+        pub BatteryChargingGPIOConfig: c_uchar,
+        pub FlashEEPROMDetection: c_uchar,
+        pub MSIO_Control: ULONG,
+        pub GPIO_Control: ULONG,
+    }
+
+    impl Default for FT_60XCONFIGURATION {
+        fn default() -> Self {
+            Self {
+                VendorID: 0,
+                ProductID: 0,
+                StringDescriptors: [0; 128],
+                Reserved: 0,
+                PowerAttributes: 0,
+                PowerConsumption: 0,
+                Reserved2: 0,
+                FIFOClock: 0,
+                FIFOMode: 0,
+                ChannelConfig: 0,
+                OptionalFeatureSupport: 0,
+                BatteryChargingGPIOConfig: 0,
+                FlashEEPROMDetection: 0,
+                MSIO_Control: 0,
+                GPIO_Control: 0,
+            }
+        }
+    }
+
+    /// Information passed to a notification callback for data notifications.
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    #[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+    pub struct FT_NOTIFICATION_CALLBACK_INFO_DATA {
+        pub ulRecvNotificationLength: ULONG,
+        pub ucEndpointNo: c_uchar,
+    }
+
+    /// Information passed to a notification callback for GPIO notifications.
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    #[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+    pub struct FT_NOTIFICATION_CALLBACK_INFO_GPIO {
+        pub bGPIO0: c_int,
+        pub bGPIO1: c_int,
+    }
+
+    /// Callback registered with `FT_SetNotificationCallback`. The second argument is
+    /// one of the `E_FT_NOTIFICATION_CALLBACK_TYPE_*` constants.
+    pub type FT_NOTIFICATION_CALLBACK =
+        Option<unsafe extern "C" fn(*mut c_void, c_int, *mut c_void)>;
+
+    /// `ULONG` as used by D3XX. This is 32 bits wide on every platform, unlike `c_ulong`.
+    pub type ULONG = u32;
+
+    /// `DWORD` as used by D3XX.
+    pub type DWORD = u32;
+
     #[allow(non_camel_case_types)]
-    pub(crate) type FT_STATUS = c_ulong;
+    pub type FT_STATUS = ULONG;
     #[allow(non_camel_case_types)]
-    pub(crate) type FT_HANDLE = *mut c_void;
-
-    /// Convert a C string received via FFI to a Rust `String`.
-    pub(crate) fn c_str_to_string(s: &[c_uchar]) -> Result<String> {
-        unsafe {
-            Ok(CStr::from_ptr(s.as_ptr() as *const _)
-                .to_str()
-                .or(Err(D3xxError::OtherError))?
-                .to_string())
-        }
+    pub type FT_HANDLE = *mut c_void;
+
+    /// Convert a fixed-size, possibly unterminated, C string array received via FFI
+    /// to a Rust `String`. Invalid UTF-8 is replaced rather than rejected.
+    pub fn fixed_c_str_to_string(s: &[c_uchar]) -> String {
+        let len = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+        String::from_utf8_lossy(&s[..len]).into_owned()
     }
 }
 
@@ -306,38 +749,84 @@ pub mod types {
 /// These are defined in `FTD3XX.h`.
 #[allow(dead_code)]
 pub mod constants {
-    use libc::{c_uchar, c_ulong, c_ushort};
+    use libc::{c_int, c_uchar, c_ushort};
+
+    use super::types::ULONG;
 
     // Standard Descriptor Types
-    pub(crate) const FT_DEVICE_DESCRIPTOR_TYPE: c_ushort = 0x01;
-    pub(crate) const FT_CONFIGURATION_DESCRIPTOR_TYPE: c_ushort = 0x02;
-    pub(crate) const FT_STRING_DESCRIPTOR_TYPE: c_ushort = 0x03;
-    pub(crate) const FT_INTERFACE_DESCRIPTOR_TYPE: c_ushort = 0x04;
+    pub const FT_DEVICE_DESCRIPTOR_TYPE: c_ushort = 0x01;
+    pub const FT_CONFIGURATION_DESCRIPTOR_TYPE: c_ushort = 0x02;
+    pub const FT_STRING_DESCRIPTOR_TYPE: c_ushort = 0x03;
+    pub const FT_INTERFACE_DESCRIPTOR_TYPE: c_ushort = 0x04;
 
     // Reserved pipes
-    pub(crate) const FT_RESERVED_INTERFACE_INDEX: c_ushort = 0x0;
-    pub(crate) const FT_RESERVED_PIPE_INDEX_SESSION: c_ushort = 0x0;
-    pub(crate) const FT_RESERVED_PIPE_INDEX_NOTIFICATION: c_ushort = 0x1;
-    pub(crate) const FT_RESERVED_PIPE_SESSION: c_ushort = 0x1;
-    pub(crate) const FT_RESERVED_PIPE_NOTIFICATION: c_ushort = 0x81;
+    pub const FT_RESERVED_INTERFACE_INDEX: c_ushort = 0x0;
+    pub const FT_RESERVED_PIPE_INDEX_SESSION: c_ushort = 0x0;
+    pub const FT_RESERVED_PIPE_INDEX_NOTIFICATION: c_ushort = 0x1;
+    pub const FT_RESERVED_PIPE_SESSION: c_ushort = 0x1;
+    pub const FT_RESERVED_PIPE_NOTIFICATION: c_ushort = 0x81;
 
     // Create flags
-    pub(crate) const FT_OPEN_BY_SERIAL_NUMBER: c_ulong = 0x00000001;
-    pub(crate) const FT_OPEN_BY_DESCRIPTION: c_ulong = 0x00000002;
-    pub(crate) const FT_OPEN_BY_LOCATION: c_ulong = 0x00000004;
-    pub(crate) const FT_OPEN_BY_GUID: c_ulong = 0x00000008;
-    pub(crate) const FT_OPEN_BY_INDEX: c_ulong = 0x00000010;
+    pub const FT_OPEN_BY_SERIAL_NUMBER: ULONG = 0x00000001;
+    pub const FT_OPEN_BY_DESCRIPTION: ULONG = 0x00000002;
+    pub const FT_OPEN_BY_LOCATION: ULONG = 0x00000004;
+    pub const FT_OPEN_BY_GUID: ULONG = 0x00000008;
+    pub const FT_OPEN_BY_INDEX: ULONG = 0x00000010;
 
     // ListDevices flags
-    pub(crate) const FT_LIST_ALL: c_ulong = 0x20000000;
-    pub(crate) const FT_LIST_BY_INDEX: c_ulong = 0x40000000;
-    pub(crate) const FT_LIST_NUMBER_ONLY: c_ulong = 0x80000000;
+    pub const FT_LIST_ALL: ULONG = 0x20000000;
+    pub const FT_LIST_BY_INDEX: ULONG = 0x40000000;
+    pub const FT_LIST_NUMBER_ONLY: ULONG = 0x80000000;
 
     // GPIO direction, value
-    pub(crate) const FT_GPIO_DIRECTION_IN: c_uchar = 0;
-    pub(crate) const FT_GPIO_DIRECTION_OUT: c_uchar = 1;
-    pub(crate) const FT_GPIO_VALUE_LOW: c_uchar = 0;
-    pub(crate) const FT_GPIO_VALUE_HIGH: c_uchar = 1;
-    pub(crate) const FT_GPIO_0: c_uchar = 0;
-    pub(crate) const FT_GPIO_1: c_uchar = 1;
+    pub const FT_GPIO_DIRECTION_IN: c_uchar = 0;
+    pub const FT_GPIO_DIRECTION_OUT: c_uchar = 1;
+    pub const FT_GPIO_VALUE_LOW: c_uchar = 0;
+    pub const FT_GPIO_VALUE_HIGH: c_uchar = 1;
+    pub const FT_GPIO_0: c_uchar = 0;
+    pub const FT_GPIO_1: c_uchar = 1;
+
+    // Device flags, as reported in FT_DEVICE_LIST_INFO_NODE
+    pub const FT_FLAGS_OPENED: ULONG = 0x1;
+    pub const FT_FLAGS_HISPEED: ULONG = 0x2;
+    pub const FT_FLAGS_SUPERSPEED: ULONG = 0x4;
+
+    // Device types, as reported in FT_DEVICE_LIST_INFO_NODE
+    pub const FT_DEVICE_UNKNOWN: ULONG = 3;
+    pub const FT_DEVICE_600: ULONG = 600;
+    pub const FT_DEVICE_601: ULONG = 601;
+
+    // Pipe types
+    pub const FT_PIPE_TYPE_CONTROL: c_int = 0;
+    pub const FT_PIPE_TYPE_ISOCHRONOUS: c_int = 1;
+    pub const FT_PIPE_TYPE_BULK: c_int = 2;
+    pub const FT_PIPE_TYPE_INTERRUPT: c_int = 3;
+
+    // Notification callback types
+    pub const E_FT_NOTIFICATION_CALLBACK_TYPE_DATA: c_int = 0;
+    pub const E_FT_NOTIFICATION_CALLBACK_TYPE_GPIO: c_int = 1;
+
+    // Chip configuration: FIFOClock
+    pub const CONFIGURATION_FIFO_CLK_100: c_uchar = 0;
+    pub const CONFIGURATION_FIFO_CLK_66: c_uchar = 1;
+
+    // Chip configuration: FIFOMode
+    pub const CONFIGURATION_FIFO_MODE_245: c_uchar = 0;
+    pub const CONFIGURATION_FIFO_MODE_600: c_uchar = 1;
+
+    // Chip configuration: ChannelConfig
+    pub const CONFIGURATION_CHANNEL_CONFIG_4: c_uchar = 0;
+    pub const CONFIGURATION_CHANNEL_CONFIG_2: c_uchar = 1;
+    pub const CONFIGURATION_CHANNEL_CONFIG_1: c_uchar = 2;
+    pub const CONFIGURATION_CHANNEL_CONFIG_1_OUTPIPE: c_uchar = 3;
+    pub const CONFIGURATION_CHANNEL_CONFIG_1_INPIPE: c_uchar = 4;
+
+    // Chip configuration: OptionalFeatureSupport
+    pub const CONFIGURATION_OPTIONAL_FEATURE_DISABLEALL: c_ushort = 0x0000;
+    pub const CONFIGURATION_OPTIONAL_FEATURE_ENABLEBATTERYCHARGING: c_ushort = 0x0001;
+    pub const CONFIGURATION_OPTIONAL_FEATURE_DISABLECANCELSESSIONUNDERRUN: c_ushort = 0x0002;
+    pub const CONFIGURATION_OPTIONAL_FEATURE_ENABLENOTIFICATIONMESSAGE_INCHALL: c_ushort = 0x003C;
+    pub const CONFIGURATION_OPTIONAL_FEATURE_DISABLEUNDERRUN_INCHALL: c_ushort = 0x03C0;
+    pub const CONFIGURATION_OPTIONAL_FEATURE_SUPPORT_ENABLE_FIFO_IN_SUSPEND: c_ushort = 0x0400;
+    pub const CONFIGURATION_OPTIONAL_FEATURE_SUPPORT_DISABLE_CHIP_POWERDOWN: c_ushort = 0x0800;
 }
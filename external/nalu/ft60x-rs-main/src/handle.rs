@@ -0,0 +1,125 @@
+//! Owned and borrowed D3XX handles.
+//!
+//! These types follow the design of [`std::os::fd`]: an [`OwnedHandle`] closes its
+//! handle when dropped, and a [`BorrowedHandle`] is a copy of a handle which cannot
+//! outlive its owner. Use [`AsHandle::as_handle`] to pass the handle of a
+//! [`Device`](crate::Device) to your own D3XX calls without risking a use after
+//! `FT_Close`:
+//!
+//! ```no_run
+//! # fn main() -> ft60x_rs::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! use ft60x_rs::handle::AsHandle;
+//!
+//! let handle = device.as_handle();
+//! let raw = handle.as_raw_handle();
+//! // ... call vendor-specific D3XX functions with `raw` while `device` is alive ...
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::ffi::{lib, types};
+
+/// A raw D3XX handle, as used by the functions in `FTD3XX.h`.
+pub type RawHandle = types::FT_HANDLE;
+
+/// An owned D3XX handle, which is closed with `FT_Close` when dropped.
+pub struct OwnedHandle {
+    handle: RawHandle,
+}
+
+impl OwnedHandle {
+    /// Take ownership of a raw handle.
+    ///
+    /// # Safety
+    /// The handle must be open, returned by `FT_Create`, and not owned by anything
+    /// else. It will be closed when the returned value is dropped.
+    pub unsafe fn from_raw_handle(handle: RawHandle) -> OwnedHandle {
+        Self { handle }
+    }
+
+    /// Give up ownership of the handle without closing it.
+    pub fn into_raw_handle(self) -> RawHandle {
+        let handle = self.handle;
+        std::mem::forget(self);
+        handle
+    }
+
+    /// Get the raw handle without giving up ownership.
+    pub fn as_raw_handle(&self) -> RawHandle {
+        self.handle
+    }
+}
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = lib::FT_Close(self.handle);
+        }
+    }
+}
+
+impl Debug for OwnedHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OwnedHandle").field(&self.handle).finish()
+    }
+}
+
+/// A borrowed D3XX handle, valid for as long as its owner is alive.
+#[derive(Clone, Copy)]
+pub struct BorrowedHandle<'a> {
+    handle: RawHandle,
+    _owner: PhantomData<&'a OwnedHandle>,
+}
+
+impl BorrowedHandle<'_> {
+    /// Borrow a raw handle.
+    ///
+    /// # Safety
+    /// The handle must remain open for the lifetime of the returned value.
+    pub unsafe fn borrow_raw(handle: RawHandle) -> Self {
+        Self {
+            handle,
+            _owner: PhantomData,
+        }
+    }
+
+    /// Get the raw handle. The raw handle must not be used after the owner of this
+    /// handle has been dropped, and must not be closed.
+    pub fn as_raw_handle(&self) -> RawHandle {
+        self.handle
+    }
+}
+
+impl Debug for BorrowedHandle<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("BorrowedHandle").field(&self.handle).finish()
+    }
+}
+
+/// A type which can lend out its D3XX handle.
+pub trait AsHandle {
+    /// Borrow the D3XX handle.
+    fn as_handle(&self) -> BorrowedHandle<'_>;
+}
+
+impl AsHandle for OwnedHandle {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        unsafe { BorrowedHandle::borrow_raw(self.handle) }
+    }
+}
+
+impl<T: AsHandle> AsHandle for &T {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        T::as_handle(self)
+    }
+}
+
+// The D3XX library may be called from any thread. See the `split` module for which
+// calls may be made concurrently on the same handle.
+unsafe impl Send for OwnedHandle {}
+unsafe impl Sync for OwnedHandle {}
+unsafe impl Send for BorrowedHandle<'_> {}
+unsafe impl Sync for BorrowedHandle<'_> {}
@@ -12,18 +12,91 @@
 //! or [`load_bundled_dylib`] to use the bundled library for the current platform.
 
 pub(crate) mod assets;
+pub mod buffer;
+pub mod cancel;
+pub mod capture;
 pub mod error;
 pub(crate) mod ffi;
+pub mod handle;
+#[cfg(feature = "recorder")]
+pub(crate) mod lz4;
+pub mod metrics;
+pub mod options;
+pub mod pattern;
+pub mod power;
+#[cfg(feature = "recorder")]
+pub mod recorder;
+pub mod replay;
+pub mod split;
+pub mod stats;
+pub mod stream;
+
+/// Direct access to the D3XX library.
+///
+/// This module re-exports every function, type and constant declared in `FTD3XX.h`,
+/// for functionality which is not covered by the safe API. Every function is
+/// `unsafe` and returns the library status as a [`Result`](crate::Result); a
+/// function which is not exported by the loaded library fails with
+/// [`D3xxError::NotSupported`](crate::D3xxError::NotSupported).
+///
+/// Use [`AsHandle`](crate::AsHandle) to obtain the handle of an open device:
+///
+/// ```no_run
+/// # fn main() -> ft60x_rs::Result<()> {
+/// # let device = ft60x_rs::list_devices()?[0].open()?;
+/// use ft60x_rs::{raw, AsHandle};
+///
+/// let mut config = device.chip_configuration()?.to_raw();
+/// config.FIFOMode = raw::CONFIGURATION_FIFO_MODE_245;
+/// unsafe {
+///     raw::FT_SetChipConfiguration(
+///         device.as_handle().as_raw_handle(),
+///         &mut config as *mut _ as *mut std::ffi::c_void,
+///     )?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub mod raw {
+    pub use crate::ffi::constants::*;
+    pub use crate::ffi::lib::*;
+    pub use crate::ffi::types::*;
+}
 
-use std::{ffi::CString, fmt::Debug, ptr::null_mut, time::Duration};
-
-use ffi::{constants, lib, ptr_mut, types};
-use libc::{c_uchar, c_ulong, c_ushort, c_void};
-
-pub use error::D3xxError;
-pub use assets::{load_dylib, load_bundled_dylib};
-
-pub type Result<T, E = D3xxError> = std::result::Result<T, E>;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ffi::CString,
+    fmt::Debug,
+    marker::PhantomData,
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use ffi::{constants, lib, ptr_mut, types, types::ULONG};
+use libc::{c_uchar, c_ushort, c_void};
+use once_cell::sync::OnceCell;
+use stats::Stats;
+
+pub use assets::{load_bundled_dylib, load_dylib};
+pub use buffer::{AlignedBuf, BufferPool, PooledBuf};
+pub use cancel::CancelHandle;
+pub use capture::{CaptureHeader, CaptureReader, CaptureWriter};
+pub use error::{D3xxError, Error};
+pub use handle::{AsHandle, BorrowedHandle, OwnedHandle, RawHandle};
+pub use options::{DeviceSelector, OpenOptions};
+pub use pattern::{Pattern, PatternChecker, PatternGenerator, PatternReport};
+pub use power::SuspendGuard;
+pub use replay::{ReplayDevice, Timing, WriteMode};
+pub use split::{PipeReader, PipeWriter};
+pub use stats::{LatencyHistogram, PipeStats};
+pub use stream::{Stream, StreamBuffer, StreamConfig};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 /// A D3XX device.
 ///
@@ -31,57 +104,132 @@ pub type Result<T, E = D3xxError> = std::result::Result<T, E>;
 /// for interacting with the device, such as configuring and reading/writing to
 /// the device.
 pub struct Device {
-    /// The raw handle to the D3XX device.
-    handle: types::FT_HANDLE,
+    /// The raw handle to the D3XX device, shared with any halves created by [`Device::split`].
+    inner: Arc<DeviceHandle>,
+    /// Recovery policy for pipes without a pipe-specific policy.
+    recovery_policy: RecoveryPolicy,
+    /// Pipe-specific recovery policies.
+    pipe_recovery_policies: HashMap<Pipe, RecoveryPolicy>,
+    /// How the device was opened, used by [`Device::reopen`].
+    selector: Option<DeviceSelector>,
+    /// Options applied when the device was opened.
+    options: OpenOptions,
+    /// Device information captured when the device was opened.
+    info: RefCell<Option<DeviceInfo>>,
+    /// A `Device` may issue concurrent transfers on the same pipe through `&self`,
+    /// so it must not be shared between threads. Use [`Device::split`] instead.
+    _not_sync: PhantomData<Cell<()>>,
 }
 
 impl Device {
+    /// Index of the USB interface holding the FIFO pipes. Interface 0 holds the
+    /// notification pipe.
+    const DATA_INTERFACE: c_uchar = 1;
+
     /// Open a device using the given device information.
+    ///
+    /// Use [`OpenOptions`] to configure the device as part of opening it.
     pub fn open(info: &DeviceInfo) -> Result<Device> {
-        Self::open_with_serial_number(&info.serial_number()?)
+        OpenOptions::new().open(info)
     }
 
     /// Open a device using the given serial number.
     pub fn open_with_serial_number(serial_number: &str) -> Result<Device> {
+        OpenOptions::new().open(serial_number)
+    }
+
+    /// Close and re-open the device, re-applying the [`OpenOptions`] it was opened with.
+    ///
+    /// Intended for recovering after the device has been disconnected or its port has
+    /// been power cycled. Fails with [`D3xxError::DeviceNotFound`] if the device was
+    /// created using [`Device::from_handle`], as there is no way to find it again.
+    ///
+    /// If the device has been split, its handle is only closed once every half has
+    /// been dropped.
+    pub fn reopen(self) -> Result<Device> {
+        let selector = self.selector.clone().ok_or(D3xxError::DeviceNotFound)?;
+        let options = self.options.clone();
+        drop(self);
+        options.open(selector)
+    }
+
+    /// The options applied when the device was opened.
+    pub fn options(&self) -> &OpenOptions {
+        &self.options
+    }
+
+    /// Open the device identified by the selector without configuring it.
+    fn create(selector: &DeviceSelector) -> Result<Device> {
+        let name = match selector {
+            DeviceSelector::SerialNumber(s) | DeviceSelector::Description(s) => {
+                Some(CString::new(s.as_str()).or(Err(D3xxError::InvalidParameter))?)
+            }
+            DeviceSelector::Index(_) => None,
+        };
+        let name_ptr = name
+            .as_ref()
+            .map_or(null_mut(), |n| n.as_ptr() as *mut c_void);
+        let (arg, flags) = match selector {
+            DeviceSelector::SerialNumber(_) => (name_ptr, constants::FT_OPEN_BY_SERIAL_NUMBER),
+            DeviceSelector::Description(_) => (name_ptr, constants::FT_OPEN_BY_DESCRIPTION),
+            DeviceSelector::Index(index) => (*index as *mut c_void, constants::FT_OPEN_BY_INDEX),
+        };
+        let serial_number = match selector {
+            DeviceSelector::SerialNumber(s) => Some(s.as_str()),
+            _ => None,
+        };
+
         unsafe {
-            let serial = CString::new(serial_number).or(Err(D3xxError::InvalidParameter))?;
             let mut handle: types::FT_HANDLE = std::ptr::null_mut();
-            lib::FT_Create(
-                serial.as_ptr() as *mut c_void,
-                constants::FT_OPEN_BY_SERIAL_NUMBER,
-                &mut handle as *mut types::FT_HANDLE,
-            )?;
-            Ok(Self::from_handle(handle))
+            lib::FT_Create(arg, flags, &mut handle as *mut types::FT_HANDLE)
+                .map_err(|e| e.with_serial_number(serial_number))?;
+            let mut device = Self::from_handle(OwnedHandle::from_raw_handle(handle));
+            Arc::get_mut(&mut device.inner)
+                .expect("device handle is not shared")
+                .serial_number = serial_number.map(str::to_owned);
+            Ok(device)
         }
     }
 
-    /// Create a device wrapper using a raw handle
-    pub unsafe fn from_handle(handle: types::FT_HANDLE) -> Device {
-        Self { handle }
-    }
-
-    /// Get the raw handle to the D3XX device.
-    pub fn raw_handle(&self) -> types::FT_HANDLE {
-        self.handle
+    /// Create a device wrapper around an open handle. The handle is closed when the
+    /// device is dropped.
+    ///
+    /// Settings are not applied, and [`Device::reopen`] is unavailable as the device
+    /// cannot be found again.
+    pub fn from_handle(handle: OwnedHandle) -> Device {
+        Self {
+            inner: Arc::new(DeviceHandle::new(handle)),
+            recovery_policy: RecoveryPolicy::default(),
+            pipe_recovery_policies: HashMap::new(),
+            selector: None,
+            options: OpenOptions::default(),
+            info: RefCell::new(None),
+            _not_sync: PhantomData,
+        }
     }
 
     /// Gets information about the device.
+    ///
+    /// The information is captured when the device is opened and cached, so calling
+    /// this does not rebuild the D3XX device list. Use [`Device::refresh_info`] to
+    /// update it.
     pub fn info(&self) -> Result<DeviceInfo> {
-        let index = self.index()?;
-        let mut device_info = types::FT_DEVICE_LIST_INFO_NODE::default();
-        unsafe {
-            lib::FT_GetDeviceInfoDetail(
-                index as c_ulong,
-                ptr_mut(&mut device_info.Flags),
-                ptr_mut(&mut device_info.Type),
-                ptr_mut(&mut device_info.ID),
-                ptr_mut(&mut device_info.LocId),
-                ptr_mut(&mut device_info.SerialNumber),
-                ptr_mut(&mut device_info.Description),
-                ptr_mut(&mut device_info.ftHandle),
-            )?;
+        if let Some(info) = self.info.borrow().as_ref() {
+            return Ok(info.clone());
         }
-        Ok(DeviceInfo::new(index, device_info))
+        self.refresh_info()
+    }
+
+    /// Rebuilds the D3XX device list and updates the cached device information.
+    ///
+    /// This invalidates the indices of any [`DeviceInfo`] obtained previously.
+    pub fn refresh_info(&self) -> Result<DeviceInfo> {
+        let info = list_devices()?
+            .into_iter()
+            .find(|x| x.raw_handle() == Some(self.inner.raw()))
+            .ok_or_else(|| self.inner.device_error(D3xxError::DeviceNotFound.into()))?;
+        self.info.replace(Some(info.clone()));
+        Ok(info)
     }
 
     /// Get the vendor ID of the device.
@@ -99,7 +247,8 @@ impl Device {
         let mut vid: c_ushort = 0;
         let mut pid: c_ushort = 0;
         unsafe {
-            lib::FT_GetVIDPID(self.handle, ptr_mut(&mut vid), ptr_mut(&mut pid))?;
+            lib::FT_GetVIDPID(self.inner.raw(), ptr_mut(&mut vid), ptr_mut(&mut pid))
+                .map_err(|e| self.inner.device_error(e))?;
         }
         Ok((vid as usize, pid as usize))
     }
@@ -116,170 +265,363 @@ impl Device {
 
     /// Gets the D3XX kernel driver version.
     pub fn driver_version(&self) -> Result<Version> {
-        let mut version: c_ulong = 0;
+        let mut version: ULONG = 0;
         unsafe {
-            lib::FT_GetDriverVersion(self.handle, ptr_mut(&mut version))?;
+            lib::FT_GetDriverVersion(self.inner.raw(), ptr_mut(&mut version))
+                .map_err(|e| self.inner.device_error(e))?;
         }
         Ok(Version::new(version as u32))
     }
 
     /// Get the index of this device in the current device info list.
+    ///
+    /// This rebuilds the device list; see [`Device::refresh_info`].
     pub fn index(&self) -> Result<usize> {
-        let devices = list_devices()?;
-        let (i, _) = devices
-            .iter()
-            .enumerate()
-            .find(|(_, x)| match x.raw_handle() {
-                Some(handle) => handle == self.handle,
-                None => false,
-            })
-            .ok_or(D3xxError::DeviceNotFound)?;
-        Ok(i)
+        Ok(self.refresh_info()?.index())
     }
 
     /// Get information about a pipe.
-    #[allow(unused, unreachable_code)]
+    ///
+    /// Returns an `InvalidParameter` error if the pipe is not enabled by the chip's
+    /// channel configuration.
     pub fn pipe_info(&self, pipe: Pipe) -> Result<PipeInfo> {
-        todo!("this doesn't work, interface index is wrong");
-
-        let mut info = PipeInfo::default();
+        let mut interface = types::FT_INTERFACE_DESCRIPTOR::default();
         unsafe {
-            lib::FT_GetPipeInformation(self.handle, 0, pipe as c_uchar, ptr_mut(&mut info))?;
+            lib::FT_GetInterfaceDescriptor(
+                self.inner.raw(),
+                Self::DATA_INTERFACE,
+                ptr_mut(&mut interface),
+            )
+            .map_err(|e| self.inner.device_error(e))?;
         }
-        Ok(info)
+        // The pipe index is the position of the endpoint in the interface, not its address.
+        for index in 0..interface.bNumEndpoints {
+            let mut info = PipeInfo::default();
+            unsafe {
+                lib::FT_GetPipeInformation(
+                    self.inner.raw(),
+                    Self::DATA_INTERFACE,
+                    index,
+                    ptr_mut(&mut info.inner),
+                )
+                .map_err(|e| self.inner.pipe_error(pipe, e))?;
+            }
+            if info.inner.PipeID == pipe as u8 {
+                return Ok(info);
+            }
+        }
+        Err(self
+            .inner
+            .pipe_error(pipe, D3xxError::InvalidParameter.into()))
     }
 
     /// Writes data to the specified pipe. This method will block
     /// until the transfer is complete, or the timeout is reached.
+    ///
+    /// The length of `buf` must be a multiple of the FIFO bus width of the chip
+    /// (see [`ChipType::bus_width`]), otherwise a `MisalignedLength` error is returned.
+    ///
+    /// Errors are handled according to the pipe's [`RecoveryPolicy`].
     pub fn write(&self, pipe: Pipe, buf: &[u8]) -> Result<usize> {
-        if !pipe.is_write_pipe() {
-            Err(D3xxError::InvalidParameter)?;
-        }
+        self.inner
+            .write(pipe, buf, self.recovery_policy(pipe), None)
+    }
 
-        let mut bytes_transferred = 0;
-        unsafe {
-            match lib::FT_WritePipeEx(
-                self.handle,
-                0x02,
-                buf as *const _ as *const u8,
-                buf.len() as c_ulong,
-                &mut bytes_transferred,
-                std::ptr::null_mut(),
-            ) {
-                Ok(_) => (),
-                Err(e) => {
-                    self.abort_transfers(pipe)?;
-                    return Err(e);
-                }
-            }
-        }
-        Ok(bytes_transferred as usize)
+    /// Writes data to the specified pipe, using the given timeout instead of the
+    /// pipe timeout for this call only.
+    ///
+    /// See [`Device::read_timeout`] for how the timeout is applied.
+    pub fn write_timeout(&self, pipe: Pipe, buf: &[u8], timeout: Duration) -> Result<usize> {
+        self.inner
+            .write(pipe, buf, self.recovery_policy(pipe), Some(timeout))
     }
 
     /// Reads data from the specified pipe. This method will block
     /// until the transfer is complete, or the timeout is reached.
+    ///
+    /// The length of `buf` must be a multiple of the FIFO bus width of the chip
+    /// (see [`ChipType::bus_width`]), otherwise a `MisalignedLength` error is returned.
+    ///
+    /// Errors are handled according to the pipe's [`RecoveryPolicy`].
     pub fn read(&self, pipe: Pipe, buf: &mut [u8]) -> Result<usize> {
-        if !pipe.is_read_pipe() {
-            Err(D3xxError::InvalidParameter)?;
-        }
+        self.inner.read(pipe, buf, self.recovery_policy(pipe), None)
+    }
 
-        let mut bytes_transferred = 0;
-        unsafe {
-            match lib::FT_ReadPipe(
-                self.handle,
-                pipe as c_uchar,
-                buf as *mut _ as *mut u8,
-                buf.len() as c_ulong,
-                &mut bytes_transferred,
-                std::ptr::null_mut(),
-            ) {
-                Ok(_) => (),
-                Err(e) => {
-                    self.abort_transfers(pipe)?;
-                    return Err(e);
-                }
-            }
-        }
-        Ok(bytes_transferred as usize)
+    /// Reads data from the specified pipe, using the given timeout instead of the
+    /// pipe timeout for this call only.
+    ///
+    /// On Linux and macOS the timeout is passed to the driver with the transfer. The
+    /// Windows driver has no per-call timeout, so the pipe timeout is changed for the
+    /// duration of the call and restored afterwards.
+    pub fn read_timeout(&self, pipe: Pipe, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.inner
+            .read(pipe, buf, self.recovery_policy(pipe), Some(timeout))
+    }
+
+    /// Sets the recovery policy used for pipes without a pipe-specific policy.
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy) {
+        self.recovery_policy = policy;
+    }
+
+    /// Sets the recovery policy for a single pipe, overriding the device-wide policy.
+    /// Passing `None` reverts the pipe to the device-wide policy.
+    pub fn set_pipe_recovery_policy(&mut self, pipe: Pipe, policy: Option<RecoveryPolicy>) {
+        match policy {
+            Some(policy) => self.pipe_recovery_policies.insert(pipe, policy),
+            None => self.pipe_recovery_policies.remove(&pipe),
+        };
+    }
+
+    /// Get the recovery policy in effect for the given pipe.
+    pub fn recovery_policy(&self, pipe: Pipe) -> RecoveryPolicy {
+        self.pipe_recovery_policies
+            .get(&pipe)
+            .copied()
+            .unwrap_or(self.recovery_policy)
     }
 
     /// Discards any data cached in an IN pipe.
     /// If `pipe` is an OUT pipe, an `InvalidParameter` error is returned.
     pub fn flush(&self, pipe: Pipe) -> Result<()> {
-        if !pipe.is_read_pipe() {
-            Err(D3xxError::InvalidParameter)?;
-        }
-        unsafe { lib::FT_FlushPipe(self.handle, pipe as c_uchar) }
+        self.inner.flush(pipe)
     }
 
     /// Configures a timeout for the specified endpoint. Reading and writing will
     /// timeout in the event the operation hangs for the given duration.
     ///
     /// The new value is only valid as long as the device is open; re-opening the device
-    /// will reset the timeout to the default of 5 seconds.
+    /// will reset the timeout to the default of 5 seconds. Use [`OpenOptions::timeout`]
+    /// to configure the timeout each time the device is opened.
     pub fn set_timeout(&self, pipe: Pipe, timeout: Duration) -> Result<()> {
-        unsafe {
-            lib::FT_SetPipeTimeout(self.handle, pipe as c_uchar, timeout.as_millis() as c_ulong)
-        }
+        self.inner.set_timeout(pipe, timeout)
     }
 
     /// Get the timeout configured for the specified pipe.
     pub fn get_timeout(&self, pipe: Pipe) -> Result<Duration> {
-        let mut timeout_millis: c_ulong = 0;
-        unsafe {
-            lib::FT_GetPipeTimeout(self.handle, pipe as c_uchar, ptr_mut(&mut timeout_millis))?;
-        }
-        Ok(Duration::from_millis(timeout_millis as u64))
+        self.inner.timeout(pipe)
     }
 
     /// Sets streaming protocol transfer for the specified pipe. This is for
     /// applications that read or write a fixed size of data to or from the device.
     pub fn set_stream_size(&self, pipe: Pipe, stream_size: Option<u32>) -> Result<()> {
-        unsafe {
-            match stream_size {
-                Some(size) => lib::FT_SetStreamPipe(
-                    self.handle,
-                    false as c_uchar,
-                    false as c_uchar,
-                    pipe as c_uchar,
-                    size as c_ulong,
-                ),
-                None => lib::FT_ClearStreamPipe(
-                    self.handle,
-                    false as c_uchar,
-                    false as c_uchar,
-                    pipe as c_uchar,
-                ),
-            }
-        }
+        self.inner.set_stream_size(pipe, stream_size)
     }
 
     /// Aborts all pending transfers for the given pipe.
     pub fn abort_transfers(&self, pipe: Pipe) -> Result<()> {
-        unsafe { lib::FT_AbortPipe(self.handle, pipe as c_uchar) }
+        self.inner.abort_transfers(pipe)
+    }
+
+    /// Enables or disables per-pipe transfer statistics. See the [`stats`] module.
+    ///
+    /// Statistics collected so far are kept when disabled and while the device is
+    /// shared with a [`Stream`] or split halves.
+    pub fn set_stats_enabled(&self, enabled: bool) {
+        self.inner.set_stats_enabled(enabled)
+    }
+
+    /// Get the transfer statistics for a pipe, or `None` if statistics are disabled.
+    pub fn stats(&self, pipe: Pipe) -> Option<PipeStats> {
+        self.inner.stats().map(|stats| stats.snapshot(pipe))
+    }
+
+    /// Resets the transfer statistics of every pipe.
+    pub fn reset_stats(&self) {
+        if let Some(stats) = self.inner.stats.get() {
+            stats.reset();
+        }
+    }
+
+    /// Get the type of chip, as reported in the device list when the device was opened.
+    ///
+    /// Returns a `NotSupported` error if the chip type is not known.
+    pub fn chip_type(&self) -> Result<ChipType> {
+        self.inner
+            .chip
+            .ok_or_else(|| self.inner.device_error(D3xxError::NotSupported.into()))
+    }
+
+    /// Allocates an [`AlignedBuf`] suitable for transfers with this device.
+    /// See the [`buffer`] module.
+    pub fn alloc_buf(&self, capacity: usize) -> Result<AlignedBuf> {
+        AlignedBuf::with_granularity(capacity, self.inner.granularity())
+    }
+
+    /// Allocates a [`BufferPool`] suitable for transfers with this device.
+    /// See the [`buffer`] module.
+    pub fn buffer_pool(&self, buffer_size: usize, count: usize) -> Result<BufferPool> {
+        BufferPool::with_granularity(buffer_size, count, self.inner.granularity())
+    }
+
+    /// Starts streaming from an IN pipe on a background thread.
+    ///
+    /// The returned [`Stream`] yields filled buffers in the order they were received.
+    /// Reading from the same pipe through [`Device::read`] while the stream is running
+    /// is not supported. See the [`stream`] module.
+    pub fn stream(&self, pipe: Pipe, config: StreamConfig) -> Result<Stream> {
+        Stream::start(self.inner.clone(), pipe, config)
+    }
+
+    /// Get the number of bytes received from an IN pipe which have not been read yet.
+    /// If `pipe` is an OUT pipe, an `InvalidParameter` error is returned.
+    ///
+    /// Not supported by the Windows driver.
+    pub fn read_queue_status(&self, pipe: Pipe) -> Result<usize> {
+        if !pipe.is_read_pipe() {
+            Err(self
+                .inner
+                .pipe_error(pipe, D3xxError::InvalidParameter.into()))?;
+        }
+        let mut bytes: ULONG = 0;
+        unsafe {
+            lib::FT_GetReadQueueStatus(self.inner.raw(), pipe.channel(), ptr_mut(&mut bytes))
+                .map_err(|e| self.inner.pipe_error(pipe, e))?;
+        }
+        Ok(bytes as usize)
+    }
+
+    /// Get the number of bytes queued on an OUT pipe which have not been sent yet.
+    /// If `pipe` is an IN pipe, an `InvalidParameter` error is returned.
+    ///
+    /// Not supported by the Windows driver.
+    pub fn write_queue_status(&self, pipe: Pipe) -> Result<usize> {
+        if !pipe.is_write_pipe() {
+            Err(self
+                .inner
+                .pipe_error(pipe, D3xxError::InvalidParameter.into()))?;
+        }
+        let mut bytes: ULONG = 0;
+        unsafe {
+            lib::FT_GetWriteQueueStatus(self.inner.raw(), pipe.channel(), ptr_mut(&mut bytes))
+                .map_err(|e| self.inner.pipe_error(pipe, e))?;
+        }
+        Ok(bytes as usize)
+    }
+
+    /// Get the time the device may be idle before the host selectively suspends it.
+    /// A duration of zero means selective suspend is disabled.
+    ///
+    /// Not supported by the Linux driver.
+    pub fn suspend_timeout(&self) -> Result<Duration> {
+        self.inner.suspend_timeout()
+    }
+
+    /// Sets the time the device may be idle before the host selectively suspends it.
+    /// The timeout has a resolution of one second; a duration of zero disables
+    /// selective suspend.
+    ///
+    /// Not supported by the Linux driver.
+    pub fn set_suspend_timeout(&self, timeout: Duration) -> Result<()> {
+        self.inner.set_suspend_timeout(timeout)
+    }
+
+    /// Disables selective suspend until the returned guard is dropped, which
+    /// restores the previous suspend timeout. See the [`power`] module.
+    ///
+    /// Not supported by the Linux driver.
+    pub fn disable_suspend(&self) -> Result<SuspendGuard> {
+        SuspendGuard::new(self.inner.clone())
     }
 
     /// Get the USB device descriptor.
     pub fn device_descriptor(&self) -> Result<DeviceDescriptor> {
         let mut device_descriptor = DeviceDescriptor::default();
         unsafe {
-            lib::FT_GetDeviceDescriptor(self.handle, ptr_mut(&mut device_descriptor.inner))?;
+            lib::FT_GetDeviceDescriptor(self.inner.raw(), ptr_mut(&mut device_descriptor.inner))
+                .map_err(|e| self.inner.device_error(e))?;
         }
         Ok(device_descriptor)
     }
 
+    /// Reads the chip configuration stored in the device's EEPROM.
+    pub fn chip_configuration(&self) -> Result<ChipConfiguration> {
+        let mut configuration = ChipConfiguration::default();
+        unsafe {
+            lib::FT_GetChipConfiguration(self.inner.raw(), ptr_mut(&mut configuration.inner))
+                .map_err(|e| self.inner.device_error(e))?;
+        }
+        Ok(configuration)
+    }
+
     /// Power cycles the device port. This causes the device to be re-enumermated by the host.
     /// Consumes the object, meaning the device must be re-opened.
     pub fn power_cycle_port(self) -> Result<()> {
         // TODO: determine if device needs to be reopened.
-        unsafe { lib::FT_CycleDevicePort(self.handle) }
+        unsafe { lib::FT_CycleDevicePort(self.inner.raw()) }.map_err(|e| self.inner.device_error(e))
+    }
+
+    /// Get a handle which can cancel transfers on this device from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle::new(&self.inner)
+    }
+
+    /// Splits the device into a reader and a writer for each of the four channels.
+    ///
+    /// The halves share ownership of the device handle, which is closed once every
+    /// half has been dropped. Each half can be moved to a different thread; see the
+    /// [`split`] module for which operations may run concurrently.
+    ///
+    /// Each half starts out with the recovery policy configured on this device for
+    /// its pipe.
+    ///
+    /// ```no_run
+    /// # fn main() -> ft60x_rs::Result<()> {
+    /// # let device = ft60x_rs::list_devices()?[0].open()?;
+    /// let ([mut data, ..], [mut commands, ..]) = device.split();
+    /// std::thread::spawn(move || {
+    ///     let mut buf = vec![0; 4096];
+    ///     data.read(&mut buf)
+    /// });
+    /// commands.write(&[0x01, 0x02, 0x03, 0x04])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split(self) -> ([PipeReader; 4], [PipeWriter; 4]) {
+        let reader = |pipe| PipeReader::new(self.inner.clone(), pipe, self.recovery_policy(pipe));
+        let writer = |pipe| PipeWriter::new(self.inner.clone(), pipe, self.recovery_policy(pipe));
+        (
+            [
+                reader(Pipe::In0),
+                reader(Pipe::In1),
+                reader(Pipe::In2),
+                reader(Pipe::In3),
+            ],
+            [
+                writer(Pipe::Out0),
+                writer(Pipe::Out1),
+                writer(Pipe::Out2),
+                writer(Pipe::Out3),
+            ],
+        )
     }
 }
 
-impl Drop for Device {
-    fn drop(&mut self) {
-        unsafe {
-            let _ = lib::FT_Close(self.handle);
+impl AsHandle for Device {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        self.inner.handle.as_handle()
+    }
+}
+
+impl From<OwnedHandle> for Device {
+    fn from(handle: OwnedHandle) -> Self {
+        Device::from_handle(handle)
+    }
+}
+
+impl TryFrom<Device> for OwnedHandle {
+    type Error = Device;
+
+    /// Take the handle out of a device without closing it.
+    ///
+    /// Fails, returning the device, while the handle is shared with a [`Stream`],
+    /// [`SuspendGuard`], split half or any other holder created from the device.
+    fn try_from(mut device: Device) -> std::result::Result<Self, Device> {
+        match Arc::try_unwrap(device.inner) {
+            Ok(inner) => Ok(inner.handle),
+            Err(inner) => {
+                device.inner = inner;
+                Err(device)
+            }
         }
     }
 }
@@ -287,30 +629,654 @@ impl Drop for Device {
 impl Debug for Device {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Device")
-            .field("handle", &self.handle)
+            .field("handle", &self.inner.raw())
+            .field("serial_number", &self.inner.serial_number)
             .finish()
     }
 }
 
+// =============================================================================
+/// The transfer and configuration methods of [`Device`], as a trait.
+///
+/// Implemented by [`Device`] and by [`ReplayDevice`], so acquisition and analysis
+/// code written against this trait can run on hardware or offline against a
+/// capture. Each method behaves as the [`Device`] method of the same name.
+pub trait DeviceIo {
+    /// See [`Device::info`].
+    fn info(&self) -> Result<DeviceInfo>;
+
+    /// See [`Device::device_descriptor`].
+    fn device_descriptor(&self) -> Result<DeviceDescriptor>;
+
+    /// See [`Device::driver_version`].
+    fn driver_version(&self) -> Result<Version>;
+
+    /// See [`Device::chip_type`].
+    fn chip_type(&self) -> Result<ChipType>;
+
+    /// See [`Device::read`].
+    fn read(&self, pipe: Pipe, buf: &mut [u8]) -> Result<usize>;
+
+    /// See [`Device::read_timeout`].
+    fn read_timeout(&self, pipe: Pipe, buf: &mut [u8], timeout: Duration) -> Result<usize>;
+
+    /// See [`Device::write`].
+    fn write(&self, pipe: Pipe, buf: &[u8]) -> Result<usize>;
+
+    /// See [`Device::write_timeout`].
+    fn write_timeout(&self, pipe: Pipe, buf: &[u8], timeout: Duration) -> Result<usize>;
+
+    /// See [`Device::flush`].
+    fn flush(&self, pipe: Pipe) -> Result<()>;
+
+    /// See [`Device::abort_transfers`].
+    fn abort_transfers(&self, pipe: Pipe) -> Result<()>;
+
+    /// See [`Device::set_timeout`].
+    fn set_timeout(&self, pipe: Pipe, timeout: Duration) -> Result<()>;
+
+    /// See [`Device::get_timeout`].
+    fn get_timeout(&self, pipe: Pipe) -> Result<Duration>;
+
+    /// See [`Device::set_stream_size`].
+    fn set_stream_size(&self, pipe: Pipe, stream_size: Option<u32>) -> Result<()>;
+}
+
+impl DeviceIo for Device {
+    fn info(&self) -> Result<DeviceInfo> {
+        Device::info(self)
+    }
+
+    fn device_descriptor(&self) -> Result<DeviceDescriptor> {
+        Device::device_descriptor(self)
+    }
+
+    fn driver_version(&self) -> Result<Version> {
+        Device::driver_version(self)
+    }
+
+    fn chip_type(&self) -> Result<ChipType> {
+        Device::chip_type(self)
+    }
+
+    fn read(&self, pipe: Pipe, buf: &mut [u8]) -> Result<usize> {
+        Device::read(self, pipe, buf)
+    }
+
+    fn read_timeout(&self, pipe: Pipe, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        Device::read_timeout(self, pipe, buf, timeout)
+    }
+
+    fn write(&self, pipe: Pipe, buf: &[u8]) -> Result<usize> {
+        Device::write(self, pipe, buf)
+    }
+
+    fn write_timeout(&self, pipe: Pipe, buf: &[u8], timeout: Duration) -> Result<usize> {
+        Device::write_timeout(self, pipe, buf, timeout)
+    }
+
+    fn flush(&self, pipe: Pipe) -> Result<()> {
+        Device::flush(self, pipe)
+    }
+
+    fn abort_transfers(&self, pipe: Pipe) -> Result<()> {
+        Device::abort_transfers(self, pipe)
+    }
+
+    fn set_timeout(&self, pipe: Pipe, timeout: Duration) -> Result<()> {
+        Device::set_timeout(self, pipe, timeout)
+    }
+
+    fn get_timeout(&self, pipe: Pipe) -> Result<Duration> {
+        Device::get_timeout(self, pipe)
+    }
+
+    fn set_stream_size(&self, pipe: Pipe, stream_size: Option<u32>) -> Result<()> {
+        Device::set_stream_size(self, pipe, stream_size)
+    }
+}
+
+// =============================================================================
+/// An open D3XX handle, shared between a [`Device`] and its split halves.
+///
+/// The handle is closed when the last owner is dropped.
+pub(crate) struct DeviceHandle {
+    /// The handle to the D3XX device.
+    pub(crate) handle: OwnedHandle,
+    /// Serial number used to open the device, if known. Attached to errors.
+    pub(crate) serial_number: Option<String>,
+    /// The chip, if known. Transfer lengths are checked against its bus width.
+    pub(crate) chip: Option<ChipType>,
+    /// Pending cancellation requests, indexed by [`Pipe::index`].
+    pub(crate) cancelled: [AtomicBool; 8],
+    /// Number of transfers between their cancellation check and the return of their
+    /// D3XX call, indexed by [`Pipe::index`].
+    in_flight: [AtomicU32; 8],
+    /// Whether transfers are recorded in `stats`.
+    stats_enabled: AtomicBool,
+    /// Transfer statistics, allocated when first enabled.
+    stats: OnceCell<Stats>,
+    /// Pipe timeouts in milliseconds, indexed by [`Pipe::index`]. The Linux driver takes
+    /// the timeout with each transfer rather than storing it.
+    #[cfg(not(windows))]
+    timeouts: [AtomicU32; 8],
+}
+
+impl DeviceHandle {
+    /// How long [`DeviceHandle::cancel`] keeps aborting a transfer which has not
+    /// observed the cancellation.
+    const CANCEL_RETRY_LIMIT: Duration = Duration::from_millis(100);
+
+    /// Timeout used by the driver until one is configured.
+    #[cfg(not(windows))]
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub(crate) fn new(handle: OwnedHandle) -> DeviceHandle {
+        DeviceHandle {
+            handle,
+            serial_number: None,
+            chip: None,
+            cancelled: Default::default(),
+            in_flight: Default::default(),
+            stats_enabled: AtomicBool::new(false),
+            stats: OnceCell::new(),
+            #[cfg(not(windows))]
+            timeouts: std::array::from_fn(|_| {
+                AtomicU32::new(Self::DEFAULT_TIMEOUT.as_millis() as u32)
+            }),
+        }
+    }
+
+    /// The raw handle, for passing to D3XX functions.
+    pub(crate) fn raw(&self) -> RawHandle {
+        self.handle.as_raw_handle()
+    }
+
+    /// Writes data to the specified pipe, recovering from errors using the given policy.
+    ///
+    /// If `timeout` is given it replaces the pipe timeout for this call only.
+    pub(crate) fn write(
+        &self,
+        pipe: Pipe,
+        buf: &[u8],
+        policy: RecoveryPolicy,
+        timeout: Option<Duration>,
+    ) -> Result<usize> {
+        if !pipe.is_write_pipe() {
+            Err(self.pipe_error(pipe, D3xxError::InvalidParameter.into()))?;
+        }
+        self.check_len(pipe, buf.len())?;
+
+        self.with_timeout(pipe, timeout, |timeout| {
+            self.transfer(pipe, buf.len(), policy, |offset| {
+                let mut bytes_transferred = 0;
+                let result = unsafe {
+                    self.write_pipe(pipe, &buf[offset..], &mut bytes_transferred, timeout)
+                };
+                (result, bytes_transferred as usize)
+            })
+        })
+    }
+
+    /// Reads data from the specified pipe, recovering from errors using the given policy.
+    ///
+    /// If `timeout` is given it replaces the pipe timeout for this call only.
+    pub(crate) fn read(
+        &self,
+        pipe: Pipe,
+        buf: &mut [u8],
+        policy: RecoveryPolicy,
+        timeout: Option<Duration>,
+    ) -> Result<usize> {
+        if !pipe.is_read_pipe() {
+            Err(self.pipe_error(pipe, D3xxError::InvalidParameter.into()))?;
+        }
+        self.check_len(pipe, buf.len())?;
+
+        let len = buf.len();
+        self.with_timeout(pipe, timeout, |timeout| {
+            self.transfer(pipe, len, policy, |offset| {
+                let mut bytes_transferred = 0;
+                let result = unsafe {
+                    self.read_pipe(pipe, &mut buf[offset..], &mut bytes_transferred, timeout)
+                };
+                (result, bytes_transferred as usize)
+            })
+        })
+    }
+
+    /// Checks that a transfer length is a multiple of the bus width of the chip.
+    fn check_len(&self, pipe: Pipe, len: usize) -> Result<()> {
+        if !len.is_multiple_of(self.granularity()) {
+            Err(self.pipe_error(pipe, D3xxError::MisalignedLength.into()))?;
+        }
+        Ok(())
+    }
+
+    /// The granularity of transfer lengths, or 1 if the chip is unknown.
+    pub(crate) fn granularity(&self) -> usize {
+        self.chip.map_or(1, |chip| chip.bus_width())
+    }
+
+    /// Runs `f` with the timeout in milliseconds to pass to the transfer functions.
+    ///
+    /// The Windows driver only supports pipe timeouts, so a per-call timeout is
+    /// applied by changing the pipe timeout for the duration of the call. Failing to
+    /// restore the previous timeout is ignored, so that the result of the transfer,
+    /// and the data it moved, is not lost.
+    #[cfg(windows)]
+    fn with_timeout<T>(
+        &self,
+        pipe: Pipe,
+        timeout: Option<Duration>,
+        f: impl FnOnce(types::DWORD) -> Result<T>,
+    ) -> Result<T> {
+        let Some(timeout) = timeout else {
+            return f(0);
+        };
+        let previous = self.timeout(pipe)?;
+        self.set_timeout(pipe, timeout)?;
+        let result = f(0);
+        if let Err(_e) = self.set_timeout(pipe, previous) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(%pipe, error = %_e, "failed to restore the pipe timeout");
+        }
+        result
+    }
+
+    /// Runs `f` with the timeout in milliseconds to pass to the transfer functions.
+    #[cfg(not(windows))]
+    fn with_timeout<T>(
+        &self,
+        pipe: Pipe,
+        timeout: Option<Duration>,
+        f: impl FnOnce(types::DWORD) -> Result<T>,
+    ) -> Result<T> {
+        let millis = match timeout {
+            Some(timeout) => duration_to_millis(timeout),
+            None => self.timeouts[pipe.index()].load(Ordering::Relaxed),
+        };
+        f(millis)
+    }
+
+    /// Makes a single write call. The pipe timeout applies; `_timeout` is unused.
+    #[cfg(windows)]
+    unsafe fn write_pipe(
+        &self,
+        pipe: Pipe,
+        buf: &[u8],
+        bytes_transferred: &mut ULONG,
+        _timeout: types::DWORD,
+    ) -> Result<()> {
+        let len = buf.len().min(ULONG::MAX as usize) as ULONG;
+        unsafe {
+            lib::FT_WritePipeEx(
+                self.raw(),
+                pipe as c_uchar,
+                buf.as_ptr(),
+                len,
+                bytes_transferred,
+                null_mut(),
+            )
+        }
+    }
+
+    /// Makes a single write call on the pipe's FIFO channel.
+    #[cfg(not(windows))]
+    unsafe fn write_pipe(
+        &self,
+        pipe: Pipe,
+        buf: &[u8],
+        bytes_transferred: &mut ULONG,
+        timeout: types::DWORD,
+    ) -> Result<()> {
+        let len = buf.len().min(ULONG::MAX as usize) as ULONG;
+        unsafe {
+            lib::FT_WritePipeEx(
+                self.raw(),
+                pipe.channel(),
+                buf.as_ptr(),
+                len,
+                bytes_transferred,
+                timeout,
+            )
+        }
+    }
+
+    /// Makes a single read call. The pipe timeout applies; `_timeout` is unused.
+    #[cfg(windows)]
+    unsafe fn read_pipe(
+        &self,
+        pipe: Pipe,
+        buf: &mut [u8],
+        bytes_transferred: &mut ULONG,
+        _timeout: types::DWORD,
+    ) -> Result<()> {
+        let len = buf.len().min(ULONG::MAX as usize) as ULONG;
+        unsafe {
+            lib::FT_ReadPipe(
+                self.raw(),
+                pipe as c_uchar,
+                buf.as_mut_ptr(),
+                len,
+                bytes_transferred,
+                null_mut(),
+            )
+        }
+    }
+
+    /// Makes a single read call on the pipe's FIFO channel.
+    #[cfg(not(windows))]
+    unsafe fn read_pipe(
+        &self,
+        pipe: Pipe,
+        buf: &mut [u8],
+        bytes_transferred: &mut ULONG,
+        timeout: types::DWORD,
+    ) -> Result<()> {
+        let len = buf.len().min(ULONG::MAX as usize) as ULONG;
+        unsafe {
+            lib::FT_ReadPipeEx(
+                self.raw(),
+                pipe.channel(),
+                buf.as_mut_ptr(),
+                len,
+                bytes_transferred,
+                timeout,
+            )
+        }
+    }
+
+    /// Runs a transfer, applying the recovery policy if it fails.
+    ///
+    /// `op` is called with the number of bytes transferred so far, and returns the
+    /// result of the D3XX call along with the number of bytes it transferred.
+    fn transfer(
+        &self,
+        pipe: Pipe,
+        len: usize,
+        policy: RecoveryPolicy,
+        op: impl FnMut(usize) -> (Result<()>, usize),
+    ) -> Result<usize> {
+        let Some(stats) = self.stats() else {
+            return self.transfer_with_recovery(pipe, len, policy, op);
+        };
+        let start = Instant::now();
+        let result = self.transfer_with_recovery(pipe, len, policy, op);
+        stats.record(pipe, start, len, result.as_ref().copied());
+        result
+    }
+
+    /// Implements [`DeviceHandle::transfer`] without recording statistics.
+    fn transfer_with_recovery(
+        &self,
+        pipe: Pipe,
+        len: usize,
+        policy: RecoveryPolicy,
+        mut op: impl FnMut(usize) -> (Result<()>, usize),
+    ) -> Result<usize> {
+        let mut total = 0;
+        let mut attempt = 0;
+        loop {
+            // Marked in flight before checking for cancellation, so a canceller which
+            // finds no pending request here keeps aborting until the call observes it.
+            let in_flight = &self.in_flight[pipe.index()];
+            in_flight.fetch_add(1, Ordering::SeqCst);
+            if self.take_cancelled(pipe) {
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                let e = self.pipe_error(pipe, D3xxError::Cancelled.into());
+                return Err(e.with_bytes_transferred(total));
+            }
+            let (result, n) = op(total);
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            total += n;
+            let e = match result {
+                Ok(()) => return Ok(total),
+                Err(e) => e,
+            };
+
+            // The pipe has already been aborted by the canceller.
+            if self.take_cancelled(pipe) {
+                let e = self.pipe_error(pipe, D3xxError::Cancelled.into());
+                return Err(e.with_bytes_transferred(total));
+            }
+
+            if let RecoveryPolicy::Retry { attempts, backoff } = policy {
+                if e.is_retryable() && attempt < attempts && total < len {
+                    std::thread::sleep(backoff.saturating_mul(2u32.saturating_pow(attempt)));
+                    attempt += 1;
+                    continue;
+                }
+            }
+
+            self.recover(pipe, policy);
+            return Err(self.pipe_error(pipe, e).with_bytes_transferred(total));
+        }
+    }
+
+    /// The transfer statistics, if enabled.
+    pub(crate) fn stats(&self) -> Option<&Stats> {
+        if self.stats_enabled.load(Ordering::Relaxed) {
+            self.stats.get()
+        } else {
+            None
+        }
+    }
+
+    /// Enables or disables recording of transfer statistics. Statistics are kept
+    /// while disabled.
+    pub(crate) fn set_stats_enabled(&self, enabled: bool) {
+        if enabled {
+            self.stats.get_or_init(Stats::new);
+        }
+        self.stats_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Requests cancellation of the current or next transfer on the given pipe, and
+    /// aborts any transfer currently blocked on it.
+    ///
+    /// A transfer may have checked for cancellation but not yet entered the driver, so
+    /// a single abort can miss it. While a transfer is in flight and has not observed
+    /// the request, the pipe is aborted again every millisecond, for up to
+    /// [`CANCEL_RETRY_LIMIT`](Self::CANCEL_RETRY_LIMIT).
+    pub(crate) fn cancel(&self, pipe: Pipe) -> Result<()> {
+        let cancelled = &self.cancelled[pipe.index()];
+        cancelled.store(true, Ordering::SeqCst);
+        self.abort_transfers(pipe)?;
+        let start = Instant::now();
+        while cancelled.load(Ordering::SeqCst)
+            && self.in_flight[pipe.index()].load(Ordering::SeqCst) > 0
+            && start.elapsed() < Self::CANCEL_RETRY_LIMIT
+        {
+            std::thread::sleep(Duration::from_millis(1));
+            self.abort_transfers(pipe)?;
+        }
+        Ok(())
+    }
+
+    /// Clears a pending cancellation request, returning whether there was one.
+    pub(crate) fn take_cancelled(&self, pipe: Pipe) -> bool {
+        self.cancelled[pipe.index()].swap(false, Ordering::SeqCst)
+    }
+
+    /// Puts the pipe back into a usable state after a failed transfer.
+    ///
+    /// Failures here are ignored; the error from the transfer is more useful to the caller.
+    fn recover(&self, pipe: Pipe, policy: RecoveryPolicy) {
+        match policy {
+            RecoveryPolicy::Abort | RecoveryPolicy::Retry { .. } => {
+                let _ = self.abort_transfers(pipe);
+            }
+            RecoveryPolicy::Flush => {
+                let _ = self.abort_transfers(pipe);
+                if pipe.is_read_pipe() {
+                    let _ = self.flush(pipe);
+                }
+            }
+            RecoveryPolicy::None => (),
+        }
+    }
+
+    /// Discards any data cached in an IN pipe.
+    pub(crate) fn flush(&self, pipe: Pipe) -> Result<()> {
+        if !pipe.is_read_pipe() {
+            Err(D3xxError::InvalidParameter)?;
+        }
+        unsafe { lib::FT_FlushPipe(self.raw(), pipe as c_uchar) }
+            .map_err(|e| self.pipe_error(pipe, e))
+    }
+
+    /// Configures a timeout for the specified pipe.
+    #[cfg(windows)]
+    pub(crate) fn set_timeout(&self, pipe: Pipe, timeout: Duration) -> Result<()> {
+        unsafe { lib::FT_SetPipeTimeout(self.raw(), pipe as c_uchar, duration_to_millis(timeout)) }
+            .map_err(|e| self.pipe_error(pipe, e))
+    }
+
+    /// Configures a timeout for the specified pipe. The timeout is passed to the driver
+    /// with each transfer.
+    #[cfg(not(windows))]
+    pub(crate) fn set_timeout(&self, pipe: Pipe, timeout: Duration) -> Result<()> {
+        self.timeouts[pipe.index()].store(duration_to_millis(timeout), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Get the timeout configured for the specified pipe.
+    #[cfg(not(windows))]
+    pub(crate) fn timeout(&self, pipe: Pipe) -> Result<Duration> {
+        let millis = self.timeouts[pipe.index()].load(Ordering::Relaxed);
+        Ok(Duration::from_millis(millis as u64))
+    }
+
+    /// Get the timeout configured for the specified pipe.
+    #[cfg(windows)]
+    pub(crate) fn timeout(&self, pipe: Pipe) -> Result<Duration> {
+        let mut timeout_millis: ULONG = 0;
+        unsafe {
+            lib::FT_GetPipeTimeout(self.raw(), pipe as c_uchar, ptr_mut(&mut timeout_millis))
+                .map_err(|e| self.pipe_error(pipe, e))?;
+        }
+        Ok(Duration::from_millis(timeout_millis as u64))
+    }
+
+    /// Get the suspend timeout.
+    pub(crate) fn suspend_timeout(&self) -> Result<Duration> {
+        let mut seconds: ULONG = 0;
+        unsafe {
+            lib::FT_GetSuspendTimeout(self.raw(), ptr_mut(&mut seconds))
+                .map_err(|e| self.device_error(e))?;
+        }
+        Ok(Duration::from_secs(seconds as u64))
+    }
+
+    /// Sets the suspend timeout, rounding up to whole seconds.
+    pub(crate) fn set_suspend_timeout(&self, timeout: Duration) -> Result<()> {
+        let seconds = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        let seconds = seconds.try_into().unwrap_or(ULONG::MAX);
+        unsafe { lib::FT_SetSuspendTimeout(self.raw(), seconds) }.map_err(|e| self.device_error(e))
+    }
+
+    /// Enables or disables the streaming protocol for the specified pipe.
+    pub(crate) fn set_stream_size(&self, pipe: Pipe, stream_size: Option<u32>) -> Result<()> {
+        unsafe {
+            match stream_size {
+                Some(size) => lib::FT_SetStreamPipe(
+                    self.raw(),
+                    false as c_uchar,
+                    false as c_uchar,
+                    pipe as c_uchar,
+                    size as ULONG,
+                ),
+                None => lib::FT_ClearStreamPipe(
+                    self.raw(),
+                    false as c_uchar,
+                    false as c_uchar,
+                    pipe as c_uchar,
+                ),
+            }
+        }
+        .map_err(|e| self.pipe_error(pipe, e))
+    }
+
+    /// Aborts all pending transfers for the given pipe.
+    pub(crate) fn abort_transfers(&self, pipe: Pipe) -> Result<()> {
+        unsafe { lib::FT_AbortPipe(self.raw(), pipe as c_uchar) }
+            .map_err(|e| self.pipe_error(pipe, e))
+    }
+
+    /// Attaches the serial number of this device to an error.
+    pub(crate) fn device_error(&self, e: Error) -> Error {
+        e.with_serial_number(self.serial_number.as_deref())
+    }
+
+    /// Attaches the serial number of this device and the given pipe to an error.
+    pub(crate) fn pipe_error(&self, pipe: Pipe, e: Error) -> Error {
+        self.device_error(e).with_pipe(pipe)
+    }
+}
+
 // =============================================================================
 /// Holds device information regarding a D3XX device attached to the system.
-#[derive(Clone, Debug, Default)]
+///
+/// This is a snapshot taken when the device list was built; it is not updated
+/// when the device is opened, closed or disconnected.
+///
+/// With the `serde` feature, serializes as the struct below. The raw handle is not
+/// serialized; a deserialized `DeviceInfo` has no [`raw_handle`](Self::raw_handle).
+///
+/// ```json
+/// {
+///   "index": 0,
+///   "flags": 4,
+///   "type": 601,
+///   "vendor_id": 1027,
+///   "product_id": 24607,
+///   "location_identifier": 2,
+///   "serial_number": "000000000001",
+///   "description": "FTDI SuperSpeed-FIFO Bridge",
+///   "is_open": false
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeviceInfo {
     /// Index in the D3XX device list. This value changes when the list is rebuilt!
     index: usize,
-    inner: types::FT_DEVICE_LIST_INFO_NODE,
+    flags: u32,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    type_: u32,
+    vendor_id: u16,
+    product_id: u16,
+    location_identifier: u32,
+    serial_number: String,
+    description: String,
+    is_open: bool,
+    /// Raw handle, stored as an address so the struct can be sent between threads.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    handle: usize,
 }
 
 impl DeviceInfo {
     /// Create a new DeviceInfo object from a raw value. The index is the index in the D3XX
     /// device info list.
     fn new(index: usize, node: types::FT_DEVICE_LIST_INFO_NODE) -> DeviceInfo {
-        DeviceInfo { index, inner: node }
+        DeviceInfo {
+            index,
+            flags: node.Flags as _,
+            type_: node.Type as _,
+            vendor_id: ((node.ID >> 16) & 0xFFFF) as _,
+            product_id: (node.ID & 0xFFFF) as _,
+            location_identifier: node.LocId as _,
+            serial_number: types::fixed_c_str_to_string(&node.SerialNumber),
+            description: types::fixed_c_str_to_string(&node.Description),
+            is_open: !node.ftHandle.is_null(),
+            handle: node.ftHandle as usize,
+        }
     }
 
     /// Attempts to open the device represented by this struct.
     pub fn open(&self) -> Result<Device> {
-        Device::open(&self)
+        Device::open(self)
     }
 
     /// Gets the index of this device in the current D3XX device list.
@@ -320,60 +1286,104 @@ impl DeviceInfo {
 
     /// Bit flags for USB3 or USB2 connection, etc.
     pub fn flags(&self) -> u32 {
-        self.inner.Flags as _
+        self.flags
+    }
+
+    /// The speed of the USB connection given by [`flags`](Self::flags), if it is known.
+    pub fn link_speed(&self) -> Option<LinkSpeed> {
+        if self.flags & constants::FT_FLAGS_SUPERSPEED != 0 {
+            Some(LinkSpeed::SuperSpeed)
+        } else if self.flags & constants::FT_FLAGS_HISPEED != 0 {
+            Some(LinkSpeed::HighSpeed)
+        } else {
+            None
+        }
     }
 
     /// Device type.
     pub fn type_(&self) -> u32 {
-        self.inner.Type as _
+        self.type_
+    }
+
+    /// The type of chip, if it is known.
+    pub fn chip_type(&self) -> Option<ChipType> {
+        ChipType::from_type(self.type_).or_else(|| ChipType::from_product_id(self.product_id))
     }
 
     /// Vendor ID.
     pub fn vendor_id(&self) -> u16 {
-        ((self.inner.ID >> 16) & 0xFFFF) as _
+        self.vendor_id
     }
 
     /// Product ID.
     pub fn product_id(&self) -> u16 {
-        (self.inner.ID & 0xFFFF) as _
+        self.product_id
     }
 
     /// Location identifier.
     pub fn location_identifier(&self) -> u32 {
-        self.inner.LocId as _
+        self.location_identifier
     }
 
     /// Device description.
-    pub fn description(&self) -> Result<String> {
-        types::c_str_to_string(&self.inner.Description)
+    pub fn description(&self) -> &str {
+        &self.description
     }
 
     /// Device serial number.
-    pub fn serial_number(&self) -> Result<String> {
-        types::c_str_to_string(&self.inner.SerialNumber)
+    pub fn serial_number(&self) -> &str {
+        &self.serial_number
     }
 
     /// Raw handle to the device.
     /// Returns `None` if the device is not opened, or `Some(handle)` if the device
     /// is currently open.
+    ///
+    /// The handle is owned by whoever opened the device, and is only useful for
+    /// comparison; see [`handle`] for safe access to the handle of a [`Device`].
     pub fn raw_handle(&self) -> Option<types::FT_HANDLE> {
-        if self.inner.ftHandle as usize == 0 {
+        if self.handle == 0 {
             None
         } else {
-            Some(self.inner.ftHandle)
+            Some(self.handle as types::FT_HANDLE)
         }
     }
 
     /// Checks if the device is currently in use.
     pub fn is_open(&self) -> bool {
-        self.raw_handle().is_some()
+        self.is_open
     }
 }
 
 // =============================================================================
 
 /// Holds information regarding a USB device.
+///
+/// With the `serde` feature, serializes as the fields of the USB device descriptor,
+/// in snake case and without the length and descriptor type:
+///
+/// ```json
+/// {
+///   "usb_specification_number": 800,
+///   "class_code": 0,
+///   "subclass_code": 0,
+///   "protocol_code": 0,
+///   "max_packet_size": 9,
+///   "vendor_id": 1027,
+///   "product_id": 24607,
+///   "release_number": 0,
+///   "manufacturer_index": 1,
+///   "product_index": 2,
+///   "serial_number_index": 3,
+///   "num_configurations": 1
+/// }
+/// ```
 #[derive(Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "DescriptorFields", into = "DescriptorFields")
+)]
 pub struct DeviceDescriptor {
     inner: types::FT_DEVICE_DESCRIPTOR,
 }
@@ -441,9 +1451,265 @@ impl Debug for DeviceDescriptor {
     }
 }
 
+/// The serialized form of a [`DeviceDescriptor`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DescriptorFields {
+    usb_specification_number: u16,
+    class_code: u8,
+    subclass_code: u8,
+    protocol_code: u8,
+    max_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    release_number: u16,
+    manufacturer_index: u8,
+    product_index: u8,
+    serial_number_index: u8,
+    num_configurations: u8,
+}
+
+#[cfg(feature = "serde")]
+impl From<DeviceDescriptor> for DescriptorFields {
+    fn from(descriptor: DeviceDescriptor) -> Self {
+        let d = descriptor.inner;
+        Self {
+            usb_specification_number: d.bcdUSB,
+            class_code: d.bDeviceClass,
+            subclass_code: d.bDeviceSubClass,
+            protocol_code: d.bDeviceProtocol,
+            max_packet_size: d.bMaxPacketSize0,
+            vendor_id: d.idVendor,
+            product_id: d.idProduct,
+            release_number: d.bcdDevice,
+            manufacturer_index: d.iManufacturer,
+            product_index: d.iProduct,
+            serial_number_index: d.iSerialNumber,
+            num_configurations: d.bNumConfigurations,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<DescriptorFields> for DeviceDescriptor {
+    fn from(fields: DescriptorFields) -> Self {
+        Self {
+            inner: types::FT_DEVICE_DESCRIPTOR {
+                bLength: std::mem::size_of::<types::FT_DEVICE_DESCRIPTOR>() as _,
+                bDescriptorType: constants::FT_DEVICE_DESCRIPTOR_TYPE as _,
+                bcdUSB: fields.usb_specification_number,
+                bDeviceClass: fields.class_code,
+                bDeviceSubClass: fields.subclass_code,
+                bDeviceProtocol: fields.protocol_code,
+                bMaxPacketSize0: fields.max_packet_size,
+                idVendor: fields.vendor_id,
+                idProduct: fields.product_id,
+                bcdDevice: fields.release_number,
+                iManufacturer: fields.manufacturer_index,
+                iProduct: fields.product_index,
+                iSerialNumber: fields.serial_number_index,
+                bNumConfigurations: fields.num_configurations,
+            },
+        }
+    }
+}
+
+// =============================================================================
+
+/// The configuration of an FT600 or FT601 chip, read with
+/// [`Device::chip_configuration`].
+///
+/// The enumerated settings are returned as stored; the `CONFIGURATION_*` constants
+/// in [`raw`] give their meanings.
+#[derive(Default, Clone)]
+pub struct ChipConfiguration {
+    inner: types::FT_60XCONFIGURATION,
+}
+
+impl ChipConfiguration {
+    /// The vendor ID the device enumerates with.
+    pub fn vendor_id(&self) -> u16 {
+        self.inner.VendorID
+    }
+
+    /// The product ID the device enumerates with.
+    pub fn product_id(&self) -> u16 {
+        self.inner.ProductID
+    }
+
+    /// The manufacturer, product and serial number strings, decoded from the USB
+    /// string descriptors stored in the configuration.
+    pub fn strings(&self) -> Vec<String> {
+        let mut strings = Vec::new();
+        let mut rest = &self.inner.StringDescriptors[..];
+        while let [len, 0x03, ..] = *rest {
+            let Some(descriptor) = rest.get(2..len as usize) else {
+                break;
+            };
+            let units = descriptor
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]));
+            strings.push(
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or('\u{fffd}'))
+                    .collect(),
+            );
+            rest = &rest[len as usize..];
+        }
+        strings
+    }
+
+    /// The attributes of the USB configuration descriptor, such as self-powered and
+    /// remote wakeup.
+    pub fn power_attributes(&self) -> u8 {
+        self.inner.PowerAttributes
+    }
+
+    /// The maximum power drawn from the bus, as given in the USB configuration
+    /// descriptor.
+    pub fn power_consumption(&self) -> u16 {
+        self.inner.PowerConsumption
+    }
+
+    /// The FIFO clock setting.
+    pub fn fifo_clock(&self) -> u8 {
+        self.inner.FIFOClock
+    }
+
+    /// The FIFO clock frequency in MHz, if the setting is known.
+    pub fn fifo_clock_mhz(&self) -> Option<u32> {
+        match self.inner.FIFOClock {
+            0 => Some(100),
+            1 => Some(66),
+            2 => Some(50),
+            3 => Some(40),
+            _ => None,
+        }
+    }
+
+    /// The FIFO mode setting: 245 or 600 mode.
+    pub fn fifo_mode(&self) -> u8 {
+        self.inner.FIFOMode
+    }
+
+    /// The channel configuration setting: the number of channels and their pipes.
+    pub fn channel_config(&self) -> u8 {
+        self.inner.ChannelConfig
+    }
+
+    /// The optional feature bits.
+    pub fn optional_features(&self) -> u16 {
+        self.inner.OptionalFeatureSupport
+    }
+
+    /// The GPIO configuration for battery charging detection.
+    pub fn battery_charging_gpio(&self) -> u8 {
+        self.inner.BatteryChargingGPIOConfig
+    }
+
+    /// The result of flash EEPROM detection, reported by the chip.
+    pub fn flash_eeprom_detection(&self) -> u8 {
+        self.inner.FlashEEPROMDetection
+    }
+
+    /// The MSIO control setting.
+    pub fn msio_control(&self) -> u32 {
+        self.inner.MSIO_Control
+    }
+
+    /// The GPIO control setting.
+    pub fn gpio_control(&self) -> u32 {
+        self.inner.GPIO_Control
+    }
+
+    /// The raw configuration, e.g. to modify and write with
+    /// [`raw::FT_SetChipConfiguration`].
+    pub fn to_raw(&self) -> types::FT_60XCONFIGURATION {
+        self.inner
+    }
+}
+
+impl Debug for ChipConfiguration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// Converts a timeout to the milliseconds expected by the driver, saturating if it
+/// does not fit.
+fn duration_to_millis(timeout: Duration) -> ULONG {
+    timeout.as_millis().try_into().unwrap_or(ULONG::MAX)
+}
+
+// =============================================================================
+/// The type of FTDI chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChipType {
+    /// FT600, with a 16-bit FIFO bus.
+    Ft600,
+    /// FT601, with a 32-bit FIFO bus.
+    Ft601,
+}
+
+impl ChipType {
+    /// Width of the FIFO bus in bytes. Transfer lengths must be a multiple of this.
+    pub fn bus_width(&self) -> usize {
+        match self {
+            ChipType::Ft600 => 2,
+            ChipType::Ft601 => 4,
+        }
+    }
+
+    /// Get the chip type from the device type reported in the device list.
+    fn from_type(type_: u32) -> Option<ChipType> {
+        match type_ {
+            constants::FT_DEVICE_600 => Some(ChipType::Ft600),
+            constants::FT_DEVICE_601 => Some(ChipType::Ft601),
+            _ => None,
+        }
+    }
+
+    /// Get the chip type from the default FTDI product ID.
+    fn from_product_id(product_id: u16) -> Option<ChipType> {
+        match product_id {
+            0x601E => Some(ChipType::Ft600),
+            0x601F => Some(ChipType::Ft601),
+            _ => None,
+        }
+    }
+}
+
+// =============================================================================
+/// The speed of a device's USB connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkSpeed {
+    /// USB2 high speed.
+    HighSpeed,
+    /// USB3 super speed.
+    SuperSpeed,
+}
+
+impl LinkSpeed {
+    /// The signalling rate of the link.
+    pub fn bits_per_second(&self) -> u64 {
+        match self {
+            LinkSpeed::HighSpeed => 480_000_000,
+            LinkSpeed::SuperSpeed => 5_000_000_000,
+        }
+    }
+
+    /// The USB generation, `"USB2"` or `"USB3"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkSpeed::HighSpeed => "USB2",
+            LinkSpeed::SuperSpeed => "USB3",
+        }
+    }
+}
+
 // =============================================================================
 /// Represents a pipe used for communication with a D3XX device.
-#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash)]
 pub enum Pipe {
     /// Input pipe 0 (0x82).
     In0 = 0x82,
@@ -479,6 +1745,37 @@ impl Pipe {
             Pipe::Out0 | Pipe::Out1 | Pipe::Out2 | Pipe::Out3 => true,
         }
     }
+
+    /// All pipes, IN pipes first.
+    pub const ALL: [Pipe; 8] = [
+        Pipe::In0,
+        Pipe::In1,
+        Pipe::In2,
+        Pipe::In3,
+        Pipe::Out0,
+        Pipe::Out1,
+        Pipe::Out2,
+        Pipe::Out3,
+    ];
+
+    /// The FIFO channel (0 to 3) the pipe belongs to.
+    pub fn channel(&self) -> u8 {
+        (*self as u8 & 0x0f) - 2
+    }
+
+    /// Position of the pipe in [`Pipe::ALL`]. Used to index per-pipe tables.
+    pub(crate) fn index(&self) -> usize {
+        match self {
+            Pipe::In0 => 0,
+            Pipe::In1 => 1,
+            Pipe::In2 => 2,
+            Pipe::In3 => 3,
+            Pipe::Out0 => 4,
+            Pipe::Out1 => 5,
+            Pipe::Out2 => 6,
+            Pipe::Out3 => 7,
+        }
+    }
 }
 
 impl Debug for Pipe {
@@ -497,6 +1794,46 @@ impl Debug for Pipe {
     }
 }
 
+impl std::fmt::Display for Pipe {
+    /// Formats the pipe by name, e.g. `In0`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::In0 => "In0",
+            Self::In1 => "In1",
+            Self::In2 => "In2",
+            Self::In3 => "In3",
+            Self::Out0 => "Out0",
+            Self::Out1 => "Out1",
+            Self::Out2 => "Out2",
+            Self::Out3 => "Out3",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::str::FromStr for Pipe {
+    type Err = Error;
+
+    /// Parse a pipe from its name (case-insensitive, e.g. `in0`) or its endpoint
+    /// address in decimal or hexadecimal (e.g. `0x82`).
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(pipe) = Pipe::ALL
+            .into_iter()
+            .find(|pipe| pipe.to_string().eq_ignore_ascii_case(s))
+        {
+            return Ok(pipe);
+        }
+        let address = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => s.parse(),
+        };
+        address
+            .ok()
+            .and_then(|address| Pipe::ALL.into_iter().find(|&pipe| pipe as u8 == address))
+            .ok_or_else(|| D3xxError::InvalidParameter.into())
+    }
+}
+
 impl From<u8> for Pipe {
     /// Convert from a raw pipe ID to a `Pipe` enum.
     ///
@@ -518,6 +1855,8 @@ impl From<u8> for Pipe {
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum PipeType {
     /// USB control pipe
     Control = 0,
@@ -545,8 +1884,44 @@ impl From<u8> for PipeType {
     }
 }
 
+/// Determines how [`Device::read`] and [`Device::write`] handle a failed transfer.
+///
+/// Regardless of policy, the returned [`Error`] reports the number of bytes
+/// transferred before the failure through [`Error::bytes_transferred`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Abort all pending transfers on the pipe.
+    #[default]
+    Abort,
+    /// Abort all pending transfers on the pipe, and discard any data cached in
+    /// the pipe if it is an IN pipe.
+    Flush,
+    /// Retry the remainder of the transfer if the error is retryable (see
+    /// [`Error::is_retryable`]), waiting `backoff` before the first retry and
+    /// doubling the wait after each subsequent attempt. Data received before a
+    /// retry is kept. Once `attempts` retries are exhausted, or if the error is
+    /// not retryable, the pipe is aborted as with [`RecoveryPolicy::Abort`].
+    Retry { attempts: u32, backoff: Duration },
+    /// Return the error without touching the pipe.
+    None,
+}
+
 /// Stores information about a pipe.
+///
+/// With the `serde` feature, serializes with the [`PipeType`] in lowercase and the
+/// raw pipe ID, e.g. `130` for [`Pipe::In0`]:
+///
+/// ```json
+/// { "type": "bulk", "pipe_id": 130, "maximum_packet_size": 1024, "interval": 0 }
+/// ```
+///
+/// Deserializing fails if `pipe_id` is not the address of a [`Pipe`].
 #[derive(Default, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "PipeInfoFields", into = "PipeInfoFields")
+)]
 pub struct PipeInfo {
     inner: types::FT_PIPE_INFORMATION,
 }
@@ -559,7 +1934,7 @@ impl PipeInfo {
 
     /// Get the pipe.
     pub fn pipe(&self) -> Pipe {
-        Pipe::from(self.inner.PipeID as u8)
+        Pipe::from(self.inner.PipeID)
     }
 
     /// Get the maximum transfer size for this pipe.
@@ -579,10 +1954,61 @@ impl Debug for PipeInfo {
     }
 }
 
+/// The serialized form of a [`PipeInfo`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PipeInfoFields {
+    #[serde(rename = "type")]
+    type_: PipeType,
+    pipe_id: u8,
+    maximum_packet_size: u16,
+    interval: u8,
+}
+
+#[cfg(feature = "serde")]
+impl From<PipeInfo> for PipeInfoFields {
+    fn from(info: PipeInfo) -> Self {
+        Self {
+            type_: info.type_(),
+            pipe_id: info.inner.PipeID,
+            maximum_packet_size: info.inner.MaximumPacketSize,
+            interval: info.inner.Interval,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<PipeInfoFields> for PipeInfo {
+    type Error = String;
+
+    /// Fails if `pipe_id` is not the address of a [`Pipe`], which
+    /// [`PipeInfo::pipe`] would panic on.
+    fn try_from(fields: PipeInfoFields) -> std::result::Result<Self, String> {
+        if !Pipe::ALL
+            .into_iter()
+            .any(|pipe| pipe as u8 == fields.pipe_id)
+        {
+            return Err(format!("invalid pipe ID {:#04x}", fields.pipe_id));
+        }
+        Ok(Self {
+            inner: types::FT_PIPE_INFORMATION {
+                PipeType: fields.type_ as _,
+                PipeID: fields.pipe_id,
+                MaximumPacketSize: fields.maximum_packet_size,
+                Interval: fields.interval,
+            },
+        })
+    }
+}
+
 // =============================================================================
 
 /// Represents a D3XX driver or library version number.
+///
+/// With the `serde` feature, serializes as
+/// `{ "major": 1, "minor": 3, "svn": 0, "build": 10 }`.
 #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Version {
     major: u8,
     minor: u8,
@@ -622,10 +2048,21 @@ impl Version {
     }
 }
 
+impl std::fmt::Display for Version {
+    /// Formats the version as `major.minor.svn.build`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.svn, self.build
+        )
+    }
+}
+
 // =============================================================================
 /// Get the number of D3XX devices connected to the system.
 pub fn device_count() -> Result<u32> {
-    let mut n: c_ulong = 0;
+    let mut n: ULONG = 0;
     unsafe {
         lib::FT_ListDevices(ptr_mut(&mut n), null_mut(), constants::FT_LIST_NUMBER_ONLY)?;
     }
@@ -633,25 +2070,33 @@ pub fn device_count() -> Result<u32> {
 }
 
 /// Get information about all D3XX devices connected to the system.
+///
+/// This rebuilds the D3XX device list, invalidating the indices of any
+/// [`DeviceInfo`] obtained previously.
 pub fn list_devices() -> Result<Vec<DeviceInfo>> {
-    const MAX_DEVICES: usize = 32;
-    let mut num_devices = 0;
-    let devices = unsafe {
-        let mut devices: [types::FT_DEVICE_LIST_INFO_NODE; MAX_DEVICES] = std::mem::zeroed();
+    let mut num_devices: ULONG = 0;
+    unsafe {
         lib::FT_CreateDeviceInfoList(ptr_mut(&mut num_devices))?;
-        lib::FT_GetDeviceInfoList(ptr_mut(&mut devices), ptr_mut(&mut num_devices))?;
-        devices
-    };
-    Ok(devices[..num_devices]
-        .iter()
+    }
+    if num_devices == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut devices = vec![types::FT_DEVICE_LIST_INFO_NODE::default(); num_devices as usize];
+    unsafe {
+        lib::FT_GetDeviceInfoList(devices.as_mut_ptr(), ptr_mut(&mut num_devices))?;
+    }
+    devices.truncate(num_devices as usize);
+    Ok(devices
+        .into_iter()
         .enumerate()
-        .map(|(i, e)| DeviceInfo::new(i, e.clone()))
+        .map(|(i, e)| DeviceInfo::new(i, e))
         .collect())
 }
 
 /// Get the D3XX library version.
 pub fn d3xx_version() -> Version {
-    let mut version: c_ulong = 0;
+    let mut version: ULONG = 0;
     unsafe {
         lib::FT_GetLibraryVersion(ptr_mut(&mut version))
             .expect("failed to get d3xx library version");
@@ -664,4 +2109,197 @@ pub fn d3xx_available() -> bool {
     device_count().is_ok()
 }
 
-unsafe impl Send for Device {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A device around a null handle. Closing it fails harmlessly, as no library is
+    /// loaded in tests.
+    fn null_device() -> Device {
+        Device::from_handle(unsafe { OwnedHandle::from_raw_handle(null_mut()) })
+    }
+
+    #[test]
+    fn owned_handle_requires_unshared_device() {
+        let device = null_device();
+        let holder = device.inner.clone();
+        let device = OwnedHandle::try_from(device).expect_err("handle is shared");
+        drop(holder);
+        let handle = OwnedHandle::try_from(device).expect("handle is not shared");
+        assert!(handle.into_raw_handle().is_null());
+    }
+
+    #[test]
+    fn cancel_all_can_be_cleared() {
+        let device = null_device();
+        let cancel = device.cancel_handle();
+        // Aborting fails without a library, but the requests are still recorded.
+        assert!(cancel.cancel_all().is_err());
+        assert!(cancel.clear(Pipe::In0));
+        assert!(!cancel.clear(Pipe::In0));
+        cancel.clear_all();
+        assert!(Pipe::ALL
+            .into_iter()
+            .all(|pipe| !device.inner.take_cancelled(pipe)));
+
+        drop(device);
+        assert!(!cancel.clear(Pipe::Out0));
+    }
+
+    #[test]
+    fn link_speed_from_flags() {
+        let info = |flags| {
+            DeviceInfo::new(
+                0,
+                types::FT_DEVICE_LIST_INFO_NODE {
+                    Flags: flags,
+                    Type: constants::FT_DEVICE_601,
+                    ID: 0x0403_601F,
+                    LocId: 0,
+                    SerialNumber: [0; 16],
+                    Description: [0; 32],
+                    ftHandle: null_mut(),
+                },
+            )
+        };
+        assert_eq!(info(0).link_speed(), None);
+        assert_eq!(info(0x1).link_speed(), None);
+        assert_eq!(info(0x2).link_speed(), Some(LinkSpeed::HighSpeed));
+        assert_eq!(info(0x5).link_speed(), Some(LinkSpeed::SuperSpeed));
+        assert_eq!(LinkSpeed::SuperSpeed.bits_per_second(), 5_000_000_000);
+        assert_eq!(LinkSpeed::HighSpeed.name(), "USB2");
+    }
+
+    #[test]
+    fn chip_configuration_strings() {
+        let mut configuration = ChipConfiguration::default();
+        let mut offset = 0;
+        for string in ["FTDI", "FT601 \u{b5}C", ""] {
+            let units: Vec<u16> = string.encode_utf16().collect();
+            let descriptor = &mut configuration.inner.StringDescriptors[offset..];
+            descriptor[0] = (2 + units.len() * 2) as u8;
+            descriptor[1] = 0x03;
+            for (i, unit) in units.iter().enumerate() {
+                descriptor[2 + i * 2..4 + i * 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            offset += descriptor[0] as usize;
+        }
+        assert_eq!(configuration.strings(), ["FTDI", "FT601 \u{b5}C", ""]);
+
+        // A length running past the end of the descriptors ends the list.
+        configuration.inner.StringDescriptors[offset..offset + 2].copy_from_slice(&[0xff, 0x03]);
+        assert_eq!(configuration.strings().len(), 3);
+
+        configuration.inner.FIFOClock = 1;
+        assert_eq!(configuration.fifo_clock_mhz(), Some(66));
+        configuration.inner.FIFOClock = 4;
+        assert_eq!(configuration.fifo_clock_mhz(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    mod serde {
+        use serde_json::json;
+
+        use super::*;
+
+        fn round_trip<T: ::serde::Serialize + ::serde::de::DeserializeOwned>(
+            value: &T,
+        ) -> (serde_json::Value, T) {
+            let json = serde_json::to_value(value).unwrap();
+            (json.clone(), serde_json::from_value(json).unwrap())
+        }
+
+        #[test]
+        fn device_info() {
+            let info = DeviceInfo {
+                index: 1,
+                flags: 4,
+                type_: 600,
+                vendor_id: 0x0403,
+                product_id: 0x601f,
+                location_identifier: 0x12,
+                serial_number: "000000000001".to_owned(),
+                description: "FTDI SuperSpeed-FIFO Bridge".to_owned(),
+                is_open: true,
+                handle: 0x1234,
+            };
+            let (json, decoded) = round_trip(&info);
+            assert_eq!(json["type"], 600);
+            assert!(json.get("handle").is_none());
+            // The handle is meaningless in another process and is not kept.
+            assert_eq!(decoded, DeviceInfo { handle: 0, ..info });
+        }
+
+        #[test]
+        fn device_descriptor() {
+            let descriptor = DeviceDescriptor {
+                inner: types::FT_DEVICE_DESCRIPTOR {
+                    bLength: 18,
+                    bDescriptorType: constants::FT_DEVICE_DESCRIPTOR_TYPE as _,
+                    bcdUSB: 0x0320,
+                    bDeviceClass: 0,
+                    bDeviceSubClass: 0,
+                    bDeviceProtocol: 0,
+                    bMaxPacketSize0: 9,
+                    idVendor: 0x0403,
+                    idProduct: 0x601f,
+                    bcdDevice: 0,
+                    iManufacturer: 1,
+                    iProduct: 2,
+                    iSerialNumber: 3,
+                    bNumConfigurations: 1,
+                },
+            };
+            let (json, decoded) = round_trip(&descriptor);
+            assert_eq!(json["usb_specification_number"], 0x0320);
+            assert_eq!(json["product_id"], 0x601f);
+            assert!(json.get("bLength").is_none());
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+            assert_eq!(decoded.inner.bLength, 18);
+            assert_eq!(
+                decoded.inner.bDescriptorType,
+                constants::FT_DEVICE_DESCRIPTOR_TYPE as u8
+            );
+        }
+
+        #[test]
+        fn pipe_info() {
+            let info = PipeInfo {
+                inner: types::FT_PIPE_INFORMATION {
+                    PipeType: PipeType::Bulk as _,
+                    PipeID: Pipe::In0 as u8,
+                    MaximumPacketSize: 1024,
+                    Interval: 0,
+                },
+            };
+            let (json, decoded) = round_trip(&info);
+            assert_eq!(
+                json,
+                json!({ "type": "bulk", "pipe_id": 130, "maximum_packet_size": 1024, "interval": 0 })
+            );
+            assert_eq!(decoded, info);
+            assert_eq!(decoded.pipe(), Pipe::In0);
+        }
+
+        #[test]
+        fn pipe_info_rejects_invalid_fields() {
+            let invalid_pipe = json!({ "type": "bulk", "pipe_id": 0x86, "maximum_packet_size": 1024, "interval": 0 });
+            let err = serde_json::from_value::<PipeInfo>(invalid_pipe).unwrap_err();
+            assert!(err.to_string().contains("invalid pipe ID 0x86"));
+
+            let invalid_type = json!({ "type": "stream", "pipe_id": 2, "maximum_packet_size": 1024, "interval": 0 });
+            assert!(serde_json::from_value::<PipeInfo>(invalid_type).is_err());
+        }
+
+        #[test]
+        fn version() {
+            let version = Version::new(0x0103_000a);
+            let (json, decoded) = round_trip(&version);
+            assert_eq!(
+                json,
+                json!({ "major": 1, "minor": 3, "svn": 0, "build": 10 })
+            );
+            assert_eq!(decoded, version);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! A minimal LZ4 frame encoder for the [recorder](crate::recorder).
+//!
+//! Produces standard LZ4 frames which can be read by `lz4 -d` or any LZ4 library.
+//! Blocks are compressed independently with a greedy single-pass match finder,
+//! which favors speed over ratio; blocks which do not shrink are stored as-is.
+
+use std::io::{self, Write};
+
+/// Frame magic number.
+const MAGIC: u32 = 0x184d_2204;
+/// Frame descriptor: version 01, independent blocks, no checksums or content size,
+/// 4 MiB maximum block size, followed by its header checksum,
+/// `(xxh32(descriptor) >> 8) & 0xff`.
+const DESCRIPTOR: [u8; 3] = [0x60, 0x70, 0x73];
+/// The largest block allowed by [`DESCRIPTOR`].
+const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+/// Set in a block size to mark the block as stored uncompressed.
+const UNCOMPRESSED: u32 = 0x8000_0000;
+
+/// Minimum match length.
+const MIN_MATCH: usize = 4;
+/// The last match must start at least this many bytes before the end of a block.
+const MF_LIMIT: usize = 12;
+/// The last bytes of a block are always literals.
+const LAST_LITERALS: usize = 5;
+/// Largest match offset.
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 16;
+
+/// Compresses everything written to it into an LZ4 frame written to `W`.
+///
+/// [`Lz4Encoder::finish`] must be called to complete the frame.
+pub(crate) struct Lz4Encoder<W: Write> {
+    inner: W,
+    block: Vec<u8>,
+    compressed: Vec<u8>,
+    table: Box<[u32]>,
+}
+
+impl<W: Write> Lz4Encoder<W> {
+    /// Writes the frame header to `inner`.
+    pub(crate) fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(&MAGIC.to_le_bytes())?;
+        inner.write_all(&DESCRIPTOR)?;
+        Ok(Self {
+            inner,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            compressed: Vec::with_capacity(BLOCK_SIZE),
+            table: vec![0; 1 << HASH_BITS].into_boxed_slice(),
+        })
+    }
+
+    /// Writes any buffered data and the end mark, and returns the inner writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.write_block()?;
+        self.inner.write_all(&0u32.to_le_bytes())?;
+        Ok(self.inner)
+    }
+
+    /// The inner writer.
+    pub(crate) fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// The inner writer.
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        self.compressed.clear();
+        compress_block(&self.block, &mut self.compressed, &mut self.table);
+        if self.compressed.len() < self.block.len() {
+            self.inner
+                .write_all(&(self.compressed.len() as u32).to_le_bytes())?;
+            self.inner.write_all(&self.compressed)?;
+        } else {
+            self.inner
+                .write_all(&(self.block.len() as u32 | UNCOMPRESSED).to_le_bytes())?;
+            self.inner.write_all(&self.block)?;
+        }
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Lz4Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..n]);
+        if self.block.len() == BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    /// Writes the buffered data as a block, so a partial block is flushed too.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
+
+/// Compresses `src` as a single LZ4 block appended to `dst`.
+fn compress_block(src: &[u8], dst: &mut Vec<u8>, table: &mut [u32]) {
+    table.fill(0);
+    let mut anchor = 0;
+    if src.len() > MF_LIMIT {
+        let match_limit = src.len() - LAST_LITERALS;
+        let mut i = 0;
+        while i < src.len() - MF_LIMIT {
+            let sequence = read_u32(src, i);
+            let hash = (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+            let candidate = table[hash] as usize;
+            table[hash] = i as u32;
+            if candidate < i && i - candidate <= MAX_OFFSET && read_u32(src, candidate) == sequence
+            {
+                let mut end = i + MIN_MATCH;
+                while end < match_limit && src[end] == src[end - i + candidate] {
+                    end += 1;
+                }
+                write_sequence(dst, &src[anchor..i], Some((i - candidate, end - i)));
+                i = end;
+                anchor = end;
+            } else {
+                // Skip ahead faster through data which does not compress.
+                i += 1 + ((i - anchor) >> 6);
+            }
+        }
+    }
+    write_sequence(dst, &src[anchor..], None);
+}
+
+/// Appends a sequence of literals, followed by a match of `(offset, length)` unless
+/// it is the last sequence of the block.
+fn write_sequence(dst: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    let token = (literals.len().min(15) << 4 | match_len.min(15)) as u8;
+    dst.push(token);
+    if literals.len() >= 15 {
+        write_length(dst, literals.len() - 15);
+    }
+    dst.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        dst.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_length(dst, match_len - 15);
+        }
+    }
+}
+
+/// Appends the remainder of a length which did not fit in a token.
+fn write_length(dst: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        dst.push(255);
+        len -= 255;
+    }
+    dst.push(len as u8);
+}
+
+fn read_u32(src: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes(src[i..i + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Decodes a frame written by [`Lz4Encoder`], checking the end-of-block rules
+    /// of the LZ4 block format along the way.
+    pub(crate) fn decompress(frame: &[u8]) -> Vec<u8> {
+        assert_eq!(frame[..4], MAGIC.to_le_bytes());
+        assert_eq!(frame[4..7], DESCRIPTOR);
+        let mut out = Vec::new();
+        let mut pos = 7;
+        loop {
+            let size = u32::from_le_bytes(frame[pos..pos + 4].try_into().unwrap());
+            pos += 4;
+            if size == 0 {
+                break;
+            }
+            let len = (size & !UNCOMPRESSED) as usize;
+            assert!(len <= BLOCK_SIZE);
+            let block = &frame[pos..pos + len];
+            if size & UNCOMPRESSED != 0 {
+                out.extend_from_slice(block);
+            } else {
+                decompress_block(block, &mut out);
+            }
+            pos += len;
+        }
+        assert_eq!(pos, frame.len());
+        out
+    }
+
+    fn decompress_block(block: &[u8], out: &mut Vec<u8>) {
+        let start = out.len();
+        let mut last_match = None;
+        let mut i = 0;
+        let length = |i: &mut usize, mut len: usize| {
+            if len == 15 {
+                loop {
+                    let byte = block[*i];
+                    *i += 1;
+                    len += usize::from(byte);
+                    if byte != 255 {
+                        break;
+                    }
+                }
+            }
+            len
+        };
+        loop {
+            let token = block[i];
+            i += 1;
+            let literals = length(&mut i, usize::from(token >> 4));
+            out.extend_from_slice(&block[i..i + literals]);
+            i += literals;
+            if i == block.len() {
+                break;
+            }
+            let offset = usize::from(u16::from_le_bytes([block[i], block[i + 1]]));
+            i += 2;
+            assert!(
+                offset > 0 && offset <= out.len() - start,
+                "offset out of block"
+            );
+            let len = length(&mut i, usize::from(token & 15)) + MIN_MATCH;
+            let match_start = out.len() - start;
+            for _ in 0..len {
+                out.push(out[out.len() - offset]);
+            }
+            last_match = Some((match_start, out.len() - start));
+        }
+        if let Some((match_start, match_end)) = last_match {
+            let block_len = out.len() - start;
+            assert!(match_start + MF_LIMIT <= block_len);
+            assert!(match_end + LAST_LITERALS <= block_len);
+        }
+    }
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = Lz4Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Bytes which do not compress, from a xorshift generator.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty() {
+        let frame = compress(&[]);
+        assert_eq!(frame.len(), 11);
+        assert!(decompress(&frame).is_empty());
+    }
+
+    #[test]
+    fn short_inputs() {
+        for len in 1..=MF_LIMIT + 8 {
+            let data: Vec<u8> = (0..len).map(|i| (i % 3) as u8).collect();
+            assert_eq!(decompress(&compress(&data)), data, "length {len}");
+        }
+    }
+
+    #[test]
+    fn repetitive() {
+        let data = vec![0x5a; 100_000];
+        let frame = compress(&data);
+        assert!(frame.len() < 1000);
+        assert_eq!(decompress(&frame), data);
+    }
+
+    #[test]
+    fn incompressible_is_stored() {
+        let data = noise(100_000);
+        let frame = compress(&data);
+        let size = u32::from_le_bytes(frame[7..11].try_into().unwrap());
+        assert_eq!(size, data.len() as u32 | UNCOMPRESSED);
+        assert_eq!(decompress(&frame), data);
+    }
+
+    #[test]
+    fn mixed_across_blocks() {
+        // Counters, runs and noise, spanning more than two blocks.
+        let mut data = Vec::new();
+        let mut noise = noise(BLOCK_SIZE).into_iter();
+        while data.len() < 2 * BLOCK_SIZE + 12_345 {
+            data.extend((0..4096u32).flat_map(u32::to_le_bytes));
+            data.extend(std::iter::repeat_n(0, 300));
+            data.extend(noise.by_ref().take(777));
+        }
+        let frame = compress(&data);
+        assert!(frame.len() < data.len());
+        assert_eq!(decompress(&frame), data);
+    }
+
+    #[test]
+    fn flush_ends_block() {
+        let mut encoder = Lz4Encoder::new(Vec::new()).unwrap();
+        let mut data = Vec::new();
+        for i in 0..10u8 {
+            let piece = vec![i; 1000 + usize::from(i)];
+            encoder.write_all(&piece).unwrap();
+            encoder.flush().unwrap();
+            data.extend_from_slice(&piece);
+        }
+        // Flushing with nothing buffered writes no empty block.
+        encoder.flush().unwrap();
+        let frame = encoder.finish().unwrap();
+        assert_eq!(decompress(&frame), data);
+    }
+}
@@ -0,0 +1,849 @@
+//! Live metrics for Prometheus and other OpenMetrics scrapers.
+//!
+//! A [`MetricsRegistry`] collects metrics from registered devices, streams and
+//! buffer pools, together with application-level [`Counter`]s and [`Gauge`]s such
+//! as event rates or dropped frames. [`MetricsServer`] serves the registry over
+//! HTTP on a background thread:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! use ft60x_rs::{metrics::{MetricsRegistry, MetricsServer}, Pipe, StreamConfig};
+//!
+//! let registry = MetricsRegistry::new();
+//! registry.register_device(&device)?;
+//! let events = registry.counter("daq_events", "Events built from the readout.");
+//!
+//! let _server = MetricsServer::start("0.0.0.0:9601", registry.clone())?;
+//! let stream = device.stream(Pipe::In0, StreamConfig::default())?;
+//! registry.register_stream(&stream);
+//! for buffer in stream {
+//!     let buffer = buffer?;
+//!     // ... build events from &buffer[..] ...
+//!     events.inc();
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The registry only holds weak references: metrics of a device or stream disappear
+//! once it is dropped, and registering does not keep a device open.
+//!
+//! # Metrics
+//!
+//! Every device metric has a `serial` label, and every pipe metric a `pipe` label
+//! such as `In0`. Rates are left to the scraper, e.g. `rate(ft60x_pipe_bytes_total[1m])`
+//! for bytes per second.
+//!
+//! | Metric                                  | Type    | Contents |
+//! |-----------------------------------------|---------|----------|
+//! | `ft60x_device_info`                     | info    | `description`, `chip`, `driver_version` and `library_version` labels |
+//! | `ft60x_link_speed_bits_per_second`      | gauge   | USB signalling rate from the device list when the device was registered, if known |
+//! | `ft60x_pipe_bytes_total`                | counter | Bytes transferred |
+//! | `ft60x_pipe_transfers_total`            | counter | Transfers completed or failed |
+//! | `ft60x_pipe_timeouts_total`             | counter | Transfers which timed out |
+//! | `ft60x_pipe_aborts_total`               | counter | Transfers which were aborted or cancelled |
+//! | `ft60x_pipe_short_reads_total`          | counter | Reads which returned less than requested |
+//! | `ft60x_pipe_largest_gap_seconds`        | gauge   | Longest idle time between transfers |
+//! | `ft60x_pipe_latency_seconds`            | summary | Transfer latency quantiles |
+//! | `ft60x_stream_overruns_total`           | counter | [Stream overruns](crate::stream#overruns) |
+//! | `ft60x_stream_buffers`                  | gauge   | Buffers in a stream's pool |
+//! | `ft60x_stream_buffers_in_use`           | gauge   | Buffers queued on the device or held by the consumer |
+//! | `ft60x_pool_buffers`                    | gauge   | Buffers in a registered pool, with a `pool` label |
+//! | `ft60x_pool_buffers_in_use`             | gauge   | Buffers taken from a registered pool |
+//!
+//! Pipe metrics require transfer statistics, which [`MetricsRegistry::register_device`]
+//! enables, and are only reported for pipes which have been used.
+
+use std::{
+    fmt::{Debug, Write as _},
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{stream, BufferPool, Device, DeviceHandle, LinkSpeed, Pipe, Result, Stream};
+
+/// Latency quantiles reported in `ft60x_pipe_latency_seconds`.
+const QUANTILES: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+/// How often the server checks whether it has been stopped while idle.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Time allowed for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest request head accepted.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// A monotonically increasing count, exported as an OpenMetrics counter.
+///
+/// Created by [`MetricsRegistry::counter`]. Clones share the same count.
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// Adds one.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Adds `n`.
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// The current count.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value which can go up and down, exported as an OpenMetrics gauge.
+///
+/// Created by [`MetricsRegistry::gauge`]. Clones share the same value.
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Gauge {
+    /// Sets the value.
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// The current value.
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Text exposition formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpositionFormat {
+    /// The Prometheus text format, version 0.0.4.
+    Prometheus,
+    /// OpenMetrics 1.0.
+    OpenMetrics,
+}
+
+impl ExpositionFormat {
+    /// The `Content-Type` of the format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Prometheus => "text/plain; version=0.0.4; charset=utf-8",
+            Self::OpenMetrics => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        }
+    }
+}
+
+/// A set of metrics sources which can be rendered in a text exposition format.
+///
+/// Clones share the same sources, so a clone can be handed to a [`MetricsServer`]
+/// while others are registered.
+#[derive(Clone, Default)]
+pub struct MetricsRegistry {
+    inner: Arc<Mutex<Sources>>,
+}
+
+#[derive(Default)]
+struct Sources {
+    devices: Vec<DeviceSource>,
+    streams: Vec<StreamSource>,
+    pools: Vec<(String, BufferPool)>,
+    counters: Vec<(String, String, Counter)>,
+    gauges: Vec<(String, String, Gauge)>,
+}
+
+struct DeviceSource {
+    device: Weak<DeviceHandle>,
+    serial: String,
+    description: String,
+    chip: String,
+    driver_version: String,
+    library_version: String,
+    link_speed: Option<LinkSpeed>,
+}
+
+struct StreamSource {
+    shared: Weak<stream::Shared>,
+    serial: String,
+    pipe: Pipe,
+}
+
+impl MetricsRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the device and pipe metrics of `device`, and enables its transfer
+    /// statistics.
+    ///
+    /// The device's descriptors and driver version are read once, here.
+    pub fn register_device(&self, device: &Device) -> Result<()> {
+        let info = device.info()?;
+        let source = DeviceSource {
+            device: Arc::downgrade(&device.inner),
+            serial: info.serial_number().to_owned(),
+            description: info.description().to_owned(),
+            chip: info
+                .chip_type()
+                .map_or_else(String::new, |chip| format!("{chip:?}")),
+            driver_version: device.driver_version()?.to_string(),
+            library_version: crate::d3xx_version().to_string(),
+            link_speed: info.link_speed(),
+        };
+        device.set_stats_enabled(true);
+        self.inner.lock().unwrap().devices.push(source);
+        Ok(())
+    }
+
+    /// Adds the overrun count and buffer occupancy of `stream`.
+    pub fn register_stream(&self, stream: &Stream) {
+        let source = StreamSource {
+            shared: Arc::downgrade(stream.shared()),
+            serial: stream.device().serial_number.clone().unwrap_or_default(),
+            pipe: stream.pipe(),
+        };
+        self.inner.lock().unwrap().streams.push(source);
+    }
+
+    /// Adds the occupancy of `pool`, labelled with `name`.
+    ///
+    /// The registry keeps a handle to the pool, so its buffers are not freed until
+    /// the registry is dropped.
+    pub fn register_pool(&self, name: &str, pool: &BufferPool) {
+        self.inner
+            .lock()
+            .unwrap()
+            .pools
+            .push((name.to_owned(), pool.clone()));
+    }
+
+    /// Creates a counter exported as `<name>_total`. If `name` already ends in
+    /// `_total`, the suffix is not repeated.
+    ///
+    /// # Panics
+    /// If `name` is not a valid metric name: ASCII letters, digits, `_` and `:`, not
+    /// starting with a digit.
+    pub fn counter(&self, name: &str, help: &str) -> Counter {
+        let name = name.strip_suffix("_total").unwrap_or(name);
+        assert!(is_valid_name(name), "invalid metric name `{name}`");
+        let counter = Counter::default();
+        self.inner.lock().unwrap().counters.push((
+            name.to_owned(),
+            help.to_owned(),
+            counter.clone(),
+        ));
+        counter
+    }
+
+    /// Creates a gauge exported as `name`, initially zero.
+    ///
+    /// # Panics
+    /// If `name` is not a valid metric name; see [`MetricsRegistry::counter`].
+    pub fn gauge(&self, name: &str, help: &str) -> Gauge {
+        assert!(is_valid_name(name), "invalid metric name `{name}`");
+        let gauge = Gauge::default();
+        self.inner
+            .lock()
+            .unwrap()
+            .gauges
+            .push((name.to_owned(), help.to_owned(), gauge.clone()));
+        gauge
+    }
+
+    /// Renders the current value of every metric.
+    ///
+    /// ```
+    /// use ft60x_rs::metrics::{ExpositionFormat, MetricsRegistry};
+    ///
+    /// let registry = MetricsRegistry::new();
+    /// registry.counter("daq_dropped_frames", "Frames lost to CRC errors.").inc_by(3);
+    /// registry.gauge("daq_event_rate", "Events per second.").set(1250.0);
+    ///
+    /// let text = registry.render(ExpositionFormat::OpenMetrics);
+    /// assert!(text.contains("# TYPE daq_dropped_frames counter\n"));
+    /// assert!(text.contains("daq_dropped_frames_total 3\n"));
+    /// assert!(text.contains("daq_event_rate 1250\n"));
+    /// assert!(text.ends_with("# EOF\n"));
+    /// ```
+    pub fn render(&self, format: ExpositionFormat) -> String {
+        let mut sources = self.inner.lock().unwrap();
+        sources.devices.retain(|d| d.device.strong_count() > 0);
+        sources.streams.retain(|s| s.shared.strong_count() > 0);
+        let mut out = Exposition::new(format);
+
+        for d in &sources.devices {
+            let Some(device) = d.device.upgrade() else {
+                continue;
+            };
+            let serial = [("serial", d.serial.as_str())];
+            out.info(
+                "ft60x_device",
+                "Device identity and versions.",
+                &[
+                    ("serial", &d.serial),
+                    ("description", &d.description),
+                    ("chip", &d.chip),
+                    ("driver_version", &d.driver_version),
+                    ("library_version", &d.library_version),
+                ],
+            );
+            if let Some(speed) = d.link_speed {
+                out.gauge(
+                    "ft60x_link_speed_bits_per_second",
+                    "USB signalling rate of the link.",
+                    &serial,
+                    speed.bits_per_second() as f64,
+                );
+            }
+            let Some(stats) = device.stats() else {
+                continue;
+            };
+            for pipe in Pipe::ALL {
+                let stats = stats.snapshot(pipe);
+                if stats.transfers == 0 {
+                    continue;
+                }
+                let pipe = pipe.to_string();
+                let labels = [("serial", d.serial.as_str()), ("pipe", pipe.as_str())];
+                out.counter(
+                    "ft60x_pipe_bytes",
+                    "Bytes transferred.",
+                    &labels,
+                    stats.bytes,
+                );
+                out.counter(
+                    "ft60x_pipe_transfers",
+                    "Transfers completed or failed.",
+                    &labels,
+                    stats.transfers,
+                );
+                out.counter(
+                    "ft60x_pipe_timeouts",
+                    "Transfers which timed out.",
+                    &labels,
+                    stats.timeouts,
+                );
+                out.counter(
+                    "ft60x_pipe_aborts",
+                    "Transfers which were aborted or cancelled.",
+                    &labels,
+                    stats.aborts,
+                );
+                out.counter(
+                    "ft60x_pipe_short_reads",
+                    "Reads which returned less data than requested.",
+                    &labels,
+                    stats.short_reads,
+                );
+                out.gauge(
+                    "ft60x_pipe_largest_gap_seconds",
+                    "Longest idle time between two transfers.",
+                    &labels,
+                    stats.largest_gap.as_secs_f64(),
+                );
+                let quantiles = QUANTILES.map(|q| (q, stats.latency.value_at_quantile(q)));
+                out.summary(
+                    "ft60x_pipe_latency_seconds",
+                    "Transfer latency.",
+                    &labels,
+                    &quantiles,
+                    stats.latency.sum().as_secs_f64(),
+                    stats.latency.count(),
+                );
+            }
+        }
+
+        for s in &sources.streams {
+            let Some(shared) = s.shared.upgrade() else {
+                continue;
+            };
+            let pipe = s.pipe.to_string();
+            let labels = [("serial", s.serial.as_str()), ("pipe", pipe.as_str())];
+            out.counter(
+                "ft60x_stream_overruns",
+                "Times a stream could not keep its reads queued.",
+                &labels,
+                shared.overruns.load(Ordering::Relaxed),
+            );
+            out.gauge(
+                "ft60x_stream_buffers",
+                "Buffers in the stream's pool.",
+                &labels,
+                shared.pool.count() as f64,
+            );
+            out.gauge(
+                "ft60x_stream_buffers_in_use",
+                "Buffers queued on the device or held by the consumer.",
+                &labels,
+                (shared.pool.count() - shared.pool.available()) as f64,
+            );
+        }
+
+        for (name, pool) in &sources.pools {
+            let labels = [("pool", name.as_str())];
+            out.gauge(
+                "ft60x_pool_buffers",
+                "Buffers in the pool.",
+                &labels,
+                pool.count() as f64,
+            );
+            out.gauge(
+                "ft60x_pool_buffers_in_use",
+                "Buffers taken from the pool.",
+                &labels,
+                (pool.count() - pool.available()) as f64,
+            );
+        }
+
+        for (name, help, counter) in &sources.counters {
+            out.counter(name, help, &[], counter.get());
+        }
+        for (name, help, gauge) in &sources.gauges {
+            out.gauge(name, help, &[], gauge.get());
+        }
+        out.finish()
+    }
+}
+
+impl Debug for MetricsRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sources = self.inner.lock().unwrap();
+        f.debug_struct("MetricsRegistry")
+            .field("devices", &sources.devices.len())
+            .field("streams", &sources.streams.len())
+            .field("pools", &sources.pools.len())
+            .field("counters", &sources.counters.len())
+            .field("gauges", &sources.gauges.len())
+            .finish()
+    }
+}
+
+/// Whether `name` matches `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Builds an exposition, grouping the samples of each metric family together.
+struct Exposition {
+    format: ExpositionFormat,
+    /// Families in order of first appearance: name, help, type and sample lines.
+    families: Vec<(String, String, &'static str, String)>,
+}
+
+impl Exposition {
+    fn new(format: ExpositionFormat) -> Self {
+        Self {
+            format,
+            families: Vec::new(),
+        }
+    }
+
+    /// The sample lines of a family, creating it if needed.
+    fn family(&mut self, name: &str, help: &str, type_: &'static str) -> &mut String {
+        let index = match self.families.iter().position(|f| f.0 == name) {
+            Some(index) => index,
+            None => {
+                self.families
+                    .push((name.to_owned(), help.to_owned(), type_, String::new()));
+                self.families.len() - 1
+            }
+        };
+        &mut self.families[index].3
+    }
+
+    fn counter(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: u64) {
+        let samples = self.family(name, help, "counter");
+        sample(
+            samples,
+            &format!("{name}_total"),
+            labels,
+            None,
+            value as f64,
+        );
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        let samples = self.family(name, help, "gauge");
+        sample(samples, name, labels, None, value);
+    }
+
+    fn info(&mut self, name: &str, help: &str, labels: &[(&str, &str)]) {
+        let samples = self.family(name, help, "info");
+        sample(samples, &format!("{name}_info"), labels, None, 1.0);
+    }
+
+    fn summary(
+        &mut self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        quantiles: &[(f64, Duration)],
+        sum: f64,
+        count: u64,
+    ) {
+        let samples = self.family(name, help, "summary");
+        for (quantile, value) in quantiles {
+            let quantile = quantile.to_string();
+            sample(
+                samples,
+                name,
+                labels,
+                Some(("quantile", &quantile)),
+                value.as_secs_f64(),
+            );
+        }
+        sample(samples, &format!("{name}_sum"), labels, None, sum);
+        sample(
+            samples,
+            &format!("{name}_count"),
+            labels,
+            None,
+            count as f64,
+        );
+    }
+
+    fn finish(self) -> String {
+        let mut out = String::new();
+        for (name, help, type_, samples) in self.families {
+            // The Prometheus format names counters by their sample and has no info
+            // type.
+            let (name, type_) = match (self.format, type_) {
+                (ExpositionFormat::Prometheus, "counter") => (format!("{name}_total"), type_),
+                (ExpositionFormat::Prometheus, "info") => (format!("{name}_info"), "gauge"),
+                _ => (name, type_),
+            };
+            let _ = writeln!(
+                out,
+                "# HELP {name} {}",
+                escape(&help, self.format == ExpositionFormat::OpenMetrics)
+            );
+            let _ = writeln!(out, "# TYPE {name} {type_}");
+            out.push_str(&samples);
+        }
+        if self.format == ExpositionFormat::OpenMetrics {
+            out.push_str("# EOF\n");
+        }
+        out
+    }
+}
+
+/// Appends one sample line.
+fn sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    extra: Option<(&str, &str)>,
+    value: f64,
+) {
+    out.push_str(name);
+    let mut labels = labels.iter().copied().chain(extra).peekable();
+    if labels.peek().is_some() {
+        out.push('{');
+        for (i, (label, value)) in labels.enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{label}=\"{}\"", escape(value, true));
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {value}");
+}
+
+/// Escapes a label value or help text. Quotes are escaped in label values, and in
+/// help text in the OpenMetrics format.
+fn escape(s: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quotes => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Serves a [`MetricsRegistry`] over HTTP on a background thread.
+///
+/// `GET /metrics` returns the OpenMetrics format if the `Accept` header asks for it,
+/// and the Prometheus text format otherwise. Requests are handled one at a time.
+/// The server stops when dropped.
+pub struct MetricsServer {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Listens on `addr` and starts serving `registry`. Port 0 picks a free port; see
+    /// [`MetricsServer::local_addr`].
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// use std::io::{Read, Write};
+    /// use ft60x_rs::metrics::{MetricsRegistry, MetricsServer};
+    ///
+    /// let registry = MetricsRegistry::new();
+    /// registry.counter("daq_events", "Events built.").inc_by(42);
+    /// let server = MetricsServer::start("127.0.0.1:0", registry)?;
+    ///
+    /// let mut client = std::net::TcpStream::connect(server.local_addr())?;
+    /// client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    /// let mut response = String::new();
+    /// client.read_to_string(&mut response)?;
+    /// assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    /// assert!(response.contains("\r\n\r\n# HELP daq_events_total Events built.\n"));
+    /// assert!(response.contains("daq_events_total 42\n"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn start(addr: impl ToSocketAddrs, registry: MetricsRegistry) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name("ft60x-metrics".to_owned())
+            .spawn({
+                let stop = stop.clone();
+                move || serve(listener, registry, &stop)
+            })?;
+        Ok(Self {
+            local_addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Debug for MetricsServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsServer")
+            .field("local_addr", &self.local_addr)
+            .finish()
+    }
+}
+
+fn serve(listener: TcpListener, registry: MetricsRegistry, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            // A client which misbehaves only affects its own response.
+            Ok((stream, _)) => {
+                let _ = respond(stream, &registry);
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+            Err(_) => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, registry: &MetricsRegistry) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut head = Vec::new();
+    let mut chunk = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk)?;
+        if n == 0 || head.len() + n > MAX_REQUEST_SIZE {
+            return Ok(());
+        }
+        head.extend_from_slice(&chunk[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let mut request = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = (request.next(), request.next().unwrap_or_default());
+    let path = target.split('?').next().unwrap_or_default();
+    let accept = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("accept"))
+        .map_or("", |(_, value)| value);
+
+    let (status, content_type, body) = match (method, path) {
+        (Some("GET" | "HEAD"), "/metrics") => {
+            let format = if accept.contains("application/openmetrics-text") {
+                ExpositionFormat::OpenMetrics
+            } else {
+                ExpositionFormat::Prometheus
+            };
+            ("200 OK", format.content_type(), registry.render(format))
+        }
+        (Some("GET" | "HEAD"), "/") => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            "<html><body><a href=\"/metrics\">Metrics</a></body></html>\n".to_owned(),
+        ),
+        (Some("GET" | "HEAD"), _) => ("404 Not Found", "text/plain", "Not found\n".to_owned()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n".to_owned(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len(),
+    )?;
+    if method != Some("HEAD") {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChipType, PooledBuf};
+
+    /// A registry with a pool, a counter and a gauge, the pool with one buffer taken.
+    fn registry() -> (MetricsRegistry, PooledBuf) {
+        let registry = MetricsRegistry::new();
+        let pool = BufferPool::new(ChipType::Ft601, 4096, 2).unwrap();
+        registry.register_pool("dma \"main\"\\\n", &pool);
+        registry
+            .counter("daq_dropped_frames_total", "Frames lost to CRC errors.")
+            .inc_by(3);
+        registry
+            .gauge("daq_event_rate", "Events per second, \"built\" \\ read.")
+            .set(1250.5);
+        let buffer = pool.try_get().unwrap();
+        (registry, buffer)
+    }
+
+    #[test]
+    fn prometheus() {
+        let (registry, _buffer) = registry();
+        assert_eq!(
+            registry.render(ExpositionFormat::Prometheus),
+            "# HELP ft60x_pool_buffers Buffers in the pool.\n\
+             # TYPE ft60x_pool_buffers gauge\n\
+             ft60x_pool_buffers{pool=\"dma \\\"main\\\"\\\\\\n\"} 2\n\
+             # HELP ft60x_pool_buffers_in_use Buffers taken from the pool.\n\
+             # TYPE ft60x_pool_buffers_in_use gauge\n\
+             ft60x_pool_buffers_in_use{pool=\"dma \\\"main\\\"\\\\\\n\"} 1\n\
+             # HELP daq_dropped_frames_total Frames lost to CRC errors.\n\
+             # TYPE daq_dropped_frames_total counter\n\
+             daq_dropped_frames_total 3\n\
+             # HELP daq_event_rate Events per second, \"built\" \\\\ read.\n\
+             # TYPE daq_event_rate gauge\n\
+             daq_event_rate 1250.5\n"
+        );
+    }
+
+    #[test]
+    fn open_metrics() {
+        let (registry, _buffer) = registry();
+        assert_eq!(
+            registry.render(ExpositionFormat::OpenMetrics),
+            "# HELP ft60x_pool_buffers Buffers in the pool.\n\
+             # TYPE ft60x_pool_buffers gauge\n\
+             ft60x_pool_buffers{pool=\"dma \\\"main\\\"\\\\\\n\"} 2\n\
+             # HELP ft60x_pool_buffers_in_use Buffers taken from the pool.\n\
+             # TYPE ft60x_pool_buffers_in_use gauge\n\
+             ft60x_pool_buffers_in_use{pool=\"dma \\\"main\\\"\\\\\\n\"} 1\n\
+             # HELP daq_dropped_frames Frames lost to CRC errors.\n\
+             # TYPE daq_dropped_frames counter\n\
+             daq_dropped_frames_total 3\n\
+             # HELP daq_event_rate Events per second, \\\"built\\\" \\\\ read.\n\
+             # TYPE daq_event_rate gauge\n\
+             daq_event_rate 1250.5\n\
+             # EOF\n"
+        );
+    }
+
+    /// Device metrics need a device, so the info and summary families are rendered
+    /// directly.
+    #[test]
+    fn info_and_summary() {
+        let render = |format| {
+            let mut out = Exposition::new(format);
+            out.info(
+                "ft60x_device",
+                "Device identity.",
+                &[
+                    ("serial", "000000000001"),
+                    ("description", "FTDI \"SuperSpeed\""),
+                ],
+            );
+            let labels = [("serial", "000000000001"), ("pipe", "In0")];
+            out.summary(
+                "ft60x_pipe_latency_seconds",
+                "Transfer latency.",
+                &labels,
+                &[
+                    (0.5, Duration::from_micros(250)),
+                    (0.99, Duration::from_micros(1500)),
+                ],
+                0.75,
+                1000,
+            );
+            out.finish()
+        };
+        let samples = "ft60x_pipe_latency_seconds{serial=\"000000000001\",pipe=\"In0\",quantile=\"0.5\"} 0.00025\n\
+             ft60x_pipe_latency_seconds{serial=\"000000000001\",pipe=\"In0\",quantile=\"0.99\"} 0.0015\n\
+             ft60x_pipe_latency_seconds_sum{serial=\"000000000001\",pipe=\"In0\"} 0.75\n\
+             ft60x_pipe_latency_seconds_count{serial=\"000000000001\",pipe=\"In0\"} 1000\n";
+        let info = "ft60x_device_info{serial=\"000000000001\",description=\"FTDI \\\"SuperSpeed\\\"\"} 1\n";
+
+        assert_eq!(
+            render(ExpositionFormat::Prometheus),
+            format!(
+                "# HELP ft60x_device_info Device identity.\n\
+                 # TYPE ft60x_device_info gauge\n\
+                 {info}\
+                 # HELP ft60x_pipe_latency_seconds Transfer latency.\n\
+                 # TYPE ft60x_pipe_latency_seconds summary\n\
+                 {samples}"
+            )
+        );
+        assert_eq!(
+            render(ExpositionFormat::OpenMetrics),
+            format!(
+                "# HELP ft60x_device Device identity.\n\
+                 # TYPE ft60x_device info\n\
+                 {info}\
+                 # HELP ft60x_pipe_latency_seconds Transfer latency.\n\
+                 # TYPE ft60x_pipe_latency_seconds summary\n\
+                 {samples}\
+                 # EOF\n"
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_names() {
+        assert!(is_valid_name("daq:events_2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("2xx"));
+        assert!(!is_valid_name("daq-events"));
+    }
+}
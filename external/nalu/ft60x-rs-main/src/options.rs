@@ -0,0 +1,276 @@
+//! Options for opening a [`Device`].
+//!
+//! Pipe settings such as timeouts and stream sizes are lost whenever a device is
+//! closed. [`OpenOptions`] collects these settings and applies them each time a
+//! device is opened, so that they survive a reconnect through [`Device::reopen`].
+//!
+//! ```no_run
+//! # fn main() -> ft60x_rs::Result<()> {
+//! use std::time::Duration;
+//! use ft60x_rs::{OpenOptions, Pipe};
+//!
+//! let device = OpenOptions::new()
+//!     .timeout(Pipe::In0, Duration::from_millis(200))
+//!     .stream_size(Pipe::In0, 64 * 1024)
+//!     .flush_on_open(true)
+//!     .require_usb3(true)
+//!     .open("000000000001")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::{D3xxError, Device, DeviceInfo, Pipe, RecoveryPolicy, Result};
+
+/// Identifies the device to open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// Open the device with the given serial number.
+    SerialNumber(String),
+    /// Open the device with the given description.
+    Description(String),
+    /// Open the device at the given index in the D3XX device list.
+    Index(usize),
+}
+
+impl From<&str> for DeviceSelector {
+    /// Select a device by serial number.
+    fn from(serial_number: &str) -> Self {
+        DeviceSelector::SerialNumber(serial_number.to_owned())
+    }
+}
+
+impl From<String> for DeviceSelector {
+    /// Select a device by serial number.
+    fn from(serial_number: String) -> Self {
+        DeviceSelector::SerialNumber(serial_number)
+    }
+}
+
+impl From<&DeviceInfo> for DeviceSelector {
+    /// Select a device by its serial number.
+    fn from(info: &DeviceInfo) -> Self {
+        DeviceSelector::SerialNumber(info.serial_number().to_owned())
+    }
+}
+
+impl From<usize> for DeviceSelector {
+    /// Select a device by index.
+    fn from(index: usize) -> Self {
+        DeviceSelector::Index(index)
+    }
+}
+
+/// Settings applied to a device when it is opened.
+///
+/// Settings are applied in the following order:
+///
+/// 1. USB3 link check ([`require_usb3`](OpenOptions::require_usb3))
+/// 2. pipe timeouts
+/// 3. stream sizes
+/// 4. flushing IN pipes ([`flush_on_open`](OpenOptions::flush_on_open))
+///
+/// On Windows, where the driver stores pipe timeouts, each timeout is read back to
+/// verify it was accepted. On Linux the timeout is kept by this crate and passed
+/// with each transfer, so there is nothing to verify.
+///
+/// If any step fails in [`open`](OpenOptions::open), the device is closed and the
+/// error is returned, so a device is never handed out with only part of its
+/// settings applied. See [`apply`](OpenOptions::apply) for a device which is
+/// already open.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenOptions {
+    timeouts: BTreeMap<Pipe, Duration>,
+    stream_sizes: BTreeMap<Pipe, u32>,
+    recovery_policy: RecoveryPolicy,
+    pipe_recovery_policies: BTreeMap<Pipe, RecoveryPolicy>,
+    flush_on_open: bool,
+    require_usb3: bool,
+    stats: bool,
+}
+
+impl OpenOptions {
+    /// Create a new set of options which leaves every setting at the driver default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the timeout for a pipe. See [`Device::set_timeout`].
+    pub fn timeout(&mut self, pipe: Pipe, timeout: Duration) -> &mut Self {
+        self.timeouts.insert(pipe, timeout);
+        self
+    }
+
+    /// Enable the streaming protocol for a pipe. See [`Device::set_stream_size`].
+    ///
+    /// The D3XX library provides no way to read the stream size back, so this
+    /// setting is not verified.
+    pub fn stream_size(&mut self, pipe: Pipe, stream_size: u32) -> &mut Self {
+        self.stream_sizes.insert(pipe, stream_size);
+        self
+    }
+
+    /// Set the recovery policy used for pipes without a pipe-specific policy.
+    pub fn recovery_policy(&mut self, policy: RecoveryPolicy) -> &mut Self {
+        self.recovery_policy = policy;
+        self
+    }
+
+    /// Set the recovery policy for a single pipe.
+    pub fn pipe_recovery_policy(&mut self, pipe: Pipe, policy: RecoveryPolicy) -> &mut Self {
+        self.pipe_recovery_policies.insert(pipe, policy);
+        self
+    }
+
+    /// Discard data cached in the IN pipes when the device is opened.
+    ///
+    /// The IN pipes which have a timeout or stream size configured through these
+    /// options are flushed. If there are none, `In0` is flushed.
+    pub fn flush_on_open(&mut self, flush: bool) -> &mut Self {
+        self.flush_on_open = flush;
+        self
+    }
+
+    /// Fail with [`D3xxError::Usb3Required`] if the device is not connected
+    /// using a USB3 cable.
+    pub fn require_usb3(&mut self, require: bool) -> &mut Self {
+        self.require_usb3 = require;
+        self
+    }
+
+    /// Enable per-pipe transfer statistics. See [`Device::set_stats_enabled`].
+    pub fn stats(&mut self, enabled: bool) -> &mut Self {
+        self.stats = enabled;
+        self
+    }
+
+    /// Open a device and apply these options to it.
+    pub fn open(&self, selector: impl Into<DeviceSelector>) -> Result<Device> {
+        let selector = selector.into();
+        let mut device = Device::create(&selector)?;
+
+        // Not every selector identifies the device by serial number, so take it and
+        // the chip type from the device list. The device can still be used if it can't be found there.
+        if let Ok(info) = device.refresh_info() {
+            if let Some(inner) = std::sync::Arc::get_mut(&mut device.inner) {
+                inner.serial_number = Some(info.serial_number().to_owned());
+                inner.chip = info.chip_type();
+            }
+        }
+
+        self.apply(&mut device)?;
+        device.selector = Some(selector);
+        device.options = self.clone();
+        Ok(device)
+    }
+
+    /// Apply these options to an open device.
+    ///
+    /// If a step fails, the pipe timeouts changed by this call are restored and the
+    /// stream sizes it set are cleared, since the D3XX library provides no way to
+    /// read the previous stream size. Data discarded by a flush is not restored.
+    /// Statistics and recovery policies are only changed once every other step has
+    /// succeeded.
+    pub fn apply(&self, device: &mut Device) -> Result<()> {
+        if self.require_usb3 && !device.is_usb3()? {
+            return Err(device.inner.device_error(D3xxError::Usb3Required.into()));
+        }
+
+        let mut previous_timeouts = Vec::new();
+        let mut stream_sizes_set = Vec::new();
+        let result =
+            self.apply_pipe_settings(device, &mut previous_timeouts, &mut stream_sizes_set);
+        if result.is_err() {
+            // Failures here are ignored; the original error is more useful.
+            for &pipe in &stream_sizes_set {
+                let _ = device.set_stream_size(pipe, None);
+            }
+            for &(pipe, timeout) in &previous_timeouts {
+                let _ = device.set_timeout(pipe, timeout);
+            }
+            return result;
+        }
+
+        if self.stats {
+            device.set_stats_enabled(true);
+        }
+
+        device.set_recovery_policy(self.recovery_policy);
+        for (&pipe, &policy) in &self.pipe_recovery_policies {
+            device.set_pipe_recovery_policy(pipe, Some(policy));
+        }
+        Ok(())
+    }
+
+    /// Applies the timeouts, stream sizes and flushes, recording what was changed so
+    /// [`apply`](OpenOptions::apply) can undo it.
+    fn apply_pipe_settings(
+        &self,
+        device: &Device,
+        previous_timeouts: &mut Vec<(Pipe, Duration)>,
+        stream_sizes_set: &mut Vec<Pipe>,
+    ) -> Result<()> {
+        for (&pipe, &timeout) in &self.timeouts {
+            previous_timeouts.push((pipe, device.get_timeout(pipe)?));
+            device.set_timeout(pipe, timeout)?;
+            #[cfg(windows)]
+            if device.get_timeout(pipe)?.as_millis() != timeout.as_millis() {
+                let e = D3xxError::SettingNotApplied.into();
+                return Err(device.inner.pipe_error(pipe, e));
+            }
+        }
+
+        for (&pipe, &stream_size) in &self.stream_sizes {
+            stream_sizes_set.push(pipe);
+            device.set_stream_size(pipe, Some(stream_size))?;
+        }
+
+        if self.flush_on_open {
+            for pipe in self.flushed_pipes() {
+                device.flush(pipe)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The IN pipes flushed by [`flush_on_open`](OpenOptions::flush_on_open).
+    fn flushed_pipes(&self) -> Vec<Pipe> {
+        let mut pipes: Vec<Pipe> = self
+            .timeouts
+            .keys()
+            .chain(self.stream_sizes.keys())
+            .copied()
+            .filter(Pipe::is_read_pipe)
+            .collect();
+        if pipes.is_empty() {
+            pipes.push(Pipe::In0);
+        }
+        pipes.sort();
+        pipes.dedup();
+        pipes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flushes_configured_in_pipes() {
+        let mut options = OpenOptions::new();
+        options
+            .timeout(Pipe::In2, Duration::from_secs(1))
+            .timeout(Pipe::Out0, Duration::from_secs(1))
+            .stream_size(Pipe::In1, 4096)
+            .stream_size(Pipe::In2, 4096);
+        assert_eq!(options.flushed_pipes(), [Pipe::In1, Pipe::In2]);
+    }
+
+    #[test]
+    fn flushes_in0_by_default() {
+        let mut options = OpenOptions::new();
+        options.timeout(Pipe::Out1, Duration::from_secs(1));
+        assert_eq!(options.flushed_pipes(), [Pipe::In0]);
+    }
+}
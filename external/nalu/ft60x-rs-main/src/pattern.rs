@@ -0,0 +1,515 @@
+//! Test pattern generation and checking for link validation.
+//!
+//! FPGA test modes commonly stream a 32-bit counter or a PRBS-31 sequence into the
+//! FIFO. [`PatternChecker`] validates such a stream as it is read and
+//! [`PatternGenerator`] produces the same sequence for writing to an OUT pipe, e.g.
+//! for loopback tests.
+//!
+//! Words are 32 bits wide and stored little-endian, as an FT601 delivers a 32-bit
+//! FIFO word to host memory. PRBS-31 uses the polynomial x³¹ + x²⁸ + 1, with the
+//! first bit of each word in its most significant bit. Each word of either pattern
+//! determines the next, so the checker synchronizes on the first word it sees and
+//! can resynchronize after a burst of errors.
+//!
+//! Neither type touches the device, so both can be exercised on synthetic buffers:
+//!
+//! ```
+//! use ft60x_rs::{Pattern, PatternChecker, PatternGenerator};
+//!
+//! let mut buf = vec![0; 4096];
+//! PatternGenerator::new(Pattern::Prbs31, 1).fill(&mut buf).unwrap();
+//! buf[100] ^= 0x04; // flip one bit
+//!
+//! let mut checker = PatternChecker::new(Pattern::Prbs31);
+//! checker.check(&buf);
+//! let report = checker.report();
+//! assert_eq!(report.bit_errors, 1);
+//! assert_eq!(report.first_error_offset, Some(100));
+//! ```
+
+use crate::{D3xxError, Result};
+
+/// A test pattern made of 32-bit words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Pattern {
+    /// A counter incremented by one each word, wrapping at `u32::MAX`.
+    Counter,
+    /// The PRBS-31 sequence, x³¹ + x²⁸ + 1.
+    Prbs31,
+}
+
+impl Pattern {
+    /// The word which follows `word` in the pattern.
+    pub fn next_word(&self, word: u32) -> u32 {
+        match self {
+            Self::Counter => word.wrapping_add(1),
+            Self::Prbs31 => {
+                // Bit 0 holds the newest bit of the sequence. The next bit is the XOR
+                // of the bits 31 and 28 positions back, i.e. bits 30 and 27.
+                let mut history = word;
+                for _ in 0..32 {
+                    let bit = ((history >> 30) ^ (history >> 27)) & 1;
+                    history = (history << 1) | bit;
+                }
+                history
+            }
+        }
+    }
+}
+
+/// Generates a test pattern.
+#[derive(Debug, Clone)]
+pub struct PatternGenerator {
+    pattern: Pattern,
+    next: u32,
+}
+
+impl PatternGenerator {
+    /// Creates a generator whose first word is `seed`.
+    ///
+    /// A PRBS-31 sequence never contains 31 consecutive zero bits, so for
+    /// [`Pattern::Prbs31`] a seed whose low 31 bits are zero is replaced by 1.
+    pub fn new(pattern: Pattern, seed: u32) -> Self {
+        let next = match pattern {
+            Pattern::Prbs31 if seed & 0x7fff_ffff == 0 => 1,
+            _ => seed,
+        };
+        Self { pattern, next }
+    }
+
+    /// The pattern being generated.
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    /// Returns the next word.
+    pub fn next_word(&mut self) -> u32 {
+        let word = self.next;
+        self.next = self.pattern.next_word(word);
+        word
+    }
+
+    /// Fills `buf` with the next words of the pattern.
+    ///
+    /// # Errors
+    /// [`D3xxError::MisalignedLength`] if the length of `buf` is not a multiple of
+    /// four bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        if !buf.len().is_multiple_of(4) {
+            Err(D3xxError::MisalignedLength)?;
+        }
+        for chunk in buf.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&self.next_word().to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for PatternGenerator {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        Some(self.next_word())
+    }
+}
+
+/// The results of a [`PatternChecker`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PatternReport {
+    /// Words checked, including the word the checker synchronized on.
+    pub words: u64,
+    /// Bits which differ from the expected pattern.
+    pub bit_errors: u64,
+    /// Words which differ from the expected pattern and were not explained as
+    /// dropped or duplicated words.
+    pub word_errors: u64,
+    /// Byte offset in the stream of the first word error, dropped word or
+    /// duplicated word.
+    pub first_error_offset: Option<u64>,
+    /// Words missing from the stream.
+    pub dropped_words: u64,
+    /// Words repeated in the stream.
+    pub duplicated_words: u64,
+    /// Times the checker gave up on its prediction after consecutive word errors and
+    /// synchronized on the received data again.
+    pub resyncs: u64,
+}
+
+impl PatternReport {
+    /// The fraction of checked bits which were wrong.
+    pub fn bit_error_rate(&self) -> f64 {
+        match self.words {
+            0 => 0.0,
+            words => self.bit_errors as f64 / (words as f64 * 32.0),
+        }
+    }
+
+    /// Whether the stream matched the pattern exactly.
+    pub fn is_clean(&self) -> bool {
+        self.first_error_offset.is_none()
+    }
+}
+
+/// Checks a stream against a test pattern.
+///
+/// Data may be passed to [`PatternChecker::check`] in pieces of any length; a word
+/// split between two pieces is reassembled.
+///
+/// When a word does not match the prediction the checker tries, in order:
+///
+/// 1. a *duplicated* word: the word equals the previous one, which was received
+///    and matched the pattern;
+/// 2. *dropped* words: the word appears within the next
+///    [`max_skip`](PatternChecker::set_max_skip) words of the pattern;
+/// 3. otherwise the word is counted as a *word error* and its differing bits as
+///    bit errors, and the prediction advances by one word.
+///
+/// After [`resync_threshold`](PatternChecker::set_resync_threshold) consecutive word
+/// errors the checker assumes it has lost the sequence and synchronizes on the
+/// received data again.
+///
+/// ```
+/// use ft60x_rs::{Pattern, PatternChecker};
+///
+/// let mut checker = PatternChecker::new(Pattern::Counter);
+/// for word in [10, 11, 11, 12, 15, 16] {
+///     checker.check_word(word);
+/// }
+/// let report = checker.report();
+/// assert_eq!(report.duplicated_words, 1);
+/// assert_eq!(report.dropped_words, 2);
+/// assert_eq!(report.first_error_offset, Some(8));
+/// assert_eq!(report.bit_errors, 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PatternChecker {
+    pattern: Pattern,
+    max_skip: u32,
+    resync_threshold: u32,
+    /// The previous word of the pattern, once synchronized.
+    previous: Option<u32>,
+    /// Whether `previous` was received. After a word error it holds the word which
+    /// was expected instead, and a repeat of it is not a duplicate.
+    previous_received: bool,
+    expected: u32,
+    consecutive_errors: u32,
+    partial: [u8; 4],
+    partial_len: usize,
+    report: PatternReport,
+}
+
+impl PatternChecker {
+    /// Creates a checker which synchronizes on the first word it is given.
+    ///
+    /// Up to 1024 dropped words are recognized in a counter and up to 64 in PRBS-31,
+    /// where each skipped word has to be searched for. Four consecutive word errors
+    /// cause a resync.
+    pub fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            max_skip: match pattern {
+                Pattern::Counter => 1024,
+                Pattern::Prbs31 => 64,
+            },
+            resync_threshold: 4,
+            previous: None,
+            previous_received: false,
+            expected: 0,
+            consecutive_errors: 0,
+            partial: [0; 4],
+            partial_len: 0,
+            report: PatternReport::default(),
+        }
+    }
+
+    /// The pattern being checked.
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    /// Sets the largest number of consecutive dropped words which is recognized as
+    /// such. Larger gaps are reported as word errors, and eventually a resync.
+    pub fn set_max_skip(&mut self, max_skip: u32) -> &mut Self {
+        self.max_skip = max_skip;
+        self
+    }
+
+    /// Sets the number of consecutive word errors after which the checker
+    /// resynchronizes. Must be at least 1.
+    pub fn set_resync_threshold(&mut self, threshold: u32) -> &mut Self {
+        self.resync_threshold = threshold.max(1);
+        self
+    }
+
+    /// Checks the next piece of the stream.
+    pub fn check(&mut self, mut data: &[u8]) {
+        if self.partial_len > 0 {
+            let n = (4 - self.partial_len).min(data.len());
+            self.partial[self.partial_len..self.partial_len + n].copy_from_slice(&data[..n]);
+            self.partial_len += n;
+            data = &data[n..];
+            if self.partial_len < 4 {
+                return;
+            }
+            self.partial_len = 0;
+            self.check_word(u32::from_le_bytes(self.partial));
+        }
+        let mut words = data.chunks_exact(4);
+        for word in &mut words {
+            self.check_word(u32::from_le_bytes(word.try_into().unwrap()));
+        }
+        let rest = words.remainder();
+        self.partial[..rest.len()].copy_from_slice(rest);
+        self.partial_len = rest.len();
+    }
+
+    /// Checks a single word.
+    pub fn check_word(&mut self, word: u32) {
+        let offset = self.report.words * 4;
+        self.report.words += 1;
+
+        let Some(previous) = self.previous else {
+            self.sync(word);
+            return;
+        };
+        if word == self.expected {
+            self.consecutive_errors = 0;
+            self.sync(word);
+            return;
+        }
+
+        self.report.first_error_offset.get_or_insert(offset);
+        if self.previous_received && word == previous {
+            self.report.duplicated_words += 1;
+            return;
+        }
+        if let Some(skipped) = self.skipped_words(word) {
+            self.report.dropped_words += u64::from(skipped);
+            self.consecutive_errors = 0;
+            self.sync(word);
+            return;
+        }
+
+        self.report.word_errors += 1;
+        self.report.bit_errors += u64::from((word ^ self.expected).count_ones());
+        self.consecutive_errors += 1;
+        if self.consecutive_errors >= self.resync_threshold {
+            self.report.resyncs += 1;
+            self.consecutive_errors = 0;
+            self.sync(word);
+        } else {
+            self.sync(self.expected);
+            self.previous_received = false;
+        }
+    }
+
+    /// The results so far.
+    pub fn report(&self) -> &PatternReport {
+        &self.report
+    }
+
+    /// Clears the results and the synchronization.
+    pub fn reset(&mut self) {
+        *self = Self {
+            max_skip: self.max_skip,
+            resync_threshold: self.resync_threshold,
+            ..Self::new(self.pattern)
+        };
+    }
+
+    /// Takes `word` as correct and predicts the word after it.
+    fn sync(&mut self, word: u32) {
+        self.previous = Some(word);
+        self.previous_received = true;
+        self.expected = self.pattern.next_word(word);
+    }
+
+    /// The number of words missing before `word`, if it appears within the next
+    /// `max_skip` words after the expected one.
+    fn skipped_words(&self, word: u32) -> Option<u32> {
+        match self.pattern {
+            Pattern::Counter => {
+                let skipped = word.wrapping_sub(self.expected);
+                (skipped <= self.max_skip).then_some(skipped)
+            }
+            Pattern::Prbs31 => {
+                let mut candidate = self.expected;
+                for skipped in 1..=self.max_skip {
+                    candidate = self.pattern.next_word(candidate);
+                    if candidate == word {
+                        return Some(skipped);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prbs(words: usize) -> Vec<u32> {
+        PatternGenerator::new(Pattern::Prbs31, 0x1234_5678)
+            .take(words)
+            .collect()
+    }
+
+    fn check_words(pattern: Pattern, words: &[u32]) -> PatternReport {
+        let mut checker = PatternChecker::new(pattern);
+        for &word in words {
+            checker.check_word(word);
+        }
+        checker.report().clone()
+    }
+
+    #[test]
+    fn clean_stream() {
+        let report = check_words(Pattern::Prbs31, &prbs(1000));
+        assert!(report.is_clean());
+        assert_eq!(report.words, 1000);
+        assert_eq!(report.bit_error_rate(), 0.0);
+    }
+
+    #[test]
+    fn dropped_words() {
+        let mut words = prbs(100);
+        words.drain(40..43);
+        let report = check_words(Pattern::Prbs31, &words);
+        assert_eq!(report.dropped_words, 3);
+        assert_eq!(report.word_errors, 0);
+        assert_eq!(report.first_error_offset, Some(40 * 4));
+
+        // Counter gaps wrap around `u32::MAX`.
+        let report = check_words(Pattern::Counter, &[u32::MAX - 1, u32::MAX, 2, 3]);
+        assert_eq!(report.dropped_words, 2);
+        assert_eq!(report.word_errors, 0);
+    }
+
+    #[test]
+    fn gap_beyond_max_skip_is_word_error() {
+        let mut words = prbs(200);
+        words.drain(40..110);
+        let report = check_words(Pattern::Prbs31, &words);
+        assert_eq!(report.dropped_words, 0);
+        assert_eq!(report.word_errors, 4);
+        assert_eq!(report.resyncs, 1);
+    }
+
+    #[test]
+    fn duplicated_words() {
+        let mut words = prbs(100);
+        words.insert(50, words[49]);
+        words.insert(50, words[49]);
+        let report = check_words(Pattern::Prbs31, &words);
+        assert_eq!(report.duplicated_words, 2);
+        assert_eq!(report.word_errors, 0);
+        assert_eq!(report.first_error_offset, Some(50 * 4));
+    }
+
+    #[test]
+    fn resync() {
+        // The stream jumps out of reach of `max_skip` and continues from there.
+        let mut words: Vec<u32> = (0..10).collect();
+        words.extend(5000..5010);
+        let mut checker = PatternChecker::new(Pattern::Counter);
+        checker.set_resync_threshold(3);
+        for &word in &words {
+            checker.check_word(word);
+        }
+        let report = checker.report();
+        assert_eq!(report.word_errors, 3);
+        assert_eq!(report.resyncs, 1);
+        assert_eq!(report.dropped_words, 0);
+        assert_eq!(report.first_error_offset, Some(10 * 4));
+
+        // After the resync the rest of the stream is clean.
+        checker.check_word(5010);
+        assert_eq!(checker.report().word_errors, 3);
+    }
+
+    #[test]
+    fn word_error_keeps_prediction() {
+        // A corrupted word repeated is two word errors, not a duplicate, and the
+        // stream continues where the pattern predicts.
+        let report = check_words(Pattern::Counter, &[1, 2, 0xff00, 0xff00, 5, 6]);
+        assert_eq!(report.word_errors, 2);
+        assert_eq!(report.duplicated_words, 0);
+        assert_eq!(report.dropped_words, 0);
+        assert_eq!(report.resyncs, 0);
+        assert_eq!(
+            report.bit_errors,
+            u64::from((0xff00u32 ^ 3).count_ones() + (0xff00u32 ^ 4).count_ones())
+        );
+
+        // The word in place of an error arriving late is a word error, not a
+        // duplicate, as it was never received before.
+        let report = check_words(Pattern::Counter, &[1, 2, 0xff00, 3]);
+        assert_eq!(report.duplicated_words, 0);
+        assert_eq!(report.word_errors, 2);
+
+        // Once the stream is back on the pattern, a repeated word is a duplicate.
+        let report = check_words(Pattern::Counter, &[1, 2, 0xff00, 4, 5, 5]);
+        assert_eq!(report.word_errors, 1);
+        assert_eq!(report.duplicated_words, 1);
+        assert_eq!(report.first_error_offset, Some(2 * 4));
+    }
+
+    #[test]
+    fn words_split_across_checks() {
+        let mut buf = vec![0; 4096];
+        PatternGenerator::new(Pattern::Prbs31, 7)
+            .fill(&mut buf)
+            .unwrap();
+        buf[1001] ^= 0x80;
+
+        let mut checker = PatternChecker::new(Pattern::Prbs31);
+        let mut rest = &buf[..];
+        for len in [1, 2, 3, 5, 7, 11].into_iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (piece, tail) = rest.split_at(len.min(rest.len()));
+            checker.check(piece);
+            rest = tail;
+        }
+        let report = checker.report();
+        assert_eq!(report.words, 1024);
+        assert_eq!(report.word_errors, 1);
+        assert_eq!(report.bit_errors, 1);
+        assert_eq!(report.first_error_offset, Some(1000));
+    }
+
+    #[test]
+    fn prbs_bit_errors() {
+        let mut words = prbs(1000);
+        words[10] ^= 0x8000_0001;
+        words[500] ^= 0x0001_0000;
+        words[999] ^= 0xf000_0000;
+        let report = check_words(Pattern::Prbs31, &words);
+        assert_eq!(report.word_errors, 3);
+        assert_eq!(report.bit_errors, 7);
+        assert_eq!(report.first_error_offset, Some(10 * 4));
+        assert_eq!(report.resyncs, 0);
+        assert_eq!(report.bit_error_rate(), 7.0 / (1000.0 * 32.0));
+    }
+
+    #[test]
+    fn reset_keeps_settings() {
+        let mut checker = PatternChecker::new(Pattern::Counter);
+        checker.set_max_skip(0).set_resync_threshold(2);
+        checker.check_word(1);
+        checker.check_word(3);
+        assert_eq!(checker.report().word_errors, 1);
+        checker.reset();
+        assert_eq!(checker.report(), &PatternReport::default());
+        for word in [1, 3, 5, 7] {
+            checker.check_word(word);
+        }
+        assert_eq!(checker.report().resyncs, 1);
+    }
+}
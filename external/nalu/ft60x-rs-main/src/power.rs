@@ -0,0 +1,77 @@
+//! Power management.
+//!
+//! The host may selectively suspend a device which has been idle for longer than its
+//! suspend timeout. A suspended device does not respond to transfers until it is
+//! resumed, so a read issued after a long idle period can fail with
+//! [`DeviceNotConnected`](crate::D3xxError::DeviceNotConnected).
+//!
+//! [`Device::disable_suspend`](crate::Device::disable_suspend) turns selective suspend
+//! off until the returned [`SuspendGuard`] is dropped:
+//!
+//! ```no_run
+//! # fn main() -> ft60x_rs::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! let guard = device.disable_suspend()?;
+//! // ... acquire data ...
+//! drop(guard); // the previous suspend timeout is restored
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The suspend timeout is only supported by the Windows driver; on other platforms
+//! these functions fail with [`NotSupported`](crate::D3xxError::NotSupported).
+
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use crate::{DeviceHandle, Result};
+
+/// Keeps selective suspend disabled while alive, created by
+/// [`Device::disable_suspend`](crate::Device::disable_suspend).
+///
+/// The suspend timeout in effect before the guard was created is restored when the
+/// guard is dropped. The guard keeps the device handle open until then.
+pub struct SuspendGuard {
+    device: Arc<DeviceHandle>,
+    previous: Duration,
+    restored: bool,
+}
+
+impl SuspendGuard {
+    pub(crate) fn new(device: Arc<DeviceHandle>) -> Result<Self> {
+        let previous = device.suspend_timeout()?;
+        device.set_suspend_timeout(Duration::ZERO)?;
+        Ok(Self {
+            device,
+            previous,
+            restored: false,
+        })
+    }
+
+    /// The suspend timeout which will be restored when the guard is dropped.
+    pub fn previous_timeout(&self) -> Duration {
+        self.previous
+    }
+
+    /// Restores the previous suspend timeout now, returning any error.
+    pub fn restore(mut self) -> Result<()> {
+        self.restored = true;
+        self.device.set_suspend_timeout(self.previous)
+    }
+}
+
+impl Drop for SuspendGuard {
+    fn drop(&mut self) {
+        if !self.restored {
+            let _ = self.device.set_suspend_timeout(self.previous);
+        }
+    }
+}
+
+impl Debug for SuspendGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SuspendGuard")
+            .field("handle", &self.device.handle)
+            .field("previous", &self.previous)
+            .finish()
+    }
+}
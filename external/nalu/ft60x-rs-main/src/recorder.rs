@@ -0,0 +1,973 @@
+//! Recording a pipe to disk for long runs.
+//!
+//! A [`Recorder`] writes the data it is given to a series of *segment* files in one
+//! directory. Segments are rotated by size and/or age, optionally compressed, and
+//! listed with their checksums in a manifest. Compression, checksums and file I/O
+//! happen on a background thread, so the thread reading the device only copies each
+//! buffer into a queue.
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! use std::time::Duration;
+//! use ft60x_rs::{recorder::{Compression, Recorder, RecorderConfig}, Pipe, StreamConfig};
+//!
+//! let mut config = RecorderConfig::new("/data/run-042");
+//! config.segment_duration = Some(Duration::from_secs(600));
+//! config.compression = Compression::Lz4;
+//!
+//! let mut recorder = Recorder::new(config)?;
+//! let mut stream = device.stream(Pipe::In0, StreamConfig::default())?;
+//! recorder.drain(&mut stream, |stats| stats.bytes >= 1 << 40)?;
+//! for segment in recorder.finish()? {
+//!     println!("{} {}", segment.file_name, segment.sha256_hex());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Files
+//!
+//! Segments are named `<prefix>-<index>.bin`, with the index zero-padded to six
+//! digits and `.zst` or `.lz4` appended when compressed. Each holds the raw bytes
+//! read from the pipe, so concatenating the decompressed segments in order
+//! reproduces the stream. A buffer is never split between segments.
+//!
+//! The manifest, `<prefix>.manifest`, is rewritten each time a segment is closed, so
+//! it lists every complete segment even if the process is killed. It is plain text:
+//! lines starting with `#` are comments, and each other line describes one segment
+//! with the tab-separated fields
+//!
+//! | Field          | Contents                                             |
+//! |----------------|------------------------------------------------------|
+//! | `file`         | File name, relative to the manifest                  |
+//! | `start_ns`     | When the segment was opened, in ns since the Unix epoch |
+//! | `duration_ns`  | Time from opening to closing the segment, in ns      |
+//! | `bytes`        | Bytes of data in the segment, before compression     |
+//! | `stored_bytes` | Size of the file                                     |
+//! | `sha256`       | SHA-256 of the file, in lowercase hex                |
+//!
+//! A final `# complete` line is added by [`Recorder::finish`]. A recorder refuses to
+//! start in a directory which already holds a manifest with the same prefix.
+//!
+//! # Disk space
+//!
+//! Before opening each segment and about once a second while writing, the recorder
+//! checks the space available to unprivileged users on the file system holding the
+//! directory. If it is below [`RecorderConfig::min_free_bytes`] the current segment
+//! is closed and recording stops with [`io::ErrorKind::StorageFull`]. The check is
+//! only made on Unix.
+
+use std::{
+    fmt::{Debug, Write as _},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{lz4::Lz4Encoder, D3xxError, Stream};
+
+/// How often the background thread checks segment age, the fsync interval and free
+/// space when no data arrives, and how often [`Recorder::drain`] polls its condition.
+const TICK: Duration = Duration::from_millis(100);
+/// How often free space is checked while a segment is open.
+const SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Buffer size of the file writer.
+const FILE_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Compression applied to segment files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Segments hold the raw data.
+    #[default]
+    None,
+    /// Zstandard at the given level, from 1 (fastest) to 22. Level 0 selects the
+    /// library default. Requires the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    /// LZ4 frames, compressed with a fast single-pass match finder. Much faster than
+    /// Zstandard, for links whose rate the disk cannot otherwise sustain.
+    Lz4,
+}
+
+impl Compression {
+    /// The extension of segment files.
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::None => "bin",
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => "bin.zst",
+            Self::Lz4 => "bin.lz4",
+        }
+    }
+}
+
+/// When segment files are flushed to stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FsyncPolicy {
+    /// Leave it to the operating system. Data not yet written back is lost if the
+    /// machine crashes, but recording is never held up by `fsync`.
+    Never,
+    /// Sync each segment and the manifest when the segment is closed.
+    #[default]
+    Segment,
+    /// Sync the open segment at this interval, in addition to [`FsyncPolicy::Segment`].
+    /// Compressors are flushed first, which costs a little compression ratio.
+    Interval(Duration),
+}
+
+/// Configuration for a [`Recorder`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecorderConfig {
+    /// Directory the segments and manifest are written to. Created if it does not
+    /// exist.
+    pub directory: PathBuf,
+    /// Prefix of the file names.
+    pub prefix: String,
+    /// Close a segment once it holds at least this many bytes, before compression.
+    pub segment_bytes: Option<u64>,
+    /// Close a segment once it has been open this long. The age is checked at least
+    /// every 100 ms.
+    pub segment_duration: Option<Duration>,
+    /// Compression applied to each segment.
+    pub compression: Compression,
+    /// When segments are flushed to stable storage.
+    pub fsync: FsyncPolicy,
+    /// Stop recording when less than this many bytes are available on the file
+    /// system. See the [module documentation](self#disk-space).
+    pub min_free_bytes: u64,
+    /// Number of buffers which may wait for the background thread before
+    /// [`Recorder::write`] blocks.
+    pub queue_len: usize,
+}
+
+impl RecorderConfig {
+    /// Creates a configuration for recording into `directory`.
+    ///
+    /// Files are prefixed with `ft60x` and segments are closed after 1 GiB,
+    /// uncompressed and synced when closed. Recording stops when less than 1 GiB is
+    /// available, and up to 64 buffers are queued.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            prefix: "ft60x".to_owned(),
+            segment_bytes: Some(1 << 30),
+            segment_duration: None,
+            compression: Compression::None,
+            fsync: FsyncPolicy::Segment,
+            min_free_bytes: 1 << 30,
+            queue_len: 64,
+        }
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.directory.join(format!("{}.manifest", self.prefix))
+    }
+}
+
+/// A closed segment, as listed in the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    /// File name, relative to [`RecorderConfig::directory`].
+    pub file_name: String,
+    /// When the segment was opened.
+    pub start: SystemTime,
+    /// Time from opening to closing the segment.
+    pub duration: Duration,
+    /// Bytes of data in the segment, before compression.
+    pub bytes: u64,
+    /// Size of the file.
+    pub stored_bytes: u64,
+    /// SHA-256 of the file.
+    pub sha256: [u8; 32],
+}
+
+impl Segment {
+    /// The SHA-256 of the file in lowercase hex, as listed in the manifest and
+    /// printed by `sha256sum`.
+    pub fn sha256_hex(&self) -> String {
+        self.sha256.iter().fold(String::new(), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        })
+    }
+}
+
+/// Progress of a [`Recorder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecorderStats {
+    /// Bytes written to segments, before compression.
+    pub bytes: u64,
+    /// Bytes written to segment files.
+    pub stored_bytes: u64,
+    /// Segments closed.
+    pub segments: u64,
+    /// Buffers waiting for the background thread.
+    pub queued: usize,
+}
+
+/// Counters shared with the background thread.
+#[derive(Default)]
+struct Shared {
+    bytes: AtomicU64,
+    stored_bytes: AtomicU64,
+    segments: AtomicU64,
+    queued: AtomicUsize,
+    /// Set by [`Recorder::finish`], so that dropping a recorder does not mark the
+    /// manifest complete.
+    finishing: AtomicBool,
+}
+
+type Chunk = Box<dyn AsRef<[u8]> + Send>;
+
+/// Writes data to rotating, optionally compressed segment files on a background
+/// thread. See the [module documentation](self).
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// use ft60x_rs::recorder::{Compression, Recorder, RecorderConfig};
+///
+/// let directory = tempfile::tempdir()?;
+/// let mut config = RecorderConfig::new(directory.path());
+/// config.segment_bytes = Some(64 * 1024);
+/// config.compression = Compression::Lz4;
+/// config.min_free_bytes = 0;
+///
+/// let mut recorder = Recorder::new(config)?;
+/// for _ in 0..5 {
+///     recorder.write(&[0x5a; 48 * 1024])?;
+/// }
+/// let segments = recorder.finish()?;
+/// assert_eq!(segments.len(), 3);
+/// assert_eq!(segments[0].file_name, "ft60x-000000.bin.lz4");
+/// assert_eq!(segments[0].bytes, 96 * 1024);
+/// assert!(segments[0].stored_bytes < 1024);
+///
+/// let manifest = std::fs::read_to_string(directory.path().join("ft60x.manifest"))?;
+/// assert!(manifest.contains(&segments[2].sha256_hex()));
+/// assert!(manifest.ends_with("# complete\n"));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Dropping a recorder without calling [`Recorder::finish`] still closes the last
+/// segment, but errors are ignored and the manifest is not marked complete.
+pub struct Recorder {
+    directory: PathBuf,
+    sender: Option<SyncSender<Chunk>>,
+    thread: Option<JoinHandle<io::Result<Vec<Segment>>>>,
+    shared: Arc<Shared>,
+}
+
+impl Recorder {
+    /// Creates the directory if needed and starts the background thread. No file is
+    /// created until data is written.
+    ///
+    /// # Errors
+    /// - [`io::ErrorKind::InvalidInput`] if the prefix is empty or contains a path
+    ///   separator, `segment_bytes` is zero or `queue_len` is zero.
+    /// - [`io::ErrorKind::AlreadyExists`] if the directory already holds a manifest
+    ///   with the same prefix.
+    pub fn new(config: RecorderConfig) -> io::Result<Self> {
+        if config.prefix.is_empty()
+            || config.prefix.contains(['/', '\\'])
+            || config.segment_bytes == Some(0)
+            || config.queue_len == 0
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid recorder configuration",
+            ));
+        }
+        fs::create_dir_all(&config.directory)?;
+        let manifest = config.manifest_path();
+        if manifest.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", manifest.display()),
+            ));
+        }
+
+        let shared = Arc::new(Shared::default());
+        let (sender, receiver) = mpsc::sync_channel(config.queue_len);
+        let directory = config.directory.clone();
+        let writer = SegmentWriter {
+            config,
+            shared: shared.clone(),
+            segments: Vec::new(),
+            current: None,
+            last_sync: Instant::now(),
+            last_space_check: Instant::now(),
+        };
+        let thread = std::thread::Builder::new()
+            .name("ft60x-recorder".to_owned())
+            .spawn(move || writer.run(receiver))?;
+        Ok(Self {
+            directory,
+            sender: Some(sender),
+            thread: Some(thread),
+            shared,
+        })
+    }
+
+    /// The directory being recorded into.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Queues a copy of `data` to be written. Blocks while the queue is full.
+    ///
+    /// # Errors
+    /// The error which stopped the background thread, e.g.
+    /// [`io::ErrorKind::StorageFull`] if the disk-space guard tripped.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_buffer(data.to_vec())
+    }
+
+    /// Queues `buffer` to be written without copying it. Blocks while the queue is
+    /// full.
+    ///
+    /// The buffer is held until it has been written, so queuing a
+    /// [`StreamBuffer`](crate::StreamBuffer) this way keeps it from returning to the
+    /// stream's pool; see [`Recorder::drain`].
+    ///
+    /// # Errors
+    /// As for [`Recorder::write`].
+    pub fn write_buffer(&mut self, buffer: impl AsRef<[u8]> + Send + 'static) -> io::Result<()> {
+        if buffer.as_ref().is_empty() {
+            return Ok(());
+        }
+        let Some(sender) = &self.sender else {
+            return Err(stopped());
+        };
+        self.shared.queued.fetch_add(1, Ordering::Relaxed);
+        if sender.send(Box::new(buffer)).is_err() {
+            self.shared.queued.fetch_sub(1, Ordering::Relaxed);
+            // The background thread only exits early on an error.
+            return match self.join() {
+                Err(e) => Err(e),
+                Ok(_) => Err(stopped()),
+            };
+        }
+        Ok(())
+    }
+
+    /// Records buffers from `stream` until `stop` returns `true` or the stream fails.
+    ///
+    /// `stop` is called before each buffer and at least every 100 ms. Each buffer is
+    /// copied into the queue and returned to the stream's pool straight away, so the
+    /// queue rather than the stream absorbs stalls of the disk.
+    ///
+    /// A read which times out only means the device had no data for a while, so the
+    /// stream is [restarted](Stream::restart) and recording carries on.
+    ///
+    /// # Errors
+    /// Any other error from the stream, or from restarting it, converted to an
+    /// [`io::Error`], or as for [`Recorder::write`].
+    pub fn drain(
+        &mut self,
+        stream: &mut Stream,
+        mut stop: impl FnMut(&RecorderStats) -> bool,
+    ) -> io::Result<()> {
+        while !stop(&self.stats()) {
+            match stream.recv_timeout(TICK) {
+                Some(Ok(buffer)) => self.write(&buffer)?,
+                Some(Err(e)) if matches!(e.kind(), D3xxError::Timeout) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(pipe = %stream.pipe(), "read timed out, restarting the stream");
+                    stream.restart()?;
+                }
+                Some(Err(e)) => return Err(e.into()),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The progress so far.
+    pub fn stats(&self) -> RecorderStats {
+        RecorderStats {
+            bytes: self.shared.bytes.load(Ordering::Relaxed),
+            stored_bytes: self.shared.stored_bytes.load(Ordering::Relaxed),
+            segments: self.shared.segments.load(Ordering::Relaxed),
+            queued: self.shared.queued.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Writes the queued data, closes the last segment, marks the manifest complete
+    /// and returns the segments written.
+    ///
+    /// # Errors
+    /// As for [`Recorder::write`].
+    pub fn finish(mut self) -> io::Result<Vec<Segment>> {
+        self.shared.finishing.store(true, Ordering::Release);
+        self.join()
+    }
+
+    /// Stops the background thread and returns its result.
+    fn join(&mut self) -> io::Result<Vec<Segment>> {
+        self.sender = None;
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| io::Error::other("the recorder thread panicked"))?,
+            None => Err(stopped()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("directory", &self.directory)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// The error returned once the background thread has stopped and its own error
+/// has already been returned.
+fn stopped() -> io::Error {
+    io::Error::other("the recorder has stopped")
+}
+
+/// The background thread.
+struct SegmentWriter {
+    config: RecorderConfig,
+    shared: Arc<Shared>,
+    segments: Vec<Segment>,
+    current: Option<OpenSegment>,
+    last_sync: Instant,
+    last_space_check: Instant,
+}
+
+struct OpenSegment {
+    file_name: String,
+    encoder: Encoder,
+    start: SystemTime,
+    opened: Instant,
+    bytes: u64,
+}
+
+impl SegmentWriter {
+    fn run(mut self, receiver: Receiver<Chunk>) -> io::Result<Vec<Segment>> {
+        let received = self.receive(&receiver);
+        // Stop accepting data before the last segment is closed.
+        drop(receiver);
+        let closed = self.close_segment();
+        received?;
+        closed?;
+        if self.shared.finishing.load(Ordering::Acquire) {
+            self.write_manifest(true)?;
+        }
+        Ok(self.segments)
+    }
+
+    fn receive(&mut self, receiver: &Receiver<Chunk>) -> io::Result<()> {
+        loop {
+            match receiver.recv_timeout(TICK) {
+                Ok(chunk) => {
+                    self.shared.queued.fetch_sub(1, Ordering::Relaxed);
+                    self.write_chunk((*chunk).as_ref())?;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            self.tick()?;
+        }
+    }
+
+    fn write_chunk(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.current.is_none() {
+            self.check_space()?;
+            self.open_segment()?;
+        }
+        let segment = self.current.as_mut().unwrap();
+        let stored_before = segment.encoder.sink().bytes;
+        segment.encoder.write_all(data)?;
+        segment.bytes += data.len() as u64;
+        let stored = segment.encoder.sink().bytes - stored_before;
+        self.shared
+            .bytes
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.shared
+            .stored_bytes
+            .fetch_add(stored, Ordering::Relaxed);
+
+        if self
+            .config
+            .segment_bytes
+            .is_some_and(|limit| segment.bytes >= limit)
+        {
+            self.close_segment()?;
+        }
+        Ok(())
+    }
+
+    /// Rotates an old segment, syncs on the interval and checks free space.
+    fn tick(&mut self) -> io::Result<()> {
+        let Some(segment) = &mut self.current else {
+            return Ok(());
+        };
+        if self
+            .config
+            .segment_duration
+            .is_some_and(|duration| segment.opened.elapsed() >= duration)
+        {
+            return self.close_segment();
+        }
+        if let FsyncPolicy::Interval(interval) = self.config.fsync {
+            if self.last_sync.elapsed() >= interval {
+                segment.encoder.flush()?;
+                segment.encoder.sink().file.get_ref().sync_data()?;
+                self.last_sync = Instant::now();
+            }
+        }
+        if self.last_space_check.elapsed() >= SPACE_CHECK_INTERVAL {
+            if let Err(e) = self.check_space() {
+                self.close_segment()?;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_space(&mut self) -> io::Result<()> {
+        self.last_space_check = Instant::now();
+        match available_space(&self.config.directory)? {
+            Some(available) if available < self.config.min_free_bytes => Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "only {available} bytes are available in {}, below the limit of {} bytes",
+                    self.config.directory.display(),
+                    self.config.min_free_bytes,
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn open_segment(&mut self) -> io::Result<()> {
+        let file_name = format!(
+            "{}-{:06}.{}",
+            self.config.prefix,
+            self.segments.len(),
+            self.config.compression.extension(),
+        );
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.config.directory.join(&file_name))?;
+        let sink = Sink {
+            file: BufWriter::with_capacity(FILE_BUFFER_SIZE, file),
+            hasher: Sha256::new(),
+            bytes: 0,
+        };
+        let encoder = match self.config.compression {
+            Compression::None => Encoder::Plain(sink),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => Encoder::Zstd(zstd::Encoder::new(sink, level)?),
+            Compression::Lz4 => Encoder::Lz4(Lz4Encoder::new(sink)?),
+        };
+        self.current = Some(OpenSegment {
+            file_name,
+            encoder,
+            start: SystemTime::now(),
+            opened: Instant::now(),
+            bytes: 0,
+        });
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Finishes the open segment, if any, and lists it in the manifest.
+    fn close_segment(&mut self) -> io::Result<()> {
+        let Some(segment) = self.current.take() else {
+            return Ok(());
+        };
+        let stored_before = segment.encoder.sink_ref().bytes;
+        let sink = segment.encoder.finish()?;
+        self.shared
+            .stored_bytes
+            .fetch_add(sink.bytes - stored_before, Ordering::Relaxed);
+        let file = sink.file.into_inner().map_err(|e| e.into_error())?;
+        if self.config.fsync != FsyncPolicy::Never {
+            file.sync_all()?;
+        }
+
+        self.segments.push(Segment {
+            file_name: segment.file_name,
+            start: segment.start,
+            duration: segment.opened.elapsed(),
+            bytes: segment.bytes,
+            stored_bytes: sink.bytes,
+            sha256: sink.hasher.finalize().into(),
+        });
+        self.shared.segments.fetch_add(1, Ordering::Relaxed);
+        self.write_manifest(false)
+    }
+
+    /// Replaces the manifest through a temporary file, so it is never seen half
+    /// written.
+    fn write_manifest(&self, complete: bool) -> io::Result<()> {
+        let mut text = String::from("# ft60x recording manifest v1\n");
+        text.push_str("# file\tstart_ns\tduration_ns\tbytes\tstored_bytes\tsha256\n");
+        for segment in &self.segments {
+            let start = segment.start.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let _ = writeln!(
+                text,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                segment.file_name,
+                start.as_nanos(),
+                segment.duration.as_nanos(),
+                segment.bytes,
+                segment.stored_bytes,
+                segment.sha256_hex(),
+            );
+        }
+        if complete {
+            text.push_str("# complete\n");
+        }
+
+        let path = self.config.manifest_path();
+        let temporary = path.with_extension("manifest.tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(text.as_bytes())?;
+        if self.config.fsync != FsyncPolicy::Never {
+            file.sync_all()?;
+        }
+        fs::rename(&temporary, &path)?;
+        #[cfg(unix)]
+        if self.config.fsync != FsyncPolicy::Never {
+            // Make the rename itself durable.
+            File::open(&self.config.directory)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+/// The end of the writer chain: buffers writes to the file, hashes them and counts
+/// them.
+struct Sink {
+    file: BufWriter<File>,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+enum Encoder {
+    Plain(Sink),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, Sink>),
+    Lz4(Lz4Encoder<Sink>),
+}
+
+impl Encoder {
+    fn sink(&mut self) -> &mut Sink {
+        match self {
+            Self::Plain(sink) => sink,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.get_mut(),
+            Self::Lz4(encoder) => encoder.get_mut(),
+        }
+    }
+
+    fn sink_ref(&self) -> &Sink {
+        match self {
+            Self::Plain(sink) => sink,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.get_ref(),
+            Self::Lz4(encoder) => encoder.get_ref(),
+        }
+    }
+
+    /// Completes the compressed stream and flushes the file buffer.
+    fn finish(self) -> io::Result<Sink> {
+        let mut sink = match self {
+            Self::Plain(sink) => sink,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.finish()?,
+            Self::Lz4(encoder) => encoder.finish()?,
+        };
+        sink.flush()?;
+        Ok(sink)
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(sink) => sink.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.write(buf),
+            Self::Lz4(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(sink) => sink.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::Lz4(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Bytes available to unprivileged users on the file system holding `path`, or
+/// `None` where this is not supported.
+#[cfg(unix)]
+fn available_space(path: &Path) -> io::Result<Option<u64>> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::useless_conversion)]
+    Ok(Some(
+        u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)),
+    ))
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(directory: &Path) -> RecorderConfig {
+        let mut config = RecorderConfig::new(directory);
+        config.min_free_bytes = 0;
+        config.fsync = FsyncPolicy::Never;
+        config
+    }
+
+    fn manifest(config: &RecorderConfig) -> String {
+        fs::read_to_string(config.manifest_path()).unwrap()
+    }
+
+    /// The segment lines of a manifest.
+    fn manifest_entries(manifest: &str) -> Vec<Vec<String>> {
+        manifest
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split('\t').map(str::to_owned).collect())
+            .collect()
+    }
+
+    /// Checks the segment files against `segments` and returns their decompressed
+    /// contents, concatenated.
+    fn read_segments(config: &RecorderConfig, segments: &[Segment]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for segment in segments {
+            let file = fs::read(config.directory.join(&segment.file_name)).unwrap();
+            assert_eq!(file.len() as u64, segment.stored_bytes);
+            assert_eq!(<[u8; 32]>::from(Sha256::digest(&file)), segment.sha256);
+            let contents = match config.compression {
+                Compression::None => file,
+                #[cfg(feature = "zstd")]
+                Compression::Zstd(_) => zstd::decode_all(&file[..]).unwrap(),
+                Compression::Lz4 => crate::lz4::tests::decompress(&file),
+            };
+            assert_eq!(contents.len() as u64, segment.bytes);
+            data.extend(contents);
+        }
+        data
+    }
+
+    /// Waits for the background thread to close `segments` segments.
+    fn wait_for_segments(recorder: &Recorder, segments: u64) {
+        let start = Instant::now();
+        while recorder.stats().segments < segments {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "no segment closed"
+            );
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn size_rotation() {
+        for compression in [Compression::None, Compression::Lz4] {
+            let directory = tempfile::tempdir().unwrap();
+            let mut config = config(directory.path());
+            config.segment_bytes = Some(1000);
+            config.compression = compression;
+
+            let mut recorder = Recorder::new(config.clone()).unwrap();
+            let mut data = Vec::new();
+            for i in 0..7u8 {
+                let buffer = vec![i; 400];
+                recorder.write(&buffer).unwrap();
+                data.extend(buffer);
+            }
+            let segments = recorder.finish().unwrap();
+
+            // Buffers are not split, so each segment overshoots the limit.
+            let bytes: Vec<u64> = segments.iter().map(|segment| segment.bytes).collect();
+            assert_eq!(bytes, [1200, 1200, 400]);
+            let extension = compression.extension();
+            assert_eq!(segments[2].file_name, format!("ft60x-000002.{extension}"));
+            assert_eq!(read_segments(&config, &segments), data);
+        }
+    }
+
+    #[test]
+    fn duration_rotation() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut config = config(directory.path());
+        config.segment_bytes = None;
+        config.segment_duration = Some(Duration::from_millis(50));
+
+        let mut recorder = Recorder::new(config.clone()).unwrap();
+        recorder.write(b"first").unwrap();
+        recorder.write(b"segment").unwrap();
+        // The age is checked at least every `TICK`, without any data arriving.
+        wait_for_segments(&recorder, 1);
+        recorder.write(b"second").unwrap();
+        let segments = recorder.finish().unwrap();
+
+        assert_eq!(segments.len(), 2);
+        assert!(segments[0].duration >= Duration::from_millis(50));
+        assert!(segments[0].start <= segments[1].start);
+        assert_eq!(read_segments(&config, &segments), b"firstsegmentsecond");
+    }
+
+    #[test]
+    fn manifest_is_rewritten() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut config = config(directory.path());
+        config.segment_bytes = Some(100);
+
+        let mut recorder = Recorder::new(config.clone()).unwrap();
+        // Nothing is created until data arrives.
+        assert!(!config.manifest_path().exists());
+        recorder.write(&[1; 100]).unwrap();
+        wait_for_segments(&recorder, 1);
+        let partial = manifest(&config);
+        assert_eq!(manifest_entries(&partial).len(), 1);
+        assert!(!partial.contains("# complete"));
+
+        recorder.write(&[2; 100]).unwrap();
+        recorder.write(&[3; 10]).unwrap();
+        let segments = recorder.finish().unwrap();
+        let complete = manifest(&config);
+        assert!(complete.starts_with("# ft60x recording manifest v1\n"));
+        assert!(complete.ends_with("# complete\n"));
+        assert!(!config
+            .manifest_path()
+            .with_extension("manifest.tmp")
+            .exists());
+
+        let entries = manifest_entries(&complete);
+        assert_eq!(entries.len(), 3);
+        for (entry, segment) in entries.iter().zip(&segments) {
+            let start = segment.start.duration_since(UNIX_EPOCH).unwrap();
+            assert_eq!(
+                *entry,
+                [
+                    segment.file_name.clone(),
+                    start.as_nanos().to_string(),
+                    segment.duration.as_nanos().to_string(),
+                    segment.bytes.to_string(),
+                    segment.stored_bytes.to_string(),
+                    segment.sha256_hex(),
+                ]
+            );
+        }
+
+        // The manifest is not overwritten by a second recording.
+        let err = Recorder::new(config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn drop_leaves_manifest_incomplete() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = config(directory.path());
+        let mut recorder = Recorder::new(config.clone()).unwrap();
+        recorder.write(b"data").unwrap();
+        drop(recorder);
+
+        let manifest = manifest(&config);
+        assert_eq!(manifest_entries(&manifest).len(), 1);
+        assert!(!manifest.contains("# complete"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn storage_full() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut config = config(directory.path());
+        config.min_free_bytes = u64::MAX;
+
+        let mut recorder = Recorder::new(config.clone()).unwrap();
+        // The guard trips on the background thread, so the error surfaces on a later
+        // call.
+        let result = (0..100)
+            .try_for_each(|_| {
+                std::thread::sleep(Duration::from_millis(10));
+                recorder.write(b"data")
+            })
+            .and_then(|()| recorder.finish().map(drop));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::StorageFull);
+        // No segment was opened and no manifest written.
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn invalid_config() {
+        let directory = tempfile::tempdir().unwrap();
+        for change in [
+            (|config: &mut RecorderConfig| config.prefix.clear()) as fn(&mut RecorderConfig),
+            |config| config.prefix = "a/b".to_owned(),
+            |config| config.segment_bytes = Some(0),
+            |config| config.queue_len = 0,
+        ] {
+            let mut config = config(directory.path());
+            change(&mut config);
+            let err = Recorder::new(config).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,691 @@
+//! Offline playback of [captures](crate::capture).
+//!
+//! A [`ReplayDevice`] serves the IN-pipe data recorded in a capture through the same
+//! methods as a [`Device`](crate::Device), so acquisition and analysis code can be
+//! regression-tested without hardware. Code which should run on either is written
+//! against the [`DeviceIo`] trait:
+//!
+//! ```no_run
+//! # fn main() -> ft60x_rs::Result<()> {
+//! use ft60x_rs::{D3xxError, DeviceIo, Pipe, ReplayDevice, Timing};
+//!
+//! fn acquire(device: &impl DeviceIo) -> ft60x_rs::Result<u64> {
+//!     let mut buf = vec![0; 64 * 1024];
+//!     let mut total = 0;
+//!     loop {
+//!         match device.read(Pipe::In0, &mut buf) {
+//!             Ok(n) => total += n as u64,
+//!             Err(e) if matches!(e.kind(), D3xxError::HandleEof) => return Ok(total),
+//!             Err(e) => return Err(e),
+//!         }
+//!     }
+//! }
+//!
+//! let mut device = ReplayDevice::open("run.ftcap")?;
+//! device.set_timing(Timing::Immediate)?;
+//! println!("{} bytes", acquire(&device)?);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each read returns the next record captured on that pipe, including its status:
+//! a transfer which timed out in the capture times out on replay. A record larger
+//! than the buffer is returned over several reads. Once a pipe's records are used up,
+//! reads fail with [`D3xxError::HandleEof`].
+//!
+//! Writes are handled according to the [`WriteMode`].
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    capture::{CaptureHeader, CaptureReader, Record},
+    ChipType, D3xxError, DeviceDescriptor, DeviceInfo, DeviceIo, Error, Pipe, Result, Version,
+};
+
+/// The timeout reported for a pipe until one is set, matching the D3XX default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How reads are paced during replay.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Timing {
+    /// Each record is returned no earlier than it was captured, relative to the first
+    /// read.
+    #[default]
+    Original,
+    /// As [`Timing::Original`], with time running faster by the given factor, e.g.
+    /// `2.0` replays twice as fast. The factor must be finite and positive.
+    Scaled(f64),
+    /// Records are returned as fast as they are read.
+    Immediate,
+}
+
+/// What a [`ReplayDevice`] does with writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteMode {
+    /// Each write must match the next write captured on the pipe, and returns the
+    /// captured result. Otherwise it fails with [`D3xxError::ReplayMismatch`].
+    #[default]
+    Check,
+    /// Writes succeed and are kept; see [`ReplayDevice::take_writes`].
+    Record,
+    /// Writes succeed and are discarded.
+    Ignore,
+}
+
+/// Mutable replay state. The device is used from one thread, like a
+/// [`Device`](crate::Device), so a `RefCell` suffices.
+struct State {
+    /// Indices of the records not yet replayed, for each pipe.
+    queues: [VecDeque<usize>; 8],
+    /// A record partly returned by a read, and how much of it has been returned.
+    partial: [Option<(Record, usize)>; 8],
+    timeouts: [Duration; 8],
+    /// When the first read was made.
+    started: Option<Instant>,
+    writes: Vec<Record>,
+}
+
+/// Plays back a capture through the [`Device`](crate::Device) API.
+///
+/// See the [module documentation](self). A capture can also be built in memory, e.g.
+/// to feed synthetic data to code under test:
+///
+/// ```
+/// # fn main() -> ft60x_rs::Result<()> {
+/// use std::{io::Cursor, time::{Duration, SystemTime}};
+/// use ft60x_rs::{
+///     capture::Record, CaptureHeader, CaptureReader, CaptureWriter, D3xxError, Pipe,
+///     ReplayDevice, Timing,
+/// };
+///
+/// # let header = CaptureHeader {
+/// #     created: SystemTime::now(),
+/// #     device_info: Default::default(),
+/// #     descriptor: Default::default(),
+/// #     library_version: Default::default(),
+/// #     driver_version: Default::default(),
+/// # };
+/// let mut writer = CaptureWriter::new(Vec::new(), &header)?;
+/// for (pipe, data) in [(Pipe::Out0, vec![1, 2]), (Pipe::In0, vec![3, 4, 5, 6])] {
+///     writer.append_record(&Record {
+///         pipe,
+///         status: 0,
+///         timestamp: Duration::ZERO,
+///         wall_time: SystemTime::now(),
+///         requested: data.len(),
+///         data,
+///     })?;
+/// }
+/// let capture = writer.finish()?;
+///
+/// let mut device = ReplayDevice::new(CaptureReader::new(Cursor::new(capture))?)?;
+/// device.set_timing(Timing::Immediate)?;
+/// assert_eq!(device.write(Pipe::Out0, &[1, 2])?, 2);
+/// let mut buf = [0; 2];
+/// assert_eq!(device.read(Pipe::In0, &mut buf)?, 2);
+/// assert_eq!(device.read(Pipe::In0, &mut buf)?, 2);
+/// assert_eq!(buf, [5, 6]);
+/// let e = device.read(Pipe::In0, &mut buf).unwrap_err();
+/// assert!(matches!(e.kind(), D3xxError::HandleEof));
+/// # Ok(())
+/// # }
+/// ```
+pub struct ReplayDevice<R = BufReader<File>> {
+    reader: RefCell<CaptureReader<R>>,
+    header: CaptureHeader,
+    /// Timestamp of the first record, which replay time is measured from.
+    first_timestamp: Duration,
+    timing: Timing,
+    write_mode: WriteMode,
+    state: RefCell<State>,
+}
+
+impl ReplayDevice {
+    /// Opens the capture file at `path` for replay.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(CaptureReader::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> ReplayDevice<R> {
+    /// Replays the capture read by `reader`, with the original timing and with
+    /// writes checked against the capture.
+    pub fn new(mut reader: CaptureReader<R>) -> Result<Self> {
+        let mut queues: [VecDeque<usize>; 8] = Default::default();
+        for index in 0..reader.len() {
+            queues[reader.pipe(index)?.index()].push_back(index);
+        }
+        let first_timestamp = match reader.is_empty() {
+            true => Duration::ZERO,
+            false => reader.timestamp(0)?,
+        };
+        Ok(Self {
+            header: reader.header().clone(),
+            reader: RefCell::new(reader),
+            first_timestamp,
+            timing: Timing::default(),
+            write_mode: WriteMode::default(),
+            state: RefCell::new(State {
+                queues,
+                partial: Default::default(),
+                timeouts: [DEFAULT_TIMEOUT; 8],
+                started: None,
+                writes: Vec::new(),
+            }),
+        })
+    }
+
+    /// The header of the capture being replayed.
+    pub fn header(&self) -> &CaptureHeader {
+        &self.header
+    }
+
+    /// Sets how reads are paced.
+    ///
+    /// # Errors
+    /// [`D3xxError::InvalidParameter`] if the factor of [`Timing::Scaled`] is not
+    /// finite and positive.
+    pub fn set_timing(&mut self, timing: Timing) -> Result<()> {
+        if let Timing::Scaled(speed) = timing {
+            if !(speed.is_finite() && speed > 0.0) {
+                Err(D3xxError::InvalidParameter)?;
+            }
+        }
+        self.timing = timing;
+        Ok(())
+    }
+
+    /// Sets what is done with writes.
+    pub fn set_write_mode(&mut self, mode: WriteMode) {
+        self.write_mode = mode;
+    }
+
+    /// The number of records on `pipe` which have not been replayed yet.
+    pub fn remaining(&self, pipe: Pipe) -> usize {
+        let state = self.state.borrow();
+        state.queues[pipe.index()].len() + state.partial[pipe.index()].is_some() as usize
+    }
+
+    /// Takes the writes kept in [`WriteMode::Record`], in the order they were made.
+    /// They can be saved with [`CaptureWriter::append_record`](crate::CaptureWriter::append_record).
+    pub fn take_writes(&self) -> Vec<Record> {
+        std::mem::take(&mut self.state.borrow_mut().writes)
+    }
+
+    /// The device list entry stored in the capture.
+    pub fn info(&self) -> Result<DeviceInfo> {
+        Ok(self.header.device_info.clone())
+    }
+
+    /// The device descriptor stored in the capture.
+    pub fn device_descriptor(&self) -> Result<DeviceDescriptor> {
+        Ok(self.header.descriptor.clone())
+    }
+
+    /// The driver version stored in the capture.
+    pub fn driver_version(&self) -> Result<Version> {
+        Ok(self.header.driver_version.clone())
+    }
+
+    /// The chip type of the captured device.
+    ///
+    /// Returns a `NotSupported` error if the chip type is not known.
+    pub fn chip_type(&self) -> Result<ChipType> {
+        Ok(self
+            .header
+            .device_info
+            .chip_type()
+            .ok_or(D3xxError::NotSupported)?)
+    }
+
+    /// Returns the next captured data from the pipe, waiting until it is due.
+    ///
+    /// See the [module documentation](self).
+    pub fn read(&self, pipe: Pipe, buf: &mut [u8]) -> Result<usize> {
+        self.check(pipe, buf.len(), true)?;
+        let mut state = self.state.borrow_mut();
+        let (record, offset) = match state.partial[pipe.index()].take() {
+            Some(partial) => partial,
+            None => {
+                let index = state.queues[pipe.index()]
+                    .pop_front()
+                    .ok_or_else(|| Error::new(D3xxError::HandleEof).with_pipe(pipe))?;
+                let record = self.reader.borrow_mut().record(index)?;
+                self.wait_until_due(&mut state, record.timestamp);
+                (record, 0)
+            }
+        };
+
+        let n = (record.data.len() - offset).min(buf.len());
+        buf[..n].copy_from_slice(&record.data[offset..offset + n]);
+        match record.error() {
+            Some(kind) => Err(Error::new(kind)
+                .with_pipe(pipe)
+                .with_bytes_transferred(offset + n)),
+            None => {
+                if offset + n < record.data.len() {
+                    state.partial[pipe.index()] = Some((record, offset + n));
+                }
+                Ok(n)
+            }
+        }
+    }
+
+    /// As [`ReplayDevice::read`]. The timeout is ignored: a transfer which timed out
+    /// during the capture was recorded as such and times out on replay.
+    pub fn read_timeout(&self, pipe: Pipe, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        self.read(pipe, buf)
+    }
+
+    /// Handles a write according to the [`WriteMode`].
+    pub fn write(&self, pipe: Pipe, buf: &[u8]) -> Result<usize> {
+        self.check(pipe, buf.len(), false)?;
+        let mut state = self.state.borrow_mut();
+        match self.write_mode {
+            WriteMode::Ignore => Ok(buf.len()),
+            WriteMode::Record => {
+                let started = *state.started.get_or_insert_with(Instant::now);
+                state.writes.push(Record {
+                    pipe,
+                    status: 0,
+                    timestamp: self.first_timestamp + started.elapsed(),
+                    wall_time: SystemTime::now(),
+                    requested: buf.len(),
+                    data: buf.to_vec(),
+                });
+                Ok(buf.len())
+            }
+            WriteMode::Check => {
+                let mismatch = || Error::new(D3xxError::ReplayMismatch).with_pipe(pipe);
+                let index = state.queues[pipe.index()]
+                    .pop_front()
+                    .ok_or_else(mismatch)?;
+                let record = self.reader.borrow_mut().record(index)?;
+                if record.requested != buf.len() || !buf.starts_with(&record.data) {
+                    return Err(mismatch());
+                }
+                match record.error() {
+                    Some(kind) => Err(Error::new(kind)
+                        .with_pipe(pipe)
+                        .with_bytes_transferred(record.data.len())),
+                    None => Ok(record.data.len()),
+                }
+            }
+        }
+    }
+
+    /// As [`ReplayDevice::write`]; the timeout is ignored.
+    pub fn write_timeout(&self, pipe: Pipe, buf: &[u8], _timeout: Duration) -> Result<usize> {
+        self.write(pipe, buf)
+    }
+
+    /// Discards the rest of a record partly returned by a read.
+    pub fn flush(&self, pipe: Pipe) -> Result<()> {
+        self.state.borrow_mut().partial[pipe.index()] = None;
+        Ok(())
+    }
+
+    /// Does nothing, as replayed transfers are never pending.
+    pub fn abort_transfers(&self, _pipe: Pipe) -> Result<()> {
+        Ok(())
+    }
+
+    /// Stores the timeout, to be returned by [`ReplayDevice::get_timeout`]. It has no
+    /// effect on replay.
+    pub fn set_timeout(&self, pipe: Pipe, timeout: Duration) -> Result<()> {
+        self.state.borrow_mut().timeouts[pipe.index()] = timeout;
+        Ok(())
+    }
+
+    /// Get the timeout set for the pipe.
+    pub fn get_timeout(&self, pipe: Pipe) -> Result<Duration> {
+        Ok(self.state.borrow().timeouts[pipe.index()])
+    }
+
+    /// Does nothing, as transfers are replayed as they were captured.
+    pub fn set_stream_size(&self, _pipe: Pipe, _stream_size: Option<u32>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Checks the direction of the pipe and the transfer length, as
+    /// [`Device`](crate::Device) does.
+    fn check(&self, pipe: Pipe, len: usize, read: bool) -> Result<()> {
+        if pipe.is_read_pipe() != read {
+            Err(Error::new(D3xxError::InvalidParameter).with_pipe(pipe))?;
+        }
+        let granularity = self.chip_type().map_or(1, |chip| chip.bus_width());
+        if !len.is_multiple_of(granularity) {
+            Err(Error::new(D3xxError::MisalignedLength).with_pipe(pipe))?;
+        }
+        Ok(())
+    }
+
+    /// Sleeps until a record captured at `timestamp` is due.
+    fn wait_until_due(&self, state: &mut State, timestamp: Duration) {
+        let speed = match self.timing {
+            Timing::Immediate => return,
+            Timing::Original => 1.0,
+            Timing::Scaled(speed) => speed,
+        };
+        let started = *state.started.get_or_insert_with(Instant::now);
+        let offset = timestamp.saturating_sub(self.first_timestamp);
+        if let Some(due) = Duration::try_from_secs_f64(offset.as_secs_f64() / speed)
+            .ok()
+            .and_then(|offset| started.checked_add(offset))
+        {
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> DeviceIo for ReplayDevice<R> {
+    fn info(&self) -> Result<DeviceInfo> {
+        ReplayDevice::info(self)
+    }
+
+    fn device_descriptor(&self) -> Result<DeviceDescriptor> {
+        ReplayDevice::device_descriptor(self)
+    }
+
+    fn driver_version(&self) -> Result<Version> {
+        ReplayDevice::driver_version(self)
+    }
+
+    fn chip_type(&self) -> Result<ChipType> {
+        ReplayDevice::chip_type(self)
+    }
+
+    fn read(&self, pipe: Pipe, buf: &mut [u8]) -> Result<usize> {
+        ReplayDevice::read(self, pipe, buf)
+    }
+
+    fn read_timeout(&self, pipe: Pipe, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        ReplayDevice::read_timeout(self, pipe, buf, timeout)
+    }
+
+    fn write(&self, pipe: Pipe, buf: &[u8]) -> Result<usize> {
+        ReplayDevice::write(self, pipe, buf)
+    }
+
+    fn write_timeout(&self, pipe: Pipe, buf: &[u8], timeout: Duration) -> Result<usize> {
+        ReplayDevice::write_timeout(self, pipe, buf, timeout)
+    }
+
+    fn flush(&self, pipe: Pipe) -> Result<()> {
+        ReplayDevice::flush(self, pipe)
+    }
+
+    fn abort_transfers(&self, pipe: Pipe) -> Result<()> {
+        ReplayDevice::abort_transfers(self, pipe)
+    }
+
+    fn set_timeout(&self, pipe: Pipe, timeout: Duration) -> Result<()> {
+        ReplayDevice::set_timeout(self, pipe, timeout)
+    }
+
+    fn get_timeout(&self, pipe: Pipe) -> Result<Duration> {
+        ReplayDevice::get_timeout(self, pipe)
+    }
+
+    fn set_stream_size(&self, pipe: Pipe, stream_size: Option<u32>) -> Result<()> {
+        ReplayDevice::set_stream_size(self, pipe, stream_size)
+    }
+}
+
+impl<R> std::fmt::Debug for ReplayDevice<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplayDevice")
+            .field("serial_number", &self.header.device_info.serial_number())
+            .field("timing", &self.timing)
+            .field("write_mode", &self.write_mode)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{capture::STATUS_CANCELLED, CaptureWriter};
+
+    /// Status of a transfer which timed out, `FT_TIMEOUT`.
+    const STATUS_TIMEOUT: u32 = 19;
+
+    type Replay = ReplayDevice<Cursor<Vec<u8>>>;
+
+    fn record(pipe: Pipe, millis: u64, status: u32, data: &[u8]) -> Record {
+        Record {
+            pipe,
+            status,
+            timestamp: Duration::from_millis(millis),
+            wall_time: SystemTime::UNIX_EPOCH,
+            requested: data.len(),
+            data: data.to_vec(),
+        }
+    }
+
+    /// A device replaying `records` immediately, from a capture of an FT601.
+    fn replay(records: &[Record]) -> Replay {
+        let header = CaptureHeader {
+            created: SystemTime::UNIX_EPOCH,
+            device_info: DeviceInfo {
+                type_: crate::constants::FT_DEVICE_601,
+                ..Default::default()
+            },
+            descriptor: Default::default(),
+            library_version: Default::default(),
+            driver_version: Default::default(),
+        };
+        let mut writer = CaptureWriter::new(Vec::new(), &header).unwrap();
+        for record in records {
+            writer.append_record(record).unwrap();
+        }
+        let reader = CaptureReader::new(Cursor::new(writer.finish().unwrap())).unwrap();
+        let mut device = ReplayDevice::new(reader).unwrap();
+        device.set_timing(Timing::Immediate).unwrap();
+        device
+    }
+
+    fn kind(result: Result<usize>) -> D3xxError {
+        result.unwrap_err().into_kind()
+    }
+
+    #[test]
+    fn reads_split_records_and_end_with_eof() {
+        let device = replay(&[
+            record(Pipe::In0, 0, 0, &[1, 2, 3, 4, 5, 6, 7, 8]),
+            record(Pipe::In1, 1, 0, &[9; 4]),
+            record(Pipe::In0, 2, 0, &[10; 4]),
+        ]);
+        assert_eq!(device.chip_type().unwrap(), ChipType::Ft601);
+        assert_eq!(device.remaining(Pipe::In0), 2);
+
+        let mut buf = [0; 4];
+        assert_eq!(device.read(Pipe::In0, &mut buf).unwrap(), 4);
+        assert_eq!(buf, [1, 2, 3, 4]);
+        // The rest of the record is still pending.
+        assert_eq!(device.remaining(Pipe::In0), 2);
+        let mut big = [0; 16];
+        assert_eq!(device.read(Pipe::In0, &mut big).unwrap(), 4);
+        assert_eq!(big[..4], [5, 6, 7, 8]);
+        assert_eq!(device.read(Pipe::In0, &mut big).unwrap(), 4);
+        assert_eq!(big[..4], [10; 4]);
+        assert_eq!(device.remaining(Pipe::In0), 0);
+
+        // Reads past the end of the capture keep failing with `HandleEof`.
+        for _ in 0..2 {
+            let err = device.read(Pipe::In0, &mut buf).unwrap_err();
+            assert!(matches!(err.kind(), D3xxError::HandleEof));
+            assert_eq!(err.pipe(), Some(Pipe::In0));
+        }
+        // Other pipes are independent.
+        assert_eq!(device.read(Pipe::In1, &mut buf).unwrap(), 4);
+        assert!(matches!(
+            kind(device.read(Pipe::In2, &mut buf)),
+            D3xxError::HandleEof
+        ));
+    }
+
+    #[test]
+    fn error_records() {
+        let device = replay(&[
+            record(Pipe::In0, 0, STATUS_TIMEOUT, &[1, 2, 3, 4]),
+            record(Pipe::In0, 1, STATUS_CANCELLED, &[]),
+            record(Pipe::In0, 2, 0, &[5; 4]),
+        ]);
+        // A failed transfer returns the bytes it moved, through the error.
+        let mut buf = [0; 8];
+        let err = device.read(Pipe::In0, &mut buf).unwrap_err();
+        assert!(matches!(err.kind(), D3xxError::Timeout));
+        assert_eq!(err.bytes_transferred(), Some(4));
+        assert_eq!(buf[..4], [1, 2, 3, 4]);
+
+        assert!(matches!(
+            kind(device.read(Pipe::In0, &mut buf)),
+            D3xxError::Cancelled
+        ));
+        assert_eq!(device.read(Pipe::In0, &mut buf).unwrap(), 4);
+    }
+
+    #[test]
+    fn check_writes() {
+        let device = replay(&[
+            record(Pipe::Out0, 0, 0, &[1, 2, 3, 4]),
+            record(Pipe::Out0, 1, STATUS_TIMEOUT, &[5, 6, 7, 8]),
+            record(Pipe::Out0, 2, 0, &[9; 4]),
+            record(Pipe::Out0, 3, 0, &[9; 4]),
+        ]);
+        assert_eq!(device.write(Pipe::Out0, &[1, 2, 3, 4]).unwrap(), 4);
+
+        let err = device.write(Pipe::Out0, &[5, 6, 7, 8]).unwrap_err();
+        assert!(matches!(err.kind(), D3xxError::Timeout));
+        assert_eq!(err.bytes_transferred(), Some(4));
+
+        // Different data or a different length does not match.
+        assert!(matches!(
+            kind(device.write(Pipe::Out0, &[9, 9, 9, 0])),
+            D3xxError::ReplayMismatch
+        ));
+        assert!(matches!(
+            kind(device.write(Pipe::Out0, &[9; 8])),
+            D3xxError::ReplayMismatch
+        ));
+        // Writes past the end of the capture cannot match either.
+        assert_eq!(device.remaining(Pipe::Out0), 0);
+        assert!(matches!(
+            kind(device.write(Pipe::Out0, &[9; 4])),
+            D3xxError::ReplayMismatch
+        ));
+    }
+
+    #[test]
+    fn record_writes() {
+        let mut device = replay(&[record(Pipe::Out1, 0, 0, &[1, 2, 3, 4])]);
+        device.set_write_mode(WriteMode::Record);
+        assert_eq!(device.write(Pipe::Out1, &[7; 8]).unwrap(), 8);
+        assert_eq!(device.write(Pipe::Out2, &[8; 4]).unwrap(), 4);
+
+        let writes = device.take_writes();
+        assert_eq!(writes.len(), 2);
+        assert_eq!(
+            (writes[0].pipe, &writes[0].data[..]),
+            (Pipe::Out1, &[7; 8][..])
+        );
+        assert_eq!(
+            (writes[1].pipe, &writes[1].data[..]),
+            (Pipe::Out2, &[8; 4][..])
+        );
+        assert!(writes.iter().all(|write| write.error().is_none()));
+        assert!(device.take_writes().is_empty());
+        // The captured writes are not consumed.
+        assert_eq!(device.remaining(Pipe::Out1), 1);
+    }
+
+    #[test]
+    fn ignore_writes() {
+        let mut device = replay(&[]);
+        device.set_write_mode(WriteMode::Ignore);
+        assert_eq!(device.write(Pipe::Out0, &[0; 12]).unwrap(), 12);
+        assert!(device.take_writes().is_empty());
+    }
+
+    #[test]
+    fn checks_direction_and_length() {
+        let device = replay(&[record(Pipe::In0, 0, 0, &[1; 8])]);
+        let mut buf = [0; 8];
+        assert!(matches!(
+            kind(device.read(Pipe::Out0, &mut buf)),
+            D3xxError::InvalidParameter
+        ));
+        assert!(matches!(
+            kind(device.write(Pipe::In0, &buf)),
+            D3xxError::InvalidParameter
+        ));
+        // An FT601 moves 32-bit words.
+        assert!(matches!(
+            kind(device.read(Pipe::In0, &mut buf[..6])),
+            D3xxError::MisalignedLength
+        ));
+        assert_eq!(device.remaining(Pipe::In0), 1);
+    }
+
+    #[test]
+    fn flush_discards_partial_record() {
+        let device = replay(&[
+            record(Pipe::In0, 0, 0, &[1; 8]),
+            record(Pipe::In0, 1, 0, &[2; 4]),
+        ]);
+        let mut buf = [0; 4];
+        device.read(Pipe::In0, &mut buf).unwrap();
+        device.flush(Pipe::In0).unwrap();
+        device.read(Pipe::In0, &mut buf).unwrap();
+        assert_eq!(buf, [2; 4]);
+    }
+
+    #[test]
+    fn timing() {
+        let records = [
+            record(Pipe::In0, 1000, 0, &[1; 4]),
+            record(Pipe::In0, 1200, 0, &[2; 4]),
+        ];
+        let mut buf = [0; 4];
+        let mut device = replay(&records);
+        device.set_timing(Timing::Original).unwrap();
+        let start = Instant::now();
+        device.read(Pipe::In0, &mut buf).unwrap();
+        device.read(Pipe::In0, &mut buf).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        let mut device = replay(&records);
+        device.set_timing(Timing::Scaled(4.0)).unwrap();
+        let start = Instant::now();
+        device.read(Pipe::In0, &mut buf).unwrap();
+        device.read(Pipe::In0, &mut buf).unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50));
+        assert!(elapsed < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn invalid_scale_is_rejected() {
+        let mut device = replay(&[]);
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let err = device.set_timing(Timing::Scaled(speed)).unwrap_err();
+            assert!(matches!(err.kind(), D3xxError::InvalidParameter));
+        }
+        // The previous timing is kept.
+        assert_eq!(device.timing, Timing::Immediate);
+        device.set_timing(Timing::Scaled(0.5)).unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+//! Reader and writer halves of a [`Device`](crate::Device).
+//!
+//! A [`Device`](crate::Device) can be used from only one thread at a time. Calling
+//! [`Device::split`](crate::Device::split) consumes the device and produces one
+//! [`PipeReader`] and one [`PipeWriter`] per channel. Each half owns its pipe
+//! exclusively and can be moved to a different thread.
+//!
+//! # Thread safety
+//!
+//! The D3XX library allows the following calls to be made concurrently on one handle:
+//!
+//! - transfers (`FT_ReadPipe`/`FT_ReadPipeEx`/`FT_WritePipeEx`) on *different* pipes, e.g. streaming
+//!   data from `In0` while register commands are written to `Out0`;
+//! - `FT_AbortPipe`, `FT_FlushPipe` and the pipe timeout functions on a pipe other
+//!   than the one being transferred on.
+//!
+//! Concurrent transfers on the *same* pipe are not supported. The halves prevent
+//! this by requiring `&mut self` for transfers and by never handing out two halves
+//! for the same pipe. The device handle is closed once every half has been dropped.
+
+use std::{io, sync::Arc, time::Duration};
+
+use crate::{
+    AsHandle, BorrowedHandle, CancelHandle, DeviceHandle, Pipe, PipeStats, RecoveryPolicy, Result,
+};
+
+/// The reading half of a channel, created by [`Device::split`](crate::Device::split).
+pub struct PipeReader {
+    device: Arc<DeviceHandle>,
+    pipe: Pipe,
+    recovery_policy: RecoveryPolicy,
+}
+
+impl PipeReader {
+    pub(crate) fn new(device: Arc<DeviceHandle>, pipe: Pipe, policy: RecoveryPolicy) -> Self {
+        Self {
+            device,
+            pipe,
+            recovery_policy: policy,
+        }
+    }
+
+    /// The IN pipe this half reads from.
+    pub fn pipe(&self) -> Pipe {
+        self.pipe
+    }
+
+    /// Reads data from the pipe. This method will block until the transfer is
+    /// complete, or the timeout is reached.
+    ///
+    /// See [`Device::read`](crate::Device::read).
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.device.read(self.pipe, buf, self.recovery_policy, None)
+    }
+
+    /// Reads data from the pipe, using the given timeout instead of the pipe timeout.
+    ///
+    /// See [`Device::read_timeout`](crate::Device::read_timeout).
+    pub fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.device
+            .read(self.pipe, buf, self.recovery_policy, Some(timeout))
+    }
+
+    /// Discards any data cached in the pipe.
+    pub fn flush(&self) -> Result<()> {
+        self.device.flush(self.pipe)
+    }
+
+    /// Aborts all pending transfers on the pipe.
+    pub fn abort_transfers(&self) -> Result<()> {
+        self.device.abort_transfers(self.pipe)
+    }
+
+    /// Configures the timeout for the pipe. See [`Device::set_timeout`](crate::Device::set_timeout).
+    pub fn set_timeout(&self, timeout: Duration) -> Result<()> {
+        self.device.set_timeout(self.pipe, timeout)
+    }
+
+    /// Get the timeout configured for the pipe.
+    pub fn get_timeout(&self) -> Result<Duration> {
+        self.device.timeout(self.pipe)
+    }
+
+    /// Sets the recovery policy used when a read fails.
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy) {
+        self.recovery_policy = policy;
+    }
+
+    /// Get the recovery policy used when a read fails.
+    pub fn recovery_policy(&self) -> RecoveryPolicy {
+        self.recovery_policy
+    }
+
+    /// Get a handle which can cancel transfers on this pipe from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle::new(&self.device)
+    }
+
+    /// Get the transfer statistics for the pipe, or `None` if statistics are disabled.
+    pub fn stats(&self) -> Option<PipeStats> {
+        self.device.stats().map(|stats| stats.snapshot(self.pipe))
+    }
+}
+
+impl io::Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(PipeReader::read(self, buf)?)
+    }
+}
+
+impl AsHandle for PipeReader {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        self.device.handle.as_handle()
+    }
+}
+
+impl std::fmt::Debug for PipeReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipeReader")
+            .field("handle", &self.device.handle)
+            .field("pipe", &self.pipe)
+            .finish()
+    }
+}
+
+/// The writing half of a channel, created by [`Device::split`](crate::Device::split).
+pub struct PipeWriter {
+    device: Arc<DeviceHandle>,
+    pipe: Pipe,
+    recovery_policy: RecoveryPolicy,
+}
+
+impl PipeWriter {
+    pub(crate) fn new(device: Arc<DeviceHandle>, pipe: Pipe, policy: RecoveryPolicy) -> Self {
+        Self {
+            device,
+            pipe,
+            recovery_policy: policy,
+        }
+    }
+
+    /// The OUT pipe this half writes to.
+    pub fn pipe(&self) -> Pipe {
+        self.pipe
+    }
+
+    /// Writes data to the pipe. This method will block until the transfer is
+    /// complete, or the timeout is reached.
+    ///
+    /// See [`Device::write`](crate::Device::write).
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.device
+            .write(self.pipe, buf, self.recovery_policy, None)
+    }
+
+    /// Writes data to the pipe, using the given timeout instead of the pipe timeout.
+    ///
+    /// See [`Device::write_timeout`](crate::Device::write_timeout).
+    pub fn write_timeout(&mut self, buf: &[u8], timeout: Duration) -> Result<usize> {
+        self.device
+            .write(self.pipe, buf, self.recovery_policy, Some(timeout))
+    }
+
+    /// Aborts all pending transfers on the pipe.
+    pub fn abort_transfers(&self) -> Result<()> {
+        self.device.abort_transfers(self.pipe)
+    }
+
+    /// Configures the timeout for the pipe. See [`Device::set_timeout`](crate::Device::set_timeout).
+    pub fn set_timeout(&self, timeout: Duration) -> Result<()> {
+        self.device.set_timeout(self.pipe, timeout)
+    }
+
+    /// Get the timeout configured for the pipe.
+    pub fn get_timeout(&self) -> Result<Duration> {
+        self.device.timeout(self.pipe)
+    }
+
+    /// Sets the recovery policy used when a write fails.
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy) {
+        self.recovery_policy = policy;
+    }
+
+    /// Get the recovery policy used when a write fails.
+    pub fn recovery_policy(&self) -> RecoveryPolicy {
+        self.recovery_policy
+    }
+
+    /// Get a handle which can cancel transfers on this pipe from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle::new(&self.device)
+    }
+
+    /// Get the transfer statistics for the pipe, or `None` if statistics are disabled.
+    pub fn stats(&self) -> Option<PipeStats> {
+        self.device.stats().map(|stats| stats.snapshot(self.pipe))
+    }
+}
+
+impl io::Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(PipeWriter::write(self, buf)?)
+    }
+
+    /// Transfers are not buffered on the host, so this is a no-op.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsHandle for PipeWriter {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        self.device.handle.as_handle()
+    }
+}
+
+impl std::fmt::Debug for PipeWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipeWriter")
+            .field("handle", &self.device.handle)
+            .field("pipe", &self.pipe)
+            .finish()
+    }
+}
@@ -0,0 +1,466 @@
+//! Per-pipe transfer statistics.
+//!
+//! Statistics are disabled by default. Once enabled with
+//! [`Device::set_stats_enabled`](crate::Device::set_stats_enabled) or
+//! [`OpenOptions::stats`](crate::OpenOptions::stats), every read and write records
+//! the number of bytes transferred, its outcome, the time it took and the idle time
+//! since the previous transfer on the same pipe. Transfers made by a
+//! [`Stream`](crate::Stream) or through split halves are included.
+//!
+//! ```no_run
+//! # fn main() -> ft60x_rs::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! use ft60x_rs::Pipe;
+//!
+//! device.set_stats_enabled(true);
+//! // ... run acquisition ...
+//! if let Some(stats) = device.stats(Pipe::In0) {
+//!     println!(
+//!         "{} bytes in {} reads, p99 latency {:?}, largest gap {:?}",
+//!         stats.bytes,
+//!         stats.transfers,
+//!         stats.latency.value_at_quantile(0.99),
+//!         stats.largest_gap,
+//!     );
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A long latency with short gaps points at the device or FPGA, which was not
+//! producing data; a long gap points at the host, which was not asking for it.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{D3xxError, Error, Pipe};
+
+/// Number of sub-buckets per power of two. Latencies are recorded with a relative
+/// error of at most 1/16.
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKET_BITS: u32 = 4;
+/// Enough buckets for any `u64` number of nanoseconds.
+const BUCKETS: usize = (SUB_BUCKETS + (64 - SUB_BUCKET_BITS as u64) * SUB_BUCKETS) as usize;
+
+/// The bucket holding `value`. Values below [`SUB_BUCKETS`] are recorded exactly;
+/// above that each power of two is divided into [`SUB_BUCKETS`] buckets.
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS {
+        return value as usize;
+    }
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> shift) & (SUB_BUCKETS - 1);
+    (SUB_BUCKETS + shift as u64 * SUB_BUCKETS + sub_bucket) as usize
+}
+
+/// The largest value recorded in the given bucket.
+fn bucket_upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift = (index - SUB_BUCKETS) / SUB_BUCKETS;
+    let sub_bucket = (index - SUB_BUCKETS) % SUB_BUCKETS;
+    let lower = (SUB_BUCKETS + sub_bucket) << shift;
+    lower + ((1 << shift) - 1)
+}
+
+/// A snapshot of the distribution of transfer latencies, in the style of an HDR
+/// histogram.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum_nanos: u64,
+    min_nanos: u64,
+    max_nanos: u64,
+}
+
+impl LatencyHistogram {
+    /// The number of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The shortest recorded latency, or zero if nothing has been recorded.
+    pub fn min(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            _ => Duration::from_nanos(self.min_nanos),
+        }
+    }
+
+    /// The longest recorded latency.
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max_nanos)
+    }
+
+    /// The total of the recorded latencies.
+    pub fn sum(&self) -> Duration {
+        Duration::from_nanos(self.sum_nanos)
+    }
+
+    /// The mean of the recorded latencies, or zero if nothing has been recorded.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            n => Duration::from_nanos(self.sum_nanos / n),
+        }
+    }
+
+    /// The latency below which the given fraction (0.0 to 1.0) of the recorded
+    /// latencies fall, e.g. `0.99` for the 99th percentile.
+    pub fn value_at_quantile(&self, quantile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let value = bucket_upper_bound(index).clamp(self.min_nanos, self.max_nanos);
+                return Duration::from_nanos(value);
+            }
+        }
+        self.max()
+    }
+
+    /// The non-empty buckets, as the largest latency in the bucket and the number of
+    /// latencies recorded in it.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(index, &n)| (Duration::from_nanos(bucket_upper_bound(index)), n))
+    }
+}
+
+impl std::fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count)
+            .field("min", &self.min())
+            .field("mean", &self.mean())
+            .field("p50", &self.value_at_quantile(0.5))
+            .field("p99", &self.value_at_quantile(0.99))
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+/// A snapshot of the statistics for one pipe, returned by
+/// [`Device::stats`](crate::Device::stats).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipeStats {
+    /// Bytes transferred, including those transferred by calls which failed.
+    pub bytes: u64,
+    /// Calls made, successful or not.
+    pub transfers: u64,
+    /// Calls which failed with a timeout.
+    pub timeouts: u64,
+    /// Calls which were aborted or cancelled.
+    pub aborts: u64,
+    /// Reads which returned fewer bytes than requested.
+    pub short_reads: u64,
+    /// The longest time between the end of one call and the start of the next.
+    pub largest_gap: Duration,
+    /// The time taken by each call.
+    pub latency: LatencyHistogram,
+}
+
+/// Counters for one pipe, updated without locking.
+pub(crate) struct PipeCounters {
+    bytes: AtomicU64,
+    transfers: AtomicU64,
+    timeouts: AtomicU64,
+    aborts: AtomicU64,
+    short_reads: AtomicU64,
+    largest_gap_nanos: AtomicU64,
+    /// End of the previous call in nanoseconds since [`Stats::epoch`], plus one so
+    /// that zero means no call has been made.
+    last_end: AtomicU64,
+    latency_counts: Box<[AtomicU64]>,
+    latency_sum_nanos: AtomicU64,
+    latency_min_nanos: AtomicU64,
+    latency_max_nanos: AtomicU64,
+}
+
+impl PipeCounters {
+    fn new() -> Self {
+        Self {
+            bytes: AtomicU64::new(0),
+            transfers: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            aborts: AtomicU64::new(0),
+            short_reads: AtomicU64::new(0),
+            largest_gap_nanos: AtomicU64::new(0),
+            last_end: AtomicU64::new(0),
+            latency_counts: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            latency_sum_nanos: AtomicU64::new(0),
+            latency_min_nanos: AtomicU64::new(u64::MAX),
+            latency_max_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn reset(&self) {
+        for counter in [
+            &self.bytes,
+            &self.transfers,
+            &self.timeouts,
+            &self.aborts,
+            &self.short_reads,
+            &self.largest_gap_nanos,
+            &self.last_end,
+            &self.latency_sum_nanos,
+            &self.latency_max_nanos,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.latency_min_nanos.store(u64::MAX, Ordering::Relaxed);
+        for count in self.latency_counts.iter() {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> PipeStats {
+        let counts: Vec<u64> = self
+            .latency_counts
+            .iter()
+            .map(|n| n.load(Ordering::Relaxed))
+            .collect();
+        PipeStats {
+            bytes: self.bytes.load(Ordering::Relaxed),
+            transfers: self.transfers.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            aborts: self.aborts.load(Ordering::Relaxed),
+            short_reads: self.short_reads.load(Ordering::Relaxed),
+            largest_gap: Duration::from_nanos(self.largest_gap_nanos.load(Ordering::Relaxed)),
+            latency: LatencyHistogram {
+                count: counts.iter().sum(),
+                counts,
+                sum_nanos: self.latency_sum_nanos.load(Ordering::Relaxed),
+                min_nanos: self.latency_min_nanos.load(Ordering::Relaxed),
+                max_nanos: self.latency_max_nanos.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+/// Statistics for every pipe of a device.
+pub(crate) struct Stats {
+    epoch: Instant,
+    pipes: [PipeCounters; 8],
+}
+
+impl Stats {
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            pipes: std::array::from_fn(|_| PipeCounters::new()),
+        }
+    }
+
+    pub(crate) fn snapshot(&self, pipe: Pipe) -> PipeStats {
+        self.pipes[pipe.index()].snapshot()
+    }
+
+    pub(crate) fn reset(&self) {
+        for pipe in &self.pipes {
+            pipe.reset();
+        }
+    }
+
+    /// Records a call which started at `start`, requested `len` bytes and either
+    /// transferred the given number of bytes or failed.
+    pub(crate) fn record(
+        &self,
+        pipe: Pipe,
+        start: Instant,
+        len: usize,
+        result: std::result::Result<usize, &Error>,
+    ) {
+        let end = Instant::now();
+        let counters = &self.pipes[pipe.index()];
+
+        let last_end = counters
+            .last_end
+            .swap(self.nanos_since_epoch(end) + 1, Ordering::Relaxed);
+        if last_end != 0 {
+            let gap = self.nanos_since_epoch(start).saturating_sub(last_end - 1);
+            counters.largest_gap_nanos.fetch_max(gap, Ordering::Relaxed);
+        }
+
+        let bytes = match result {
+            Ok(n) => {
+                if pipe.is_read_pipe() && n < len {
+                    counters.short_reads.fetch_add(1, Ordering::Relaxed);
+                }
+                n
+            }
+            Err(e) => {
+                match e.kind() {
+                    D3xxError::Timeout => counters.timeouts.fetch_add(1, Ordering::Relaxed),
+                    D3xxError::OperationAborted | D3xxError::Cancelled => {
+                        counters.aborts.fetch_add(1, Ordering::Relaxed)
+                    }
+                    _ => 0,
+                };
+                e.bytes_transferred().unwrap_or(0)
+            }
+        };
+        counters.transfers.fetch_add(1, Ordering::Relaxed);
+        counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+
+        let latency = end
+            .duration_since(start)
+            .as_nanos()
+            .try_into()
+            .unwrap_or(u64::MAX);
+        counters.latency_counts[bucket_index(latency)].fetch_add(1, Ordering::Relaxed);
+        counters
+            .latency_sum_nanos
+            .fetch_add(latency, Ordering::Relaxed);
+        counters
+            .latency_min_nanos
+            .fetch_min(latency, Ordering::Relaxed);
+        counters
+            .latency_max_nanos
+            .fetch_max(latency, Ordering::Relaxed);
+    }
+
+    fn nanos_since_epoch(&self, instant: Instant) -> u64 {
+        instant
+            .saturating_duration_since(self.epoch)
+            .as_nanos()
+            .try_into()
+            .unwrap_or(u64::MAX - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(values: impl IntoIterator<Item = u64>) -> LatencyHistogram {
+        let mut histogram = LatencyHistogram {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            sum_nanos: 0,
+            min_nanos: u64::MAX,
+            max_nanos: 0,
+        };
+        for value in values {
+            histogram.counts[bucket_index(value)] += 1;
+            histogram.count += 1;
+            histogram.sum_nanos = histogram.sum_nanos.wrapping_add(value);
+            histogram.min_nanos = histogram.min_nanos.min(value);
+            histogram.max_nanos = histogram.max_nanos.max(value);
+        }
+        histogram
+    }
+
+    fn nanos(duration: Duration) -> u64 {
+        duration.as_nanos() as u64
+    }
+
+    #[test]
+    fn small_values_are_exact() {
+        for value in 0..SUB_BUCKETS {
+            assert_eq!(bucket_index(value), value as usize);
+            assert_eq!(bucket_upper_bound(value as usize), value);
+        }
+    }
+
+    #[test]
+    fn bucket_boundaries() {
+        assert_eq!(bucket_index(16), 16);
+        assert_eq!(bucket_index(31), 31);
+        // From 32 on, each bucket covers two or more values.
+        assert_eq!(bucket_index(32), 32);
+        assert_eq!(bucket_index(33), 32);
+        assert_eq!(bucket_index(34), 33);
+        assert_eq!(bucket_upper_bound(32), 33);
+
+        // Buckets are contiguous: each starts one after the previous one ends, and is
+        // at most 1/16 as wide as its lowest value.
+        let mut lower = 0;
+        for index in 0..BUCKETS {
+            let upper = bucket_upper_bound(index);
+            assert_eq!(bucket_index(lower), index);
+            assert_eq!(bucket_index(upper), index);
+            assert!((upper - lower) as f64 <= lower as f64 / SUB_BUCKETS as f64);
+            lower = upper.wrapping_add(1);
+        }
+        // The last bucket ends at the largest value.
+        assert_eq!(lower, 0);
+    }
+
+    #[test]
+    fn top_bucket() {
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+        assert_eq!(bucket_upper_bound(BUCKETS - 1), u64::MAX);
+        assert_eq!(bucket_index(1 << 63), BUCKETS - SUB_BUCKETS as usize);
+
+        // Latencies too long for a `u64` of nanoseconds are recorded as `u64::MAX`.
+        let histogram = histogram([5, u64::MAX]);
+        assert_eq!(nanos(histogram.value_at_quantile(1.0)), u64::MAX);
+        assert_eq!(nanos(histogram.max()), u64::MAX);
+    }
+
+    #[test]
+    fn quantiles_of_uniform_samples() {
+        let histogram = histogram(1..=1000);
+        assert_eq!(histogram.count(), 1000);
+        assert_eq!(nanos(histogram.value_at_quantile(0.0)), 1);
+        // The 500th value lies in the bucket 496..=511.
+        assert_eq!(nanos(histogram.value_at_quantile(0.5)), 511);
+        // The 999th value lies in the bucket 992..=1023, clamped to the maximum.
+        assert_eq!(nanos(histogram.value_at_quantile(0.999)), 1000);
+        assert_eq!(nanos(histogram.value_at_quantile(1.0)), 1000);
+        // Quantiles outside 0.0 to 1.0 are clamped.
+        assert_eq!(
+            histogram.value_at_quantile(-1.0),
+            histogram.value_at_quantile(0.0)
+        );
+        assert_eq!(
+            histogram.value_at_quantile(2.0),
+            histogram.value_at_quantile(1.0)
+        );
+        assert_eq!(nanos(histogram.sum()), 500_500);
+        assert_eq!(nanos(histogram.mean()), 500);
+    }
+
+    #[test]
+    fn quantiles_with_outlier() {
+        let histogram = histogram(std::iter::repeat_n(10, 999).chain([1_000_000]));
+        assert_eq!(nanos(histogram.value_at_quantile(0.0)), 10);
+        assert_eq!(nanos(histogram.value_at_quantile(0.5)), 10);
+        assert_eq!(nanos(histogram.value_at_quantile(0.999)), 10);
+        assert_eq!(nanos(histogram.value_at_quantile(1.0)), 1_000_000);
+        assert_eq!(
+            histogram.buckets().collect::<Vec<_>>(),
+            [
+                (Duration::from_nanos(10), 999),
+                (
+                    Duration::from_nanos(bucket_upper_bound(bucket_index(1_000_000))),
+                    1
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_histogram() {
+        let histogram = histogram([]);
+        for quantile in [0.0, 0.5, 0.999, 1.0] {
+            assert_eq!(histogram.value_at_quantile(quantile), Duration::ZERO);
+        }
+        assert_eq!(histogram.min(), Duration::ZERO);
+        assert_eq!(histogram.mean(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,453 @@
+//! Continuous streaming from an IN pipe.
+//!
+//! A [`Stream`] keeps several asynchronous reads queued on a pipe at all times, so the
+//! device never waits for the host between transfers. Reads are made on a background
+//! thread into a fixed set of [aligned buffers](crate::buffer) which are handed out as
+//! [`StreamBuffer`]s and returned to the pool when dropped, so no memory is allocated
+//! while streaming.
+//!
+//! ```no_run
+//! # fn main() -> ft60x_rs::Result<()> {
+//! # let device = ft60x_rs::list_devices()?[0].open()?;
+//! use ft60x_rs::{Pipe, StreamConfig};
+//!
+//! let stream = device.stream(Pipe::In0, StreamConfig::default())?;
+//! for buffer in stream.take(1000) {
+//!     let buffer = buffer?;
+//!     if buffer.follows_overrun() {
+//!         eprintln!("host fell behind before buffer {}", buffer.sequence());
+//!     }
+//!     // ... process &buffer[..] ...
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Overruns
+//!
+//! Each buffer is resubmitted to the device as soon as it is handed out, using a
+//! buffer which has been returned to the pool. If every buffer is still held by the
+//! consumer, the read cannot be resubmitted and the device is left with fewer reads
+//! queued until a buffer is dropped. This is counted as an overrun, reported by
+//! [`Stream::overruns`] and by [`StreamBuffer::follows_overrun`] on the next buffer.
+//! Depending on the FPGA design, data may have been lost in the meantime.
+
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    buffer::{BufferPool, PooledBuf},
+    ffi::{lib, types},
+    D3xxError, DeviceHandle, Pipe, Result,
+};
+
+/// Configuration for [`Device::stream`](crate::Device::stream).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamConfig {
+    /// Size of each buffer in bytes. Each read fills at most one buffer. Must be a
+    /// multiple of [`PACKET_SIZE`](crate::buffer::PACKET_SIZE).
+    pub buffer_size: usize,
+    /// Number of reads kept queued on the device. The pool holds twice this many
+    /// buffers, so the consumer may hold up to this many without causing an overrun.
+    pub buffers_in_flight: usize,
+    /// Stream size passed to [`Device::set_stream_size`](crate::Device::set_stream_size)
+    /// for the duration of the stream. `None` uses `buffer_size`.
+    pub stream_size: Option<u32>,
+}
+
+impl Default for StreamConfig {
+    /// 1 MiB buffers with 8 reads in flight.
+    fn default() -> Self {
+        Self {
+            buffer_size: 1024 * 1024,
+            buffers_in_flight: 8,
+            stream_size: None,
+        }
+    }
+}
+
+/// State shared between a [`Stream`] and the reader thread.
+pub(crate) struct Shared {
+    /// Buffers which are not queued on the device or held by the consumer.
+    pub(crate) pool: BufferPool,
+    stop: AtomicBool,
+    pub(crate) overruns: AtomicU64,
+}
+
+impl Shared {
+    /// Takes a free buffer, waiting for one to be returned if necessary. The flag is
+    /// set if the buffer had to be waited for.
+    ///
+    /// Returns `None` if the stream is stopped while waiting.
+    fn take(&self) -> Option<(PooledBuf, bool)> {
+        if let Some(buf) = self.pool.try_get() {
+            return Some((buf, false));
+        }
+        self.overruns.fetch_add(1, Ordering::Relaxed);
+        self.pool.get().map(|buf| (buf, true))
+    }
+}
+
+/// A buffer filled by a [`Stream`]. Dereferences to the data which was read.
+///
+/// The buffer is returned to the stream's pool when dropped.
+pub struct StreamBuffer {
+    buf: PooledBuf,
+    sequence: u64,
+    follows_overrun: bool,
+}
+
+impl StreamBuffer {
+    /// The position of this buffer in the stream, starting at zero.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Whether the stream overran since the previous buffer. See the
+    /// [module documentation](self#overruns).
+    pub fn follows_overrun(&self) -> bool {
+        self.follows_overrun
+    }
+}
+
+impl Deref for StreamBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl AsRef<[u8]> for StreamBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Debug for StreamBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamBuffer")
+            .field("sequence", &self.sequence)
+            .field("len", &self.buf.len())
+            .field("follows_overrun", &self.follows_overrun)
+            .finish()
+    }
+}
+
+/// A continuous stream of buffers read from an IN pipe, created by
+/// [`Device::stream`](crate::Device::stream).
+///
+/// Iterating yields buffers in the order they were read. If a read fails, the error
+/// is yielded and the stream ends; [`Stream::restart`] starts it again. Dropping the
+/// stream aborts the outstanding reads, waits for the reader thread to exit and
+/// disables the streaming protocol on the pipe.
+pub struct Stream {
+    device: Arc<DeviceHandle>,
+    pipe: Pipe,
+    config: StreamConfig,
+    shared: Arc<Shared>,
+    receiver: Receiver<Result<StreamBuffer>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Stream {
+    pub(crate) fn start(
+        device: Arc<DeviceHandle>,
+        pipe: Pipe,
+        config: StreamConfig,
+    ) -> Result<Self> {
+        if !pipe.is_read_pipe() || config.buffer_size == 0 || config.buffers_in_flight == 0 {
+            Err(device.pipe_error(pipe, D3xxError::InvalidParameter.into()))?;
+        }
+        let stream_size = match config.stream_size {
+            Some(size) => size,
+            None => config.buffer_size.try_into().unwrap_or(u32::MAX),
+        };
+        let pool = BufferPool::with_granularity(
+            config.buffer_size,
+            config.buffers_in_flight * 2,
+            device.granularity(),
+        )
+        .map_err(|e| device.pipe_error(pipe, e))?;
+        device.set_stream_size(pipe, Some(stream_size))?;
+
+        let shared = Arc::new(Shared {
+            pool,
+            stop: AtomicBool::new(false),
+            overruns: AtomicU64::new(0),
+        });
+        // Every buffer fits in the queue, so the reader never blocks on a slow consumer.
+        let (sender, receiver) = mpsc::sync_channel(config.buffers_in_flight * 2);
+
+        let reader = Reader {
+            device: device.clone(),
+            pipe,
+            shared: shared.clone(),
+            sender,
+        };
+        let thread = std::thread::Builder::new()
+            .name(format!("ft60x-stream-{:?}", pipe))
+            .spawn(move || reader.run(config.buffers_in_flight))
+            .map_err(|e| device.pipe_error(pipe, e.into()))?;
+
+        Ok(Self {
+            device,
+            pipe,
+            config,
+            shared,
+            receiver,
+            thread: Some(thread),
+        })
+    }
+
+    /// The pipe being streamed from.
+    pub fn pipe(&self) -> Pipe {
+        self.pipe
+    }
+
+    /// The number of times the stream has overrun. See the
+    /// [module documentation](self#overruns).
+    pub fn overruns(&self) -> u64 {
+        self.shared.overruns.load(Ordering::Relaxed)
+    }
+
+    /// Stops the stream and starts it again on the same pipe with the same
+    /// configuration, e.g. after a read has timed out because the device had no data.
+    ///
+    /// Buffers which have not been received yet are discarded. The overrun count
+    /// carries over to the new stream, but a stream registered with a
+    /// [`MetricsRegistry`](crate::metrics::MetricsRegistry) must be registered again.
+    ///
+    /// # Errors
+    /// As for [`Device::stream`](crate::Device::stream). The stream stays ended.
+    pub fn restart(&mut self) -> Result<()> {
+        let overruns = self.overruns();
+        self.stop();
+        let stream = Self::start(self.device.clone(), self.pipe, self.config)?;
+        stream.shared.overruns.store(overruns, Ordering::Relaxed);
+        *self = stream;
+        Ok(())
+    }
+
+    /// Aborts the outstanding reads, waits for the reader thread and disables the
+    /// streaming protocol. Does nothing if the stream has already been stopped.
+    fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.shared.stop.store(true, Ordering::Relaxed);
+        self.shared.pool.close();
+        let _ = self.device.abort_transfers(self.pipe);
+        let _ = thread.join();
+        let _ = self.device.set_stream_size(self.pipe, None);
+    }
+
+    pub(crate) fn device(&self) -> &Arc<DeviceHandle> {
+        &self.device
+    }
+
+    pub(crate) fn shared(&self) -> &Arc<Shared> {
+        &self.shared
+    }
+
+    /// Waits up to `timeout` for the next buffer.
+    ///
+    /// Returns `None` if no buffer arrived in time or the stream has ended.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Result<StreamBuffer>> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+impl Iterator for Stream {
+    type Item = Result<StreamBuffer>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Debug for Stream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stream")
+            .field("handle", &self.device.handle)
+            .field("pipe", &self.pipe)
+            .field("overruns", &self.overruns())
+            .finish()
+    }
+}
+
+/// A queued read. Slots are never moved while a read is pending.
+struct Slot {
+    buf: Option<PooledBuf>,
+    overlapped: types::OVERLAPPED,
+    bytes_transferred: types::ULONG,
+    /// Whether `overlapped` was initialized, and must be released.
+    initialized: bool,
+    pending: bool,
+    /// When the pending read was queued, for statistics.
+    submitted: Instant,
+}
+
+/// The reader thread.
+struct Reader {
+    device: Arc<DeviceHandle>,
+    pipe: Pipe,
+    shared: Arc<Shared>,
+    sender: SyncSender<Result<StreamBuffer>>,
+}
+
+impl Reader {
+    fn run(self, in_flight: usize) {
+        let mut slots: Vec<Slot> = (0..in_flight)
+            .map(|_| Slot {
+                buf: None,
+                overlapped: types::OVERLAPPED::default(),
+                bytes_transferred: 0,
+                initialized: false,
+                pending: false,
+                submitted: Instant::now(),
+            })
+            .collect();
+
+        if let Err(e) = self.stream(&mut slots) {
+            if !self.shared.stop.load(Ordering::Relaxed) {
+                let _ = self.sender.send(Err(e));
+            }
+        }
+
+        // Release any reads which are still queued before their buffers are freed.
+        let _ = self.device.abort_transfers(self.pipe);
+        for slot in slots.iter_mut().filter(|slot| slot.initialized) {
+            unsafe {
+                if slot.pending {
+                    let mut n = 0;
+                    let _ = lib::FT_GetOverlappedResult(
+                        self.device.raw(),
+                        overlapped_ptr(&mut slot.overlapped),
+                        &mut n,
+                        1,
+                    );
+                }
+                let _ = lib::FT_ReleaseOverlapped(
+                    self.device.raw(),
+                    overlapped_ptr(&mut slot.overlapped),
+                );
+            }
+        }
+    }
+
+    fn stream(&self, slots: &mut [Slot]) -> Result<()> {
+        for slot in slots.iter_mut() {
+            unsafe {
+                lib::FT_InitializeOverlapped(
+                    self.device.raw(),
+                    overlapped_ptr(&mut slot.overlapped),
+                )
+                .map_err(|e| self.device.pipe_error(self.pipe, e))?;
+            }
+            slot.initialized = true;
+            let Some((buf, _)) = self.shared.take() else {
+                return Ok(());
+            };
+            slot.buf = Some(buf);
+            self.submit(slot)?;
+        }
+
+        let mut overrun = false;
+        for (sequence, i) in (0..slots.len()).cycle().enumerate() {
+            let slot = &mut slots[i];
+            let mut len = 0;
+            let result = unsafe {
+                lib::FT_GetOverlappedResult(
+                    self.device.raw(),
+                    overlapped_ptr(&mut slot.overlapped),
+                    &mut len,
+                    1,
+                )
+            };
+            slot.pending = false;
+            if self.shared.stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let result = result.map_err(|e| self.device.pipe_error(self.pipe, e));
+            if let Some(stats) = self.device.stats() {
+                let capacity = slot.buf.as_ref().map_or(0, |buf| buf.capacity());
+                let outcome = result.as_ref().map(|()| len as usize);
+                stats.record(self.pipe, slot.submitted, capacity, outcome);
+            }
+            result?;
+
+            let Some((next, stalled)) = self.shared.take() else {
+                return Ok(());
+            };
+            let mut filled = slot.buf.replace(next).unwrap();
+            filled.set_filled(len as usize);
+            overrun |= stalled;
+            self.submit(slot)?;
+
+            let buffer = StreamBuffer {
+                buf: filled,
+                sequence: sequence as u64,
+                follows_overrun: std::mem::take(&mut overrun),
+            };
+            if self.sender.send(Ok(buffer)).is_err() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues a read into the slot's buffer.
+    fn submit(&self, slot: &mut Slot) -> Result<()> {
+        let buf = slot.buf.as_mut().unwrap();
+        let len = buf.capacity().min(types::ULONG::MAX as usize) as types::ULONG;
+        let result = unsafe {
+            #[cfg(windows)]
+            let result = lib::FT_ReadPipeEx(
+                self.device.raw(),
+                self.pipe as u8,
+                buf.as_mut_ptr(),
+                len,
+                &mut slot.bytes_transferred,
+                overlapped_ptr(&mut slot.overlapped),
+            );
+            #[cfg(not(windows))]
+            let result = lib::FT_ReadPipeAsync(
+                self.device.raw(),
+                self.pipe.channel(),
+                buf.as_mut_ptr(),
+                len,
+                &mut slot.bytes_transferred,
+                overlapped_ptr(&mut slot.overlapped),
+            );
+            result
+        };
+        match result {
+            Ok(()) => {}
+            Err(e) if matches!(e.kind(), D3xxError::IoPending) => {}
+            Err(e) => return Err(self.device.pipe_error(self.pipe, e)),
+        }
+        slot.pending = true;
+        slot.submitted = Instant::now();
+        Ok(())
+    }
+}
+
+fn overlapped_ptr(overlapped: &mut types::OVERLAPPED) -> *mut libc::c_void {
+    overlapped as *mut types::OVERLAPPED as *mut libc::c_void
+}